@@ -0,0 +1,519 @@
+//! Export of the articles and the stats aggregations as tables for the data analysis tools.
+//!
+//! Arrow files are written in IPC file format (aka Feather v2), they can be read via
+//! `pandas.read_feather()` or `polars.read_ipc()`. Parquet files are written by our own minimal
+//! writer since the `parquet` crate compatible with our arrow version requires nightly toolchain.
+//!
+//! Timestamps are written as RFC 3339 strings in UTC to ndjson and csv files,
+//! as millisecond timestamps without a timezone (implied UTC) to arrow files
+//! and as millisecond timestamps adjusted to UTC to parquet files.
+
+mod parquet;
+
+use anyhow::{bail, Context, Result};
+use arrow::{
+    array::{
+        ArrayRef, Float32Array, Float64Array, StringArray, TimestampMillisecondArray, UInt64Array,
+    },
+    datatypes::{DataType, Field, Schema, TimeUnit},
+    ipc::writer::FileWriter,
+    record_batch::RecordBatch,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Map, Value};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExportFormat {
+    Ndjson,
+    Csv,
+    Arrow,
+    Parquet,
+}
+
+impl FromStr for ExportFormat {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" => Ok(Self::Ndjson),
+            "csv" => Ok(Self::Csv),
+            "arrow" => Ok(Self::Arrow),
+            "parquet" => Ok(Self::Parquet),
+            _ => Err("export format must be one of: ndjson, csv, arrow, parquet"),
+        }
+    }
+}
+
+impl ExportFormat {
+    /// Guesses the format by the file extension, e.g. `articles.ndjson.gz` is `ndjson`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let name = name.trim_end_matches(".gz");
+        let ext = &name[name.rfind('.')? + 1..];
+        match ext {
+            "ndjson" | "jsonl" => Some(Self::Ndjson),
+            "csv" => Some(Self::Csv),
+            "arrow" | "feather" | "ipc" => Some(Self::Arrow),
+            "parquet" | "pq" => Some(Self::Parquet),
+            _ => None,
+        }
+    }
+
+    /// Returns the explicitly specified format or guesses it by the file extension
+    pub fn resolve(format: Option<Self>, path: &Path) -> Result<Self> {
+        match format {
+            Some(it) => Ok(it),
+            None => Self::from_path(path).with_context(|| {
+                format!(
+                    "Can't guess the export format of {}, specify it via `--format`",
+                    path.display()
+                )
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColumnType {
+    Utf8,
+    Float32,
+    Float64,
+    UInt64,
+    Timestamp,
+}
+
+impl ColumnType {
+    fn to_arrow(self) -> DataType {
+        match self {
+            Self::Utf8 => DataType::Utf8,
+            Self::Float32 => DataType::Float32,
+            Self::Float64 => DataType::Float64,
+            Self::UInt64 => DataType::UInt64,
+            Self::Timestamp => DataType::Timestamp(TimeUnit::Millisecond, None),
+        }
+    }
+}
+
+/// Name, type and nullability of the table columns
+pub type TableSchema = [(&'static str, ColumnType, bool)];
+
+#[derive(Debug)]
+pub enum Column {
+    Utf8(Vec<Option<String>>),
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+    UInt64(Vec<u64>),
+    Timestamp(Vec<Option<DateTime<Utc>>>),
+}
+
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+impl Column {
+    fn ty(&self) -> ColumnType {
+        match self {
+            Self::Utf8(_) => ColumnType::Utf8,
+            Self::Float32(_) => ColumnType::Float32,
+            Self::Float64(_) => ColumnType::Float64,
+            Self::UInt64(_) => ColumnType::UInt64,
+            Self::Timestamp(_) => ColumnType::Timestamp,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Utf8(it) => it.len(),
+            Self::Float32(it) => it.len(),
+            Self::Float64(it) => it.len(),
+            Self::UInt64(it) => it.len(),
+            Self::Timestamp(it) => it.len(),
+        }
+    }
+
+    fn cell(&self, row: usize) -> Value {
+        match self {
+            Self::Utf8(it) => json!(it[row]),
+            Self::Float32(it) => json!(it[row]),
+            Self::Float64(it) => json!(it[row]),
+            Self::UInt64(it) => json!(it[row]),
+            Self::Timestamp(it) => json!(it[row].as_ref().map(format_timestamp)),
+        }
+    }
+
+    /// Nulls are written as empty strings
+    fn cell_to_string(&self, row: usize) -> String {
+        match self {
+            Self::Utf8(it) => it[row].clone().unwrap_or_default(),
+            Self::Float32(it) => it[row].to_string(),
+            Self::Float64(it) => it[row].to_string(),
+            Self::UInt64(it) => it[row].to_string(),
+            Self::Timestamp(it) => it[row].as_ref().map(format_timestamp).unwrap_or_default(),
+        }
+    }
+
+    fn to_arrow(&self) -> ArrayRef {
+        match self {
+            Self::Utf8(it) => Arc::new(StringArray::from(
+                it.iter().map(Option::as_deref).collect::<Vec<_>>(),
+            )),
+            Self::Float32(it) => Arc::new(Float32Array::from(it.clone())),
+            Self::Float64(it) => Arc::new(Float64Array::from(it.clone())),
+            Self::UInt64(it) => Arc::new(UInt64Array::from(it.clone())),
+            Self::Timestamp(it) => Arc::new(TimestampMillisecondArray::from(
+                it.iter()
+                    .map(|it| it.map(|it| it.timestamp_millis()))
+                    .collect::<Vec<_>>(),
+            )),
+        }
+    }
+}
+
+/// Batch of rows stored by columns in the order of the schema
+pub struct Table {
+    pub columns: Vec<Column>,
+}
+
+impl Table {
+    /// Creates the table from the columns with their names.
+    /// Panics if the columns don't match the schema, since it is a bug in the caller.
+    fn new(schema: &TableSchema, columns: Vec<(&str, Column)>) -> Self {
+        let actual: Vec<_> = columns
+            .iter()
+            .map(|(name, column)| (*name, column.ty()))
+            .collect();
+        let expected: Vec<_> = schema.iter().map(|(name, ty, _)| (*name, *ty)).collect();
+        assert_eq!(actual, expected, "Table columns don't match the schema");
+        Self {
+            columns: columns.into_iter().map(|(_, column)| column).collect(),
+        }
+    }
+
+    fn n_rows(&self) -> usize {
+        self.columns.first().map_or(0, Column::len)
+    }
+}
+
+pub const ARTICLES_SCHEMA: &TableSchema = &[
+    ("id", ColumnType::Utf8, false),
+    ("category", ColumnType::Utf8, false),
+    ("headline", ColumnType::Utf8, false),
+    ("authors", ColumnType::Utf8, false),
+    ("link", ColumnType::Utf8, false),
+    ("short_description", ColumnType::Utf8, false),
+    ("date", ColumnType::Timestamp, false),
+    ("ingested_at", ColumnType::Timestamp, true),
+    ("sentiment_score", ColumnType::Float32, false),
+    ("sentiment_polarity", ColumnType::Utf8, false),
+    ("duplicate_of", ColumnType::Utf8, true),
+];
+
+pub fn articles_table(articles: Vec<vna_es::WithId<vna_es::Article>>) -> Table {
+    let n = articles.len();
+    let mut ids = Vec::with_capacity(n);
+    let mut categories = Vec::with_capacity(n);
+    let mut headlines = Vec::with_capacity(n);
+    let mut authors = Vec::with_capacity(n);
+    let mut links = Vec::with_capacity(n);
+    let mut short_descriptions = Vec::with_capacity(n);
+    let mut dates = Vec::with_capacity(n);
+    let mut ingested_at = Vec::with_capacity(n);
+    let mut sentiment_scores = Vec::with_capacity(n);
+    let mut sentiment_polarities = Vec::with_capacity(n);
+    let mut duplicate_of = Vec::with_capacity(n);
+
+    for vna_es::WithId { id, doc } in articles {
+        ids.push(Some(id));
+        categories.push(Some(doc.category));
+        headlines.push(Some(doc.headline));
+        authors.push(Some(doc.authors));
+        links.push(Some(doc.link));
+        short_descriptions.push(Some(doc.short_description));
+        dates.push(Some(doc.date));
+        ingested_at.push(doc.ingested_at);
+        sentiment_scores.push(doc.sentiment_score);
+        sentiment_polarities.push(Some(format!("{:?}", doc.sentiment_polarity)));
+        duplicate_of.push(doc.duplicate_of);
+    }
+
+    Table::new(
+        ARTICLES_SCHEMA,
+        vec![
+            ("id", Column::Utf8(ids)),
+            ("category", Column::Utf8(categories)),
+            ("headline", Column::Utf8(headlines)),
+            ("authors", Column::Utf8(authors)),
+            ("link", Column::Utf8(links)),
+            ("short_description", Column::Utf8(short_descriptions)),
+            ("date", Column::Timestamp(dates)),
+            ("ingested_at", Column::Timestamp(ingested_at)),
+            ("sentiment_score", Column::Float32(sentiment_scores)),
+            ("sentiment_polarity", Column::Utf8(sentiment_polarities)),
+            ("duplicate_of", Column::Utf8(duplicate_of)),
+        ],
+    )
+}
+
+pub const STATS_SCHEMA: &TableSchema = &[
+    ("key", ColumnType::Utf8, false),
+    ("doc_count", ColumnType::UInt64, false),
+];
+
+pub fn stats_table(stats: &vna_es::Stats) -> Table {
+    let (keys, counts): (Vec<_>, Vec<_>) = stats
+        .0
+        .iter()
+        .map(|(key, n)| (Some(key.clone()), *n))
+        .unzip();
+    Table::new(
+        STATS_SCHEMA,
+        vec![
+            ("key", Column::Utf8(keys)),
+            ("doc_count", Column::UInt64(counts)),
+        ],
+    )
+}
+
+pub const SIGNIFICANT_WORDS_SCHEMA: &TableSchema = &[
+    ("key", ColumnType::Utf8, false),
+    ("doc_count", ColumnType::UInt64, false),
+    ("score", ColumnType::Float64, false),
+    ("bg_count", ColumnType::UInt64, false),
+];
+
+pub fn significant_words_table(
+    buckets: &[vna_es_utils::es_types::SignificantTextAggrBucket],
+) -> Table {
+    Table::new(
+        SIGNIFICANT_WORDS_SCHEMA,
+        vec![
+            (
+                "key",
+                Column::Utf8(buckets.iter().map(|it| Some(it.key.clone())).collect()),
+            ),
+            (
+                "doc_count",
+                Column::UInt64(buckets.iter().map(|it| it.doc_count).collect()),
+            ),
+            (
+                "score",
+                Column::Float64(buckets.iter().map(|it| it.score).collect()),
+            ),
+            (
+                "bg_count",
+                Column::UInt64(buckets.iter().map(|it| it.bg_count).collect()),
+            ),
+        ],
+    )
+}
+
+/// Writes the tables with the same schema into the file batch by batch
+pub enum TableWriter {
+    Ndjson {
+        out: BufWriter<File>,
+        schema: &'static TableSchema,
+    },
+    Csv(csv::Writer<File>),
+    Arrow {
+        writer: Box<FileWriter<File>>,
+        schema: Arc<Schema>,
+    },
+    Parquet(parquet::ParquetWriter),
+}
+
+impl TableWriter {
+    pub fn create(path: &Path, format: ExportFormat, schema: &'static TableSchema) -> Result<Self> {
+        if path.extension().map_or(false, |it| it == "gz") {
+            bail!(
+                "Only articles exported in ndjson format can be gzipped, got {}",
+                path.display()
+            );
+        }
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+
+        Ok(match format {
+            ExportFormat::Ndjson => Self::Ndjson {
+                out: BufWriter::new(file),
+                schema,
+            },
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(file);
+                writer.write_record(schema.iter().map(|(name, ..)| name))?;
+                Self::Csv(writer)
+            }
+            ExportFormat::Arrow => {
+                let schema = Arc::new(arrow_schema(schema));
+                Self::Arrow {
+                    writer: Box::new(FileWriter::try_new(file, &schema)?),
+                    schema,
+                }
+            }
+            ExportFormat::Parquet => Self::Parquet(parquet::ParquetWriter::new(file, schema)?),
+        })
+    }
+
+    pub fn write(&mut self, table: &Table) -> Result<()> {
+        if table.n_rows() == 0 {
+            return Ok(());
+        }
+        match self {
+            Self::Ndjson { out, schema } => {
+                for row in 0..table.n_rows() {
+                    let object: Map<_, _> = schema
+                        .iter()
+                        .zip(&table.columns)
+                        .map(|((name, ..), column)| ((*name).to_owned(), column.cell(row)))
+                        .collect();
+                    serde_json::to_writer(&mut *out, &object)?;
+                    out.write_all(b"\n")?;
+                }
+            }
+            Self::Csv(writer) => {
+                for row in 0..table.n_rows() {
+                    writer.write_record(table.columns.iter().map(|it| it.cell_to_string(row)))?;
+                }
+            }
+            Self::Arrow { writer, schema } => {
+                let batch = RecordBatch::try_new(
+                    schema.clone(),
+                    table.columns.iter().map(Column::to_arrow).collect(),
+                )?;
+                writer.write(&batch)?;
+            }
+            Self::Parquet(writer) => writer.write(table)?,
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Self::Ndjson { mut out, .. } => out.flush()?,
+            Self::Csv(mut writer) => writer.flush()?,
+            Self::Arrow { mut writer, .. } => writer.finish()?,
+            Self::Parquet(writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+fn arrow_schema(schema: &TableSchema) -> Schema {
+    Schema::new(
+        schema
+            .iter()
+            .map(|(name, ty, nullable)| Field::new(name, ty.to_arrow(), *nullable))
+            .collect(),
+    )
+}
+
+/// Streams all the articles that match the filters and the query into the file batch by batch.
+/// Returns the number of exported articles.
+pub async fn export_articles(opts: vna_es::ExportOpts<'_>, format: ExportFormat) -> Result<u64> {
+    let mut writer = TableWriter::create(opts.path, format, ARTICLES_SCHEMA)?;
+
+    let mut scroll = vna_es::ArticlesScroll::new(opts.elastic, opts.filters, opts.batch_size.get());
+    if let Some(query) = opts.query {
+        scroll = scroll.with_query(query);
+    }
+
+    let mut exported = 0;
+    loop {
+        let batch = scroll.next_batch().await?;
+        if batch.is_empty() {
+            break;
+        }
+        exported += batch.len() as u64;
+        writer.write(&articles_table(batch))?;
+        (opts.on_progress)(exported);
+    }
+    scroll.clear().await?;
+    writer.finish()?;
+
+    Ok(exported)
+}
+
+/// Writes the whole table into the file at once
+pub fn write_table(
+    path: &Path,
+    format: ExportFormat,
+    schema: &'static TableSchema,
+    table: &Table,
+) -> Result<()> {
+    let mut writer = TableWriter::create(path, format, schema)?;
+    writer.write(table)?;
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses_format_by_extension() {
+        let format = |path: &str| ExportFormat::from_path(Path::new(path));
+        assert_eq!(format("articles.ndjson.gz"), Some(ExportFormat::Ndjson));
+        assert_eq!(format("out/articles.csv"), Some(ExportFormat::Csv));
+        assert_eq!(format("articles.feather"), Some(ExportFormat::Arrow));
+        assert_eq!(format("articles"), None);
+        assert_eq!(format("articles.parquet"), Some(ExportFormat::Parquet));
+    }
+
+    #[test]
+    #[should_panic(expected = "Table columns don't match the schema")]
+    fn rejects_columns_that_dont_match_schema() {
+        Table::new(
+            STATS_SCHEMA,
+            vec![
+                ("doc_count", Column::UInt64(vec![1])),
+                ("key", Column::Utf8(vec![Some("key".to_owned())])),
+            ],
+        );
+    }
+
+    #[test]
+    fn converts_articles_to_columns() {
+        let article: vna_es::WithId<vna_es::Article> = serde_json::from_value(json!({
+            "id": "abc",
+            "doc": {
+                "category": "CRIME",
+                "headline": "Headline",
+                "authors": "Ron Dicker",
+                "link": "https://example.com",
+                "short_description": "Description",
+                "date": "2018-05-26",
+                "ingested_at": "2020-06-15T10:30:15.500Z",
+                "sentiment_score": 0.5,
+                "sentiment_polarity": "Positive",
+            }
+        }))
+        .unwrap();
+
+        let table = articles_table(vec![article]);
+        assert_eq!(table.columns.len(), ARTICLES_SCHEMA.len());
+        assert_eq!(table.n_rows(), 1);
+
+        let row: Vec<_> = table.columns.iter().map(|it| it.cell(0)).collect();
+        assert_eq!(
+            row,
+            [
+                json!("abc"),
+                json!("CRIME"),
+                json!("Headline"),
+                json!("Ron Dicker"),
+                json!("https://example.com"),
+                json!("Description"),
+                json!("2018-05-26T00:00:00Z"),
+                json!("2020-06-15T10:30:15.500Z"),
+                json!(0.5),
+                json!("Positive"),
+                json!(null),
+            ]
+        );
+    }
+}
@@ -0,0 +1,459 @@
+//! Minimal Parquet writer for the exported tables.
+//!
+//! Each written table becomes a row group with a single uncompressed data page (v1) per column.
+//! Values are PLAIN encoded, definition levels of the nullable columns are RLE encoded.
+//! The metadata is serialized via Thrift compact protocol, see
+//! <https://github.com/apache/parquet-format/blob/master/src/main/thrift/parquet.thrift>.
+
+use super::{Column, ColumnType, Table, TableSchema};
+use anyhow::{bail, Context, Result};
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+const MAGIC: &[u8] = b"PAR1";
+
+// Physical types
+const INT64: i32 = 2;
+const FLOAT: i32 = 4;
+const DOUBLE: i32 = 5;
+const BYTE_ARRAY: i32 = 6;
+
+// Converted types, written along with the logical types for the older readers
+const UTF8: i32 = 0;
+const TIMESTAMP_MILLIS: i32 = 9;
+const UINT_64: i32 = 14;
+
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+const UNCOMPRESSED: i32 = 0;
+const DATA_PAGE: i32 = 0;
+
+pub struct ParquetWriter {
+    out: BufWriter<File>,
+    schema: &'static TableSchema,
+    /// Position in the file where the next page will be written
+    offset: u64,
+    row_groups: Vec<Thrift>,
+    n_rows: u64,
+}
+
+impl ParquetWriter {
+    pub fn new(file: File, schema: &'static TableSchema) -> Result<Self> {
+        let mut out = BufWriter::new(file);
+        out.write_all(MAGIC)?;
+        Ok(Self {
+            out,
+            schema,
+            offset: MAGIC.len() as u64,
+            row_groups: Vec::new(),
+            n_rows: 0,
+        })
+    }
+
+    /// Writes the table as a row group
+    pub fn write(&mut self, table: &Table) -> Result<()> {
+        let n_rows = table.n_rows();
+        let mut chunks = Vec::with_capacity(table.columns.len());
+        let mut total_size = 0;
+
+        for ((name, ty, nullable), column) in self.schema.iter().zip(&table.columns) {
+            let page = encode_page(column, *nullable)
+                .with_context(|| format!("Failed to encode column {}", name))?;
+            let header = page_header(n_rows, page.len())?;
+            let size = (header.len() + page.len()) as u64;
+
+            let page_offset = self.offset;
+            self.out.write_all(&header)?;
+            self.out.write_all(&page)?;
+            self.offset += size;
+            total_size += size;
+
+            let meta = Thrift::Struct(vec![
+                (1, Thrift::I32(physical_type(*ty))),
+                (2, Thrift::List(vec![Thrift::I32(PLAIN), Thrift::I32(RLE)])),
+                (3, Thrift::List(vec![Thrift::string(name)])),
+                (4, Thrift::I32(UNCOMPRESSED)),
+                (5, Thrift::I64(n_rows as i64)),
+                (6, Thrift::I64(size as i64)),
+                (7, Thrift::I64(size as i64)),
+                (9, Thrift::I64(page_offset as i64)),
+            ]);
+            chunks.push(Thrift::Struct(vec![
+                (2, Thrift::I64(page_offset as i64)),
+                (3, meta),
+            ]));
+        }
+
+        self.row_groups.push(Thrift::Struct(vec![
+            (1, Thrift::List(chunks)),
+            (2, Thrift::I64(total_size as i64)),
+            (3, Thrift::I64(n_rows as i64)),
+        ]));
+        self.n_rows += n_rows as u64;
+        Ok(())
+    }
+
+    /// Writes the footer with the file metadata
+    pub fn finish(mut self) -> Result<()> {
+        let mut schema = vec![Thrift::Struct(vec![
+            (4, Thrift::string("schema")),
+            (5, Thrift::I32(self.schema.len() as i32)),
+        ])];
+        schema.extend(
+            self.schema
+                .iter()
+                .map(|(name, ty, nullable)| schema_element(name, *ty, *nullable)),
+        );
+
+        let meta = Thrift::Struct(vec![
+            (1, Thrift::I32(1)),
+            (2, Thrift::List(schema)),
+            (3, Thrift::I64(self.n_rows as i64)),
+            (4, Thrift::List(self.row_groups)),
+            (
+                6,
+                Thrift::string(concat!("vna version ", env!("CARGO_PKG_VERSION"))),
+            ),
+        ]);
+        let mut footer = Vec::new();
+        meta.encode(&mut footer);
+
+        self.out.write_all(&footer)?;
+        self.out.write_all(&(footer.len() as u32).to_le_bytes())?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn physical_type(ty: ColumnType) -> i32 {
+    match ty {
+        ColumnType::Utf8 => BYTE_ARRAY,
+        ColumnType::Float32 => FLOAT,
+        ColumnType::Float64 => DOUBLE,
+        ColumnType::UInt64 | ColumnType::Timestamp => INT64,
+    }
+}
+
+fn schema_element(name: &str, ty: ColumnType, nullable: bool) -> Thrift {
+    let repetition = if nullable { OPTIONAL } else { REQUIRED };
+    let mut fields = vec![
+        (1, Thrift::I32(physical_type(ty))),
+        (3, Thrift::I32(repetition)),
+        (4, Thrift::string(name)),
+    ];
+    let logical = match ty {
+        ColumnType::Utf8 => Some((UTF8, Thrift::Struct(vec![(1, Thrift::Struct(vec![]))]))),
+        ColumnType::UInt64 => Some((
+            UINT_64,
+            Thrift::Struct(vec![(
+                10,
+                Thrift::Struct(vec![(1, Thrift::I8(64)), (2, Thrift::Bool(false))]),
+            )]),
+        )),
+        ColumnType::Timestamp => Some((
+            TIMESTAMP_MILLIS,
+            Thrift::Struct(vec![(
+                8,
+                Thrift::Struct(vec![
+                    (1, Thrift::Bool(true)),
+                    (2, Thrift::Struct(vec![(1, Thrift::Struct(vec![]))])),
+                ]),
+            )]),
+        )),
+        ColumnType::Float32 | ColumnType::Float64 => None,
+    };
+    if let Some((converted, logical)) = logical {
+        fields.push((6, Thrift::I32(converted)));
+        fields.push((10, logical));
+    }
+    Thrift::Struct(fields)
+}
+
+fn page_header(n_values: usize, page_size: usize) -> Result<Vec<u8>> {
+    let page_size = i32::try_from(page_size).context("Parquet page is too large")?;
+    let header = Thrift::Struct(vec![
+        (1, Thrift::I32(DATA_PAGE)),
+        (2, Thrift::I32(page_size)),
+        (3, Thrift::I32(page_size)),
+        (
+            5,
+            Thrift::Struct(vec![
+                (1, Thrift::I32(n_values as i32)),
+                (2, Thrift::I32(PLAIN)),
+                (3, Thrift::I32(RLE)),
+                (4, Thrift::I32(RLE)),
+            ]),
+        ),
+    ]);
+    let mut buf = Vec::new();
+    header.encode(&mut buf);
+    Ok(buf)
+}
+
+/// Encodes the definition levels (if the column is nullable) followed by the non-null values
+fn encode_page(column: &Column, nullable: bool) -> Result<Vec<u8>> {
+    let mut values = Vec::new();
+    let mut present = Vec::with_capacity(column.len());
+    match column {
+        Column::Utf8(it) => {
+            for value in it {
+                if let Some(value) = value {
+                    values.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                    values.extend_from_slice(value.as_bytes());
+                }
+                present.push(value.is_some());
+            }
+        }
+        Column::Float32(it) => {
+            for value in it {
+                values.extend_from_slice(&value.to_le_bytes());
+                present.push(true);
+            }
+        }
+        Column::Float64(it) => {
+            for value in it {
+                values.extend_from_slice(&value.to_le_bytes());
+                present.push(true);
+            }
+        }
+        Column::UInt64(it) => {
+            for value in it {
+                values.extend_from_slice(&value.to_le_bytes());
+                present.push(true);
+            }
+        }
+        Column::Timestamp(it) => {
+            for value in it {
+                if let Some(value) = value {
+                    values.extend_from_slice(&value.timestamp_millis().to_le_bytes());
+                }
+                present.push(value.is_some());
+            }
+        }
+    }
+
+    if !nullable {
+        if present.contains(&false) {
+            bail!("Got null in the required column");
+        }
+        return Ok(values);
+    }
+    let levels = encode_levels(&present);
+    let mut page = Vec::with_capacity(4 + levels.len() + values.len());
+    page.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+    page.extend_from_slice(&levels);
+    page.extend_from_slice(&values);
+    Ok(page)
+}
+
+/// Encodes the 1-bit levels as RLE runs of the RLE/bit-packing hybrid encoding
+fn encode_levels(present: &[bool]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut rest = present;
+    while let Some(&value) = rest.first() {
+        let run = rest.iter().take_while(|&&it| it == value).count();
+        write_varint(&mut buf, (run as u64) << 1);
+        buf.push(value as u8);
+        rest = &rest[run..];
+    }
+    buf
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+/// Value serialized via Thrift compact protocol
+#[derive(Debug)]
+enum Thrift {
+    Bool(bool),
+    I8(i8),
+    I32(i32),
+    I64(i64),
+    Binary(Vec<u8>),
+    /// Elements must have the same type
+    List(Vec<Thrift>),
+    /// Fields with their ids in ascending order
+    Struct(Vec<(i16, Thrift)>),
+}
+
+impl Thrift {
+    fn string(s: &str) -> Self {
+        Self::Binary(s.as_bytes().to_vec())
+    }
+
+    fn type_id(&self) -> u8 {
+        match self {
+            Self::Bool(true) => 1,
+            Self::Bool(false) => 2,
+            Self::I8(_) => 3,
+            Self::I32(_) => 5,
+            Self::I64(_) => 6,
+            Self::Binary(_) => 8,
+            Self::List(_) => 9,
+            Self::Struct(_) => 12,
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            // Booleans are encoded in the field header
+            Self::Bool(_) => {}
+            Self::I8(n) => buf.push(*n as u8),
+            Self::I32(n) => write_varint(buf, zigzag(i64::from(*n))),
+            Self::I64(n) => write_varint(buf, zigzag(*n)),
+            Self::Binary(bytes) => {
+                write_varint(buf, bytes.len() as u64);
+                buf.extend_from_slice(bytes);
+            }
+            Self::List(items) => {
+                // Our lists never contain booleans, which are encoded as 1 byte in lists
+                let item_type = items.first().map_or(12, Thrift::type_id);
+                if items.len() < 15 {
+                    buf.push((items.len() as u8) << 4 | item_type);
+                } else {
+                    buf.push(0xf0 | item_type);
+                    write_varint(buf, items.len() as u64);
+                }
+                for item in items {
+                    item.encode(buf);
+                }
+            }
+            Self::Struct(fields) => {
+                let mut last_id = 0;
+                for (id, value) in fields {
+                    let delta = id - last_id;
+                    if (1..=15).contains(&delta) {
+                        buf.push((delta as u8) << 4 | value.type_id());
+                    } else {
+                        buf.push(value.type_id());
+                        write_varint(buf, zigzag(i64::from(*id)));
+                    }
+                    value.encode(buf);
+                    last_id = *id;
+                }
+                buf.push(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn encodes_thrift_compact_protocol() {
+        let value = Thrift::Struct(vec![
+            (1, Thrift::I32(-1)),
+            (
+                2,
+                Thrift::List(vec![Thrift::string("a"), Thrift::string("bc")]),
+            ),
+            (4, Thrift::Bool(true)),
+            (20, Thrift::Struct(vec![(1, Thrift::I64(300))])),
+        ]);
+        let mut buf = Vec::new();
+        value.encode(&mut buf);
+        assert_eq!(
+            buf,
+            [
+                0x15, 0x01, // field 1: i32 -1
+                0x19, 0x28, 0x01, b'a', 0x02, b'b', b'c', // field 2: list of 2 strings
+                0x21, // field 4: true
+                0x0c, 0x28, // field 20 in the long form: struct
+                0x16, 0xd8, 0x04, 0x00, // field 1: i64 300, end of the nested struct
+                0x00, // end of the struct
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_definition_levels_as_runs() {
+        let levels = encode_levels(&[true, true, true, false, true]);
+        assert_eq!(levels, [0x06, 0x01, 0x02, 0x00, 0x02, 0x01]);
+        assert!(encode_levels(&[]).is_empty());
+    }
+
+    #[test]
+    fn rejects_null_in_required_column() {
+        let column = Column::Utf8(vec![Some("a".to_owned()), None]);
+        assert!(encode_page(&column, false).is_err());
+        assert_eq!(
+            encode_page(&column, true).unwrap(),
+            [4, 0, 0, 0, 0x02, 0x01, 0x02, 0x00, 1, 0, 0, 0, b'a']
+        );
+    }
+
+    /// `testdata/articles.parquet` was checked to be read back by the reader of the
+    /// `parquet` crate as these tables. Regenerate and recheck it if the format
+    /// or the crate version (it is written to the footer) changes.
+    #[test]
+    fn writes_file_readable_by_other_implementations() {
+        const SCHEMA: &TableSchema = &[
+            ("id", ColumnType::Utf8, false),
+            ("date", ColumnType::Timestamp, false),
+            ("ingested_at", ColumnType::Timestamp, true),
+            ("score", ColumnType::Float32, false),
+            ("ratio", ColumnType::Float64, false),
+            ("count", ColumnType::UInt64, false),
+            ("duplicate_of", ColumnType::Utf8, true),
+        ];
+        let date = |millis| Utc.timestamp_millis(millis);
+        let tables = [
+            Table {
+                columns: vec![
+                    Column::Utf8(vec![Some("a".to_owned()), Some("Київ".to_owned())]),
+                    Column::Timestamp(vec![Some(date(1_527_292_800_000)), Some(date(-1))]),
+                    Column::Timestamp(vec![None, Some(date(1_592_000_000_123))]),
+                    Column::Float32(vec![0.5, -1.25]),
+                    Column::Float64(vec![0.1, 1e100]),
+                    Column::UInt64(vec![0, u64::MAX]),
+                    Column::Utf8(vec![None, Some("a".to_owned())]),
+                ],
+            },
+            Table {
+                columns: vec![
+                    Column::Utf8(vec![Some(String::new())]),
+                    Column::Timestamp(vec![Some(date(0))]),
+                    Column::Timestamp(vec![Some(date(1))]),
+                    Column::Float32(vec![3.0]),
+                    Column::Float64(vec![-0.0]),
+                    Column::UInt64(vec![42]),
+                    Column::Utf8(vec![None]),
+                ],
+            },
+        ];
+
+        let path =
+            std::env::temp_dir().join(format!("vna_parquet_test_{}.parquet", std::process::id()));
+        let mut writer = ParquetWriter::new(File::create(&path).unwrap(), SCHEMA).unwrap();
+        for table in &tables {
+            writer.write(table).unwrap();
+        }
+        writer.finish().unwrap();
+        let written = std::fs::read(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            written.unwrap(),
+            &include_bytes!("testdata/articles.parquet")[..]
+        );
+    }
+}
@@ -1,27 +1,146 @@
 //! vee-news-analyzer cli entrypoint
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use charts::{Chart, ScaleBand, ScaleLinear, VerticalBarView};
+use config::SnapshotsConfig;
+use itertools::Itertools;
 use std::{
+    io::Write,
+    net::SocketAddr,
     num::NonZeroU32,
     ops::Deref,
     path::{Path, PathBuf},
+    time::Duration,
 };
 use structopt::StructOpt;
 use url::Url;
 use vna_es_utils::es_types;
 
+mod config;
+mod export;
+mod schedule;
+mod shell;
+
 #[structopt(name = "vee-news-analyzer")]
 #[derive(Debug, StructOpt)]
 struct CliArgs {
-    /// Elasticsearch endpoint url to use
-    #[structopt(long, env = "VNA_ES_URL")]
-    es_url: Url,
+    /// Name of the `[profiles.<name>]` table in the config files to apply
+    #[structopt(long, env = "VNA_PROFILE")]
+    profile: Option<String>,
+
+    #[structopt(flatten)]
+    es: EsConnectionArgs,
+
+    #[structopt(flatten)]
+    health: WaitForHealthArgs,
 
     #[structopt(flatten)]
     subcommand: CliSubcommand,
 }
 
+/// Cli flags override the values from the config files and environment variables,
+/// see `config/defaults.toml` for the config keys they correspond to
+#[derive(Debug, StructOpt)]
+struct EsConnectionArgs {
+    /// Elasticsearch endpoint urls to use (comma-separated),
+    /// requests are distributed among them in round-robin fashion [env: VNA_ES_URL]
+    #[structopt(long = "es-url", use_delimiter = true)]
+    es_urls: Option<Vec<Url>>,
+
+    /// Id of Elastic Cloud deployment to connect to instead of `--es-url` [env: VNA_ES_CLOUD_ID]
+    #[structopt(long, conflicts_with = "es-urls")]
+    es_cloud_id: Option<String>,
+
+    /// Discover all the nodes of the cluster via the specified `--es-url` seed nodes
+    #[structopt(long)]
+    es_sniff: bool,
+
+    /// Username for basic authentication in Elasticsearch [env: VNA_ES_USERNAME]
+    #[structopt(long)]
+    es_username: Option<String>,
+
+    /// Password for basic authentication in Elasticsearch [env: VNA_ES_PASSWORD]
+    #[structopt(long)]
+    es_password: Option<String>,
+
+    /// Id of the API key to authenticate in Elasticsearch with [env: VNA_ES_API_KEY_ID]
+    #[structopt(long, conflicts_with = "es-username")]
+    es_api_key_id: Option<String>,
+
+    /// API key to authenticate in Elasticsearch with [env: VNA_ES_API_KEY]
+    #[structopt(long)]
+    es_api_key: Option<String>,
+
+    /// Path to PKCS#12 archive with the client certificate to authenticate
+    /// in Elasticsearch with [env: VNA_ES_CLIENT_CERT]
+    #[structopt(long, conflicts_with_all = &["es-username", "es-api-key-id"])]
+    es_client_cert: Option<PathBuf>,
+
+    /// Password of the PKCS#12 archive with the client certificate [env: VNA_ES_CLIENT_CERT_PASSWORD]
+    #[structopt(long)]
+    es_client_cert_password: Option<String>,
+
+    /// Path to PEM-encoded CA certificate to validate Elasticsearch certificates with [env: VNA_ES_CA_CERT]
+    #[structopt(long)]
+    es_ca_cert: Option<PathBuf>,
+
+    /// Don't validate Elasticsearch certificates at all (dangerous, use only for development)
+    #[structopt(long, conflicts_with = "es-ca-cert")]
+    es_insecure: bool,
+
+    /// Timeout for each request to Elasticsearch in seconds [env: VNA_ES_TIMEOUT]
+    #[structopt(long)]
+    es_timeout: Option<u64>,
+}
+
+impl EsConnectionArgs {
+    fn apply_to(&self, layers: &mut config::Layers) -> Result<()> {
+        layers.set_flag("elasticsearch.urls", &self.es_urls)?;
+        layers.set_flag("elasticsearch.cloud_id", &self.es_cloud_id)?;
+        layers.set_flag("elasticsearch.sniff", &true_or_none(self.es_sniff))?;
+        layers.set_flag("elasticsearch.username", &self.es_username)?;
+        layers.set_flag("elasticsearch.password", &self.es_password)?;
+        layers.set_flag("elasticsearch.api_key_id", &self.es_api_key_id)?;
+        layers.set_flag("elasticsearch.api_key", &self.es_api_key)?;
+        layers.set_flag("elasticsearch.client_cert", &self.es_client_cert)?;
+        layers.set_flag(
+            "elasticsearch.client_cert_password",
+            &self.es_client_cert_password,
+        )?;
+        layers.set_flag("elasticsearch.ca_cert", &self.es_ca_cert)?;
+        layers.set_flag("elasticsearch.insecure", &true_or_none(self.es_insecure))?;
+        layers.set_flag("elasticsearch.timeout_secs", &self.es_timeout)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct WaitForHealthArgs {
+    /// Wait until the cluster health is at least the given status (green|yellow|red)
+    /// before running `data-sync` and `snapshots` commands
+    #[structopt(long)]
+    wait_for_status: Option<vna_es_utils::HealthStatus>,
+
+    /// Maximum number of seconds to wait for `--wait-for-status` [default: 30]
+    #[structopt(long)]
+    wait_timeout: Option<u64>,
+}
+
+impl WaitForHealthArgs {
+    fn apply_to(&self, layers: &mut config::Layers) -> Result<()> {
+        layers.set_flag("health.wait_for_status", &self.wait_for_status)?;
+        layers.set_flag("health.wait_timeout_secs", &self.wait_timeout)
+    }
+}
+
+/// Boolean flags can only turn the config option on, absent flag leaves it as is
+fn true_or_none(flag: bool) -> Option<bool> {
+    if flag {
+        Some(true)
+    } else {
+        None
+    }
+}
+
 #[derive(Debug, StructOpt)]
 enum CliSubcommand {
     /// Run data synchronization job that will use the external data source
@@ -33,25 +152,49 @@ enum CliSubcommand {
         #[structopt(long)]
         scrape_interval: Option<u32>,
 
-        /// Maximum amount of news to retain in Elasticsearch database
-        #[structopt(long, default_value = "300000")]
-        max_news: u64,
+        /// Maximum amount of news to retain in Elasticsearch database [default: 300000]
+        #[structopt(long)]
+        max_news: Option<u64>,
+
+        /// Maximum size of the batch to ingest data with into Elasticsearch [default: 50000]
+        #[structopt(long)]
+        ingest_batch: Option<NonZeroU32>,
+
+        /// Number of shards to use for the Elasticsearch indices (min: 1) [default: 1]
+        #[structopt(long)]
+        n_shards: Option<NonZeroU32>,
+
+        /// Number of relicas to create for the Elasticsearch indices [default: 0]
+        #[structopt(long)]
+        n_replicas: Option<u32>,
+
+        /// Number of the previous index versions to keep after the new one
+        /// is created to be able to roll back to them [default: 1]
+        #[structopt(long)]
+        keep_versions: Option<u32>,
+
+        /// Take a snapshot of the new index after each successful sync (before the old
+        /// versions are deleted) and prune the expired data sync snapshots
+        #[structopt(long)]
+        snapshot: bool,
 
-        /// Maximum size of the batch to ingest data with into Elasticsearch
-        #[structopt(long, default_value = "50000")]
-        ingest_batch: NonZeroU32,
+        /// Prefix of the data sync snapshot names, the current time is appended to it
+        /// [default: vna_data_sync]
+        #[structopt(long)]
+        snapshot_prefix: Option<stdx::NonHollowString>,
 
-        /// Number of shards to use for the Elasticsearch indices (min: 1)
-        #[structopt(long, default_value = "1")]
-        n_shards: NonZeroU32,
+        /// Number of the most recent data sync snapshots to keep regardless of their age
+        #[structopt(long)]
+        snapshot_keep_last: Option<u32>,
 
-        /// Number of relicas to create for the Elasticsearch indices
-        #[structopt(long, default_value = "0")]
-        n_replicas: u32,
+        /// Delete the data sync snapshots older than that (e.g. `30d`, `12h`)
+        /// unless they are kept by `--snapshot-keep-last`
+        #[structopt(long)]
+        snapshot_max_age: Option<String>,
 
-        /// Whether to delete old index once the new one is created,
+        /// Abort on the first malformed dataset record instead of logging and skipping it
         #[structopt(long)]
-        leave_old_index: bool,
+        strict: bool,
 
         #[structopt(flatten)]
         data_source: DataSourceArgs,
@@ -70,48 +213,432 @@ enum CliSubcommand {
         /// text fields
         #[structopt(long)]
         field_name: Option<String>,
+
+        #[structopt(flatten)]
+        filters: FilterArgs,
     },
 
     /// Elasticsearch snapshots management commands
     Snapshots(Snapshots),
+
+    /// Commands to act on the individual articles by their ids
+    Article(ArticleCmd),
+
+    /// Report clusters of near-duplicate articles and optionally mark or remove them
+    Dedup {
+        /// Minimal similarity (from 0 to 1) of the headlines and descriptions
+        /// of the articles to consider them near-duplicates
+        #[structopt(long, default_value = "0.8")]
+        threshold: f64,
+
+        /// Maximum number of similar articles to compare each article with
+        #[structopt(long, default_value = "10")]
+        max_candidates: u32,
+
+        /// Set `duplicate_of` field of the duplicates to the id of the earliest
+        /// published article in their cluster
+        #[structopt(long, conflicts_with = "remove")]
+        mark: bool,
+
+        /// Delete all the articles in each cluster except the earliest published one
+        #[structopt(long)]
+        remove: bool,
+
+        #[structopt(flatten)]
+        filters: FilterArgs,
+    },
+
+    /// Run interactive shell that keeps the current query and filters between the commands
+    Shell,
+
+    /// Run HTTP server that exposes search and stats functionality via JSON API
+    Serve {
+        /// Socket address to listen for the incoming HTTP connections on [default: 127.0.0.1:8080]
+        #[structopt(long)]
+        listen: Option<SocketAddr>,
+    },
+
+    /// Inspect the configuration of the cli
+    Config(ConfigCmd),
+
+    /// Show the cluster health and the stats of the articles index
+    Health,
+
+    /// Manage the versions of the articles index
+    Index(IndexCmd),
+
+    /// Inspect the datasets before ingesting them
+    Dataset(DatasetCmd),
+
+    /// Stream the articles into a file. Newline-delimited JSON files can be loaded via
+    /// `vna import`, CSV and Arrow files are meant for the data analysis tools.
+    Export {
+        /// Path of the file to create, ndjson files are gzipped if it has `.gz` extension
+        #[structopt(long)]
+        out: PathBuf,
+
+        /// Format of the file (ndjson|csv|arrow|parquet) [default: guessed by the file extension]
+        #[structopt(long)]
+        format: Option<export::ExportFormat>,
+
+        /// Export only the articles that match this fulltext query
+        #[structopt(long)]
+        query: Option<stdx::NonHollowString>,
+
+        /// Number of articles to fetch at once (min: 1)
+        #[structopt(long, default_value = "1000")]
+        batch: NonZeroU32,
+
+        #[structopt(flatten)]
+        filters: FilterArgs,
+    },
+
+    /// Load the articles exported via `vna export` into the next version of the
+    /// articles index and point the articles alias to it
+    Import {
+        /// Path of the exported file, it is gunzipped if it has `.gz` extension
+        path: PathBuf,
+
+        /// Number of shards to use for the new index (min: 1) [default: 1]
+        #[structopt(long)]
+        n_shards: Option<NonZeroU32>,
+
+        /// Number of replicas to create for the new index [default: 0]
+        #[structopt(long)]
+        n_replicas: Option<u32>,
+
+        /// Number of articles to ingest at once (min: 1)
+        #[structopt(long, default_value = "1000")]
+        batch: NonZeroU32,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum TemplateCmd {
+    /// Create or update the index template, it affects only the indices created after that
+    Apply {
+        /// Path to the file with synonym rules, one rule per line in Solr format
+        #[structopt(long)]
+        synonyms: Option<PathBuf>,
+    },
+    /// Print the index template registered in Elasticsearch
+    Show,
+}
+
+#[derive(Debug, StructOpt)]
+enum IndexCmd {
+    /// List all the versions of the articles index with their stats
+    List,
+    /// Create the next version of the articles index without pointing the alias to it
+    Create {
+        /// Number of shards to use for the index (min: 1) [default: 1]
+        #[structopt(long)]
+        n_shards: Option<NonZeroU32>,
+
+        /// Number of replicas to create for the index [default: 0]
+        #[structopt(long)]
+        n_replicas: Option<u32>,
+    },
+    /// Delete the given version of the articles index (except the one the alias points to)
+    Delete { version: vna_es::IndexVersion },
+    /// Delete all the versions of the articles index except the one the alias
+    /// points to and the given number of the most recent other ones
+    Prune {
+        /// Number of the versions to keep besides the current one [default: 1]
+        #[structopt(long)]
+        keep: Option<u32>,
+    },
+    /// Show the stats of the index and its live mapping diffed against the expected one
+    Info {
+        /// Version of the index (the one the alias points to by default)
+        version: Option<vna_es::IndexVersion>,
+    },
+    /// Point the articles alias back to the previous version of the index
+    Rollback {
+        /// Version of the index to point the alias to
+        /// (the latest one preceding the current version by default)
+        #[structopt(long)]
+        to: Option<vna_es::IndexVersion>,
+    },
+    /// Copy the documents into the next version of the index applying the pending
+    /// schema migrations and point the articles alias to it
+    Migrate {
+        /// Schema version to migrate to [default: the latest one]
+        #[structopt(long)]
+        to: Option<u32>,
+
+        /// Number of shards to use for the new index (min: 1) [default: 1]
+        #[structopt(long)]
+        n_shards: Option<NonZeroU32>,
+
+        /// Number of replicas to create for the new index [default: 0]
+        #[structopt(long)]
+        n_replicas: Option<u32>,
+
+        /// Number of documents to copy at once (min: 1)
+        #[structopt(long, default_value = "1000")]
+        batch: NonZeroU32,
+    },
+    /// Manage the index template that defines the analyzers and the mapping of the new indices
+    Template(TemplateCmd),
+    /// Make the articles alias point to exactly one version of the index,
+    /// e.g. after an interrupted sync or manual changes to the alias
+    Repair {
+        /// Version of the index to point the alias to (the write index or
+        /// the latest version among the ones the alias points to by default)
+        #[structopt(long)]
+        to: Option<vna_es::IndexVersion>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum DatasetCmd {
+    /// Read the whole dataset and report the malformed records with their line numbers
+    Validate {
+        /// Path to the dataset in any format supported by `data-sync`
+        path: PathBuf,
+
+        #[structopt(flatten)]
+        dataset: DatasetArgs,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum ConfigCmd {
+    /// Print the effective configuration merged from all the layers
+    /// with the source of each value
+    Show,
 }
 
 #[derive(Debug, StructOpt)]
 enum Snapshots {
-    /// Register snapshots repository dir on the file-system
+    /// Register snapshots repository on the file-system, by url (read-only) or in S3 bucket
     RegisterRepo {
-        /// Name of the repo to create
-        #[structopt(long, default_value = "fs_repo")]
-        repo_name: stdx::NonHollowString,
+        /// Name of the repo to create [default: fs_repo]
+        #[structopt(long)]
+        repo_name: Option<stdx::NonHollowString>,
 
-        /// Path to thre directory where to put the snapshots
-        #[structopt(long, default_value = "/mnt/snapshots")]
-        path: PathBuf,
+        /// Type of the repo (fs|url|s3) [default: fs]
+        #[structopt(long = "type")]
+        repo_type: Option<vna_es::snapshots::RepoType>,
+
+        /// Path to thre directory where to put the snapshots for `fs` repo [default: /mnt/snapshots]
+        #[structopt(long)]
+        path: Option<PathBuf>,
+
+        /// Url of the directory with the snapshots for `url` repo
+        #[structopt(long)]
+        url: Option<String>,
+
+        /// Name of the bucket for `s3` repo
+        #[structopt(long)]
+        bucket: Option<String>,
+
+        /// Name of `s3.client.<name>` settings of the nodes for `s3` repo [default: default]
+        #[structopt(long)]
+        client: Option<String>,
+
+        /// Path inside the bucket for `s3` repo [default: the root of the bucket]
+        #[structopt(long)]
+        base_path: Option<String>,
+
+        /// Whether to compress the metadata files (true|false) [default: true]
+        #[structopt(long)]
+        compress: Option<bool>,
+
+        /// Split the big files into chunks of this size, e.g. `1gb` [default: no chunking]
+        #[structopt(long)]
+        chunk_size: Option<String>,
+
+        /// Register the repo only for restoring, e.g. when the repo is written by another cluster
+        #[structopt(long)]
+        readonly: bool,
+    },
+    /// Check that all the nodes of the cluster have access to the repository
+    VerifyRepo {
+        /// Name of the repo to verify [default: fs_repo]
+        #[structopt(long)]
+        repo_name: Option<stdx::NonHollowString>,
     },
     /// Create a snapshot of all indices in the cluster, waits unitil the
-    /// completion of this operation
+    /// completion of this operation. Prunes the old snapshots afterwards
+    /// if the retention policy is configured.
     Commit {
-        /// Name of the repo to put the snapshot into
-        #[structopt(long, default_value = "fs_repo")]
-        repo_name: stdx::NonHollowString,
+        /// Name of the repo to put the snapshot into [default: fs_repo]
+        #[structopt(long)]
+        repo_name: Option<stdx::NonHollowString>,
+
+        /// Prefix of the snapshot name, the current time is appended to it [default: vna_snapshot]
+        #[structopt(long)]
+        snapshot_name: Option<stdx::NonHollowString>,
+
+        /// Use the snapshot name as is without appending the current time to it
+        #[structopt(long)]
+        no_timestamp: bool,
+
+        #[structopt(flatten)]
+        retention: RetentionArgs,
+    },
+    /// Run forever taking the snapshots of all indices on the cron-like schedule
+    /// and pruning the expired ones after each of them
+    Schedule {
+        /// Name of the repo to put the snapshots into [default: fs_repo]
+        #[structopt(long)]
+        repo_name: Option<stdx::NonHollowString>,
+
+        /// Prefix of the snapshot names, the current time is appended to it [default: vna_snapshot]
+        #[structopt(long)]
+        snapshot_name: Option<stdx::NonHollowString>,
+
+        /// Schedule in cron format evaluated in UTC (`minute hour day-of-month month day-of-week`),
+        /// e.g. `0 3 * * *` for every day at 03:00
+        #[structopt(long)]
+        cron: Option<String>,
 
-        /// Name of the snapshot to produce
-        #[structopt(long, default_value = "vna_snapshot")]
-        snapshot_name: stdx::NonHollowString,
+        #[structopt(flatten)]
+        retention: RetentionArgs,
+    },
+    /// List all the snapshots in the repository from the oldest to the newest
+    List {
+        /// Name of the repo to list the snapshots of [default: fs_repo]
+        #[structopt(long)]
+        repo_name: Option<stdx::NonHollowString>,
+    },
+    /// Show the detailed status of the snapshot or of all the running snapshots
+    Status {
+        /// Name of the repo to look for the snapshot [default: fs_repo]
+        #[structopt(long)]
+        repo_name: Option<stdx::NonHollowString>,
+
+        /// Name of the snapshot (all the running snapshots by default)
+        snapshot: Option<String>,
+    },
+    /// Delete the snapshot from the repository
+    Delete {
+        /// Name of the repo to delete the snapshot from [default: fs_repo]
+        #[structopt(long)]
+        repo_name: Option<stdx::NonHollowString>,
+
+        snapshot: String,
+    },
+    /// Delete the snapshots that are expired according to the retention policy
+    Prune {
+        /// Name of the repo to prune [default: fs_repo]
+        #[structopt(long)]
+        repo_name: Option<stdx::NonHollowString>,
+
+        #[structopt(flatten)]
+        retention: RetentionArgs,
     },
     /// Restore Elasticsearch cluster from the given snapshot
     RestoreFrom {
-        /// Name of the repo to look for the snapshot
-        #[structopt(long, default_value = "fs_repo")]
-        repo_name: stdx::NonHollowString,
+        /// Name of the repo to look for the snapshot [default: fs_repo]
+        #[structopt(long)]
+        repo_name: Option<stdx::NonHollowString>,
+
+        /// Name of the snapshot use for restoring [default: the latest successful
+        /// snapshot with `snapshots.snapshot_name` prefix]
+        #[structopt(long)]
+        snapshot_name: Option<stdx::NonHollowString>,
+
+        /// Name or wildcard pattern of the index to restore, may be specified
+        /// several times (restores all indices by default)
+        #[structopt(long = "index-name", number_of_values = 1)]
+        index_names: Vec<String>,
+
+        /// Regex to rename the restored indices with, e.g. `articles_(.+)`
+        #[structopt(long, requires = "rename-replacement")]
+        rename_pattern: Option<String>,
+
+        /// Replacement of the renamed indices, e.g. `restored_articles_$1`
+        #[structopt(long, requires = "rename-pattern")]
+        rename_replacement: Option<String>,
+
+        /// Also restore the cluster state (templates, persistent settings, etc.)
+        #[structopt(long)]
+        include_global_state: bool,
 
-        /// Name of the snapshot use for restoring
-        #[structopt(long, default_value = "vna_snapshot")]
-        snapshot_name: stdx::NonHollowString,
+        /// What to do with the existing indices that have the same names as the restored ones
+        /// (fail|close|delete)
+        #[structopt(long, default_value = "fail")]
+        on_conflict: vna_es::snapshots::OnConflict,
 
-        /// Name of the index to restore (restores all indices by default)
+        /// Don't ask for a confirmation before closing or deleting the conflicting indices
         #[structopt(long)]
-        index_name: Option<stdx::NonHollowString>,
+        yes: bool,
+
+        /// Point the articles alias to the restored `articles_<version>` index,
+        /// the indices must not be renamed
+        #[structopt(long, conflicts_with = "rename-pattern")]
+        point_alias: bool,
+    },
+}
+
+/// Retention policy of the snapshots named as `<snapshots.snapshot_name>-<UTC time>`
+#[derive(Debug, StructOpt)]
+struct RetentionArgs {
+    /// Number of the most recent snapshots to keep regardless of their age
+    #[structopt(long)]
+    keep_last: Option<u32>,
+
+    /// Delete the snapshots older than that (e.g. `30d`, `12h`) unless they are kept by `--keep-last`
+    #[structopt(long)]
+    max_age: Option<String>,
+}
+
+impl RetentionArgs {
+    fn apply_to(&self, layers: &mut config::Layers) -> Result<()> {
+        layers.set_flag("snapshots.keep_last", &self.keep_last)?;
+        layers.set_flag("snapshots.max_age", &self.max_age)
+    }
+}
+
+/// Write the aggregation results into a file instead of rendering the chart
+#[derive(Debug, StructOpt)]
+struct StatsExportArgs {
+    /// Path of the file to write the aggregation results to
+    #[structopt(long)]
+    out: Option<PathBuf>,
+
+    /// Format of the file (ndjson|csv|arrow|parquet) [default: guessed by the file extension]
+    #[structopt(long, requires = "out")]
+    format: Option<export::ExportFormat>,
+}
+
+impl StatsExportArgs {
+    /// Returns `false` if the results should be rendered as a chart
+    fn write(&self, schema: &'static export::TableSchema, table: export::Table) -> Result<bool> {
+        let out = match &self.out {
+            Some(it) => it,
+            None => return Ok(false),
+        };
+        let format = export::ExportFormat::resolve(self.format, out)?;
+        export::write_table(out, format, schema, &table)?;
+        eprintln!("Wrote the aggregation results to {}", out.display());
+        Ok(true)
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum ArticleCmd {
+    /// Print the article with the given id
+    Get {
+        /// Id of the article in Elasticsearch
+        id: String,
+    },
+    /// Find the articles similar to the given one by their headline and description
+    Similar {
+        /// Id of the article to find the similar ones for
+        id: String,
+
+        /// Maximum number of similar articles to return
+        #[structopt(long, default_value = "10")]
+        max_results: u32,
+    },
+    /// Delete the article with the given id
+    Delete {
+        /// Id of the article in Elasticsearch
+        id: String,
     },
 }
 
@@ -130,9 +657,12 @@ enum Stats {
         #[structopt(long, default_value = "15")]
         max_words: u32,
 
-        /// Path where to put the rendered SVG chart
-        #[structopt(long, default_value = "./significant_words.svg")]
-        chart_path: PathBuf,
+        /// Path where to put the rendered SVG chart [default: ./significant_words.svg]
+        #[structopt(long)]
+        chart_path: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        output: StatsExportArgs,
     },
     /// Display the sentiment analysis statistics for the given
     /// subset of documents filtered by the query string or for all news
@@ -146,9 +676,15 @@ enum Stats {
         #[structopt(long, default_value = "short_description")]
         field_name: String,
 
-        /// Path where to put the rendered SVG chart
-        #[structopt(long, default_value = "./sentiment.svg")]
-        chart_path: PathBuf,
+        /// Path where to put the rendered SVG chart [default: ./sentiment.svg]
+        #[structopt(long)]
+        chart_path: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        output: StatsExportArgs,
+
+        #[structopt(flatten)]
+        filters: FilterArgs,
     },
     /// Display the category statistics for the given
     /// subset of documents filtered by the query string or for all news
@@ -162,17 +698,280 @@ enum Stats {
         #[structopt(long, default_value = "short_description")]
         field_name: String,
 
-        /// Path where to put the rendered SVG chart
-        #[structopt(long, default_value = "./category.svg")]
-        chart_path: PathBuf,
+        /// Path where to put the rendered SVG chart [default: ./category.svg]
+        #[structopt(long)]
+        chart_path: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        output: StatsExportArgs,
+
+        #[structopt(flatten)]
+        filters: FilterArgs,
+    },
+    /// Display the number of news published over time for the given
+    /// subset of documents filtered by the query string or for all news
+    /// altogether
+    Timeline {
+        /// Query that will be used to filter the documents to aggregate timeline
+        /// info. If not specified returns the timeline for all the news in Elasticsearch
+        query: Option<stdx::NonHollowString>,
+
+        /// Particular name of the field to search by in elasticsearch.
+        #[structopt(long, default_value = "short_description")]
+        field_name: String,
+
+        /// Size of the date buckets (day|week|month|quarter|year)
+        #[structopt(long, default_value = "month")]
+        interval: vna_es::CalendarInterval,
+
+        /// Path where to put the rendered SVG chart [default: ./timeline.svg]
+        #[structopt(long)]
+        chart_path: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        output: StatsExportArgs,
+
+        #[structopt(flatten)]
+        filters: FilterArgs,
     },
 }
 
+#[derive(Debug, StructOpt)]
+struct FilterArgs {
+    /// Take into account only the news of the given category
+    #[structopt(long)]
+    category: Option<String>,
+
+    /// Take into account only the news of the given sentiment polarity (positive|negative)
+    #[structopt(long)]
+    sentiment: Option<vna_es::SentimentPolarity>,
+
+    /// Take into account only the news published at this date or later
+    #[structopt(long)]
+    date_from: Option<String>,
+
+    /// Take into account only the news published at this date or earlier
+    #[structopt(long)]
+    date_to: Option<String>,
+}
+
+impl FilterArgs {
+    /// `time_zone` is the one the dates without an explicit offset are interpreted in
+    fn into_filters(self, time_zone: chrono::FixedOffset) -> vna_es::ArticleFilters {
+        vna_es::ArticleFilters {
+            category: self.category,
+            sentiment_polarity: self.sentiment,
+            date_from: self.date_from,
+            date_to: self.date_to,
+            time_zone: Some(time_zone),
+        }
+    }
+}
+
 #[derive(Debug, StructOpt)]
 struct DataSourceArgs {
-    /// Path to kaggle news dataset
-    #[structopt(long, env = "VNA_KAGGLE_PATH")]
-    kaggle_path: PathBuf,
+    /// Path to kaggle news dataset [env: VNA_KAGGLE_PATH].
+    /// It may be compressed with gzip (`.gz`), zstd (`.zst`) or zip (`.zip`)
+    #[structopt(long)]
+    kaggle_path: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    dataset: DatasetArgs,
+}
+
+#[derive(Debug, StructOpt)]
+struct DatasetArgs {
+    /// Format of the dataset records: ndjson, json (array of objects) or csv.
+    /// Detected by the file extension and the content by default
+    #[structopt(long)]
+    format: Option<vna_data_sync::DatasetFormat>,
+
+    /// Delimiter of the csv dataset columns [default: ,]
+    #[structopt(long)]
+    csv_delimiter: Option<char>,
+
+    /// Renames the dataset field to the article field, e.g. `--map-field title=headline`.
+    /// May be specified multiple times
+    #[structopt(long, number_of_values = 1, parse(try_from_str = parse_field_mapping))]
+    map_field: Vec<(String, String)>,
+
+    /// Timezone of the dataset dates without an explicit offset, e.g. `UTC` or `+02:00`
+    /// [default: UTC]. Only fixed offsets are supported, not IANA names with DST
+    /// like `Europe/Kiev`
+    #[structopt(long)]
+    default_timezone: Option<String>,
+}
+
+fn parse_field_mapping(mapping: &str) -> Result<(String, String)> {
+    let i = mapping
+        .find('=')
+        .with_context(|| format!("Field mapping '{}' must be `from=to`", mapping))?;
+    let (from, to) = (mapping[..i].trim(), mapping[i + 1..].trim());
+    if from.is_empty() || to.is_empty() || from.contains('.') {
+        bail!(
+            "Field mapping '{}' must be `from=to` with non-empty field names without dots",
+            mapping
+        );
+    }
+    Ok((from.to_owned(), to.to_owned()))
+}
+
+impl DatasetArgs {
+    fn apply_to(&self, layers: &mut config::Layers) -> Result<()> {
+        layers.set_flag("data_sync.format", &self.format)?;
+        layers.set_flag("data_sync.csv_delimiter", &self.csv_delimiter)?;
+        layers.set_flag("data_sync.default_timezone", &self.default_timezone)?;
+        for (from, to) in &self.map_field {
+            layers.set_flag(&format!("data_sync.field_mapping.{}", from), &Some(to))?;
+        }
+        Ok(())
+    }
+}
+
+impl CliSubcommand {
+    fn apply_to(&self, layers: &mut config::Layers) -> Result<()> {
+        match self {
+            CliSubcommand::DataSync {
+                max_news,
+                ingest_batch,
+                n_shards,
+                n_replicas,
+                keep_versions,
+                snapshot,
+                snapshot_prefix,
+                snapshot_keep_last,
+                snapshot_max_age,
+                strict,
+                data_source,
+                ..
+            } => {
+                layers.set_flag("data_sync.kaggle_path", &data_source.kaggle_path)?;
+                data_source.dataset.apply_to(layers)?;
+                layers.set_flag("data_sync.strict", &true_or_none(*strict))?;
+                layers.set_flag("data_sync.max_news", max_news)?;
+                layers.set_flag("data_sync.ingest_batch", ingest_batch)?;
+                layers.set_flag("data_sync.n_shards", n_shards)?;
+                layers.set_flag("data_sync.n_replicas", n_replicas)?;
+                layers.set_flag("data_sync.keep_versions", keep_versions)?;
+                layers.set_flag("data_sync.snapshot", &true_or_none(*snapshot))?;
+                layers.set_flag("data_sync.snapshot_prefix", snapshot_prefix)?;
+                layers.set_flag("data_sync.snapshot_keep_last", snapshot_keep_last)?;
+                layers.set_flag("data_sync.snapshot_max_age", snapshot_max_age)
+            }
+            CliSubcommand::Snapshots(Snapshots::RegisterRepo {
+                repo_name,
+                repo_type,
+                path,
+                url,
+                bucket,
+                client,
+                base_path,
+                compress,
+                chunk_size,
+                readonly,
+            }) => {
+                layers.set_flag("snapshots.repo_name", repo_name)?;
+                layers.set_flag("snapshots.repo_type", repo_type)?;
+                layers.set_flag("snapshots.repo_path", path)?;
+                layers.set_flag("snapshots.repo_url", url)?;
+                layers.set_flag("snapshots.s3_bucket", bucket)?;
+                layers.set_flag("snapshots.s3_client", client)?;
+                layers.set_flag("snapshots.s3_base_path", base_path)?;
+                layers.set_flag("snapshots.compress", compress)?;
+                layers.set_flag("snapshots.chunk_size", chunk_size)?;
+                layers.set_flag("snapshots.readonly", &true_or_none(*readonly))
+            }
+            CliSubcommand::Snapshots(Snapshots::Commit {
+                repo_name,
+                snapshot_name,
+                no_timestamp,
+                retention,
+            }) => {
+                layers.set_flag("snapshots.repo_name", repo_name)?;
+                layers.set_flag("snapshots.snapshot_name", snapshot_name)?;
+                layers.set_flag(
+                    "snapshots.timestamped",
+                    &if *no_timestamp { Some(false) } else { None },
+                )?;
+                retention.apply_to(layers)
+            }
+            CliSubcommand::Snapshots(Snapshots::Schedule {
+                repo_name,
+                snapshot_name,
+                cron,
+                retention,
+            }) => {
+                layers.set_flag("snapshots.repo_name", repo_name)?;
+                layers.set_flag("snapshots.snapshot_name", snapshot_name)?;
+                layers.set_flag("snapshots.schedule", cron)?;
+                retention.apply_to(layers)
+            }
+            CliSubcommand::Snapshots(Snapshots::Prune {
+                repo_name,
+                retention,
+            }) => {
+                layers.set_flag("snapshots.repo_name", repo_name)?;
+                retention.apply_to(layers)
+            }
+            // The snapshot name is not a prefix here, so it is not a part of the config
+            CliSubcommand::Snapshots(Snapshots::RestoreFrom { repo_name, .. })
+            | CliSubcommand::Snapshots(Snapshots::VerifyRepo { repo_name })
+            | CliSubcommand::Snapshots(Snapshots::List { repo_name })
+            | CliSubcommand::Snapshots(Snapshots::Status { repo_name, .. })
+            | CliSubcommand::Snapshots(Snapshots::Delete { repo_name, .. }) => {
+                layers.set_flag("snapshots.repo_name", repo_name)
+            }
+            CliSubcommand::Stats(stats) => {
+                let (key, chart_path) = match stats {
+                    Stats::SignificantWords { chart_path, .. } => {
+                        ("charts.significant_words_path", chart_path)
+                    }
+                    Stats::Sentiment { chart_path, .. } => ("charts.sentiment_path", chart_path),
+                    Stats::Category { chart_path, .. } => ("charts.category_path", chart_path),
+                    Stats::Timeline { chart_path, .. } => ("charts.timeline_path", chart_path),
+                };
+                layers.set_flag(key, chart_path)
+            }
+            CliSubcommand::Index(IndexCmd::Create {
+                n_shards,
+                n_replicas,
+            }) => {
+                layers.set_flag("data_sync.n_shards", n_shards)?;
+                layers.set_flag("data_sync.n_replicas", n_replicas)
+            }
+            CliSubcommand::Index(IndexCmd::Migrate {
+                n_shards,
+                n_replicas,
+                ..
+            })
+            | CliSubcommand::Import {
+                n_shards,
+                n_replicas,
+                ..
+            } => {
+                layers.set_flag("data_sync.n_shards", n_shards)?;
+                layers.set_flag("data_sync.n_replicas", n_replicas)
+            }
+            CliSubcommand::Index(IndexCmd::Template(TemplateCmd::Apply { synonyms })) => {
+                layers.set_flag("index.synonyms_path", synonyms)
+            }
+            CliSubcommand::Index(IndexCmd::Prune { keep }) => {
+                layers.set_flag("data_sync.keep_versions", keep)
+            }
+            CliSubcommand::Serve { listen } => layers.set_flag("server.listen", listen),
+            CliSubcommand::Dataset(DatasetCmd::Validate { dataset, .. }) => {
+                dataset.apply_to(layers)
+            }
+            CliSubcommand::Search { .. }
+            | CliSubcommand::Article(_)
+            | CliSubcommand::Dedup { .. }
+            | CliSubcommand::Shell
+            | CliSubcommand::Config(_)
+            | CliSubcommand::Health
+            | CliSubcommand::Index(_)
+            | CliSubcommand::Export { .. } => Ok(()),
+        }
+    }
 }
 
 #[tokio::main]
@@ -185,87 +984,328 @@ async fn main() -> Result<()> {
 
     let cli_args = CliArgs::from_args();
 
-    log::debug!("Using cli args: {:?}", cli_args);
+    // Connection args are not logged since they may contain credentials
+    log::debug!("Using cli subcommand: {:?}", cli_args.subcommand);
+
+    let mut layers = config::Layers::load(cli_args.profile.as_deref())?;
+    cli_args.es.apply_to(&mut layers)?;
+    cli_args.health.apply_to(&mut layers)?;
+    cli_args.subcommand.apply_to(&mut layers)?;
+
+    if let CliSubcommand::Config(ConfigCmd::Show) = cli_args.subcommand {
+        layers.print();
+        // Validate the config only after printing it to make the errors easier to track down
+        layers.resolve()?;
+        return Ok(());
+    }
+
+    let config = layers.resolve()?;
+
+    if let CliSubcommand::Dataset(DatasetCmd::Validate { path, .. }) = &cli_args.subcommand {
+        return validate_dataset(path, &config.data_sync);
+    }
+
+    let elastic = &vna_es_utils::connect(&config.elasticsearch.connection_opts()?).await?;
 
-    let elastic = &vna_es_utils::create_elasticsearch_client(cli_args.es_url)?;
+    if let (CliSubcommand::DataSync { .. }, Some(status))
+    | (CliSubcommand::Snapshots(_), Some(status)) =
+        (&cli_args.subcommand, config.health.wait_for_status)
+    {
+        let timeout = Duration::from_secs(config.health.wait_timeout_secs);
+        eprintln!("Waiting for {} cluster health status...", status);
+        vna_es_utils::wait_for_health(elastic, status, timeout).await?;
+    }
+
+    if let CliSubcommand::DataSync { .. }
+    | CliSubcommand::Index(IndexCmd::Create { .. })
+    | CliSubcommand::Index(IndexCmd::Migrate { .. })
+    | CliSubcommand::Import { .. } = &cli_args.subcommand
+    {
+        // Keep the template in sync with the code and the synonyms file before creating indices
+        apply_index_template(elastic, &config.index).await?;
+    }
 
     match cli_args.subcommand {
         CliSubcommand::DataSync {
-            max_news,
-            scrape_interval,
-            data_source,
-            n_replicas,
-            n_shards,
-            ingest_batch,
-            leave_old_index,
+            scrape_interval, ..
         } => {
+            let kaggle_path = config.data_sync.kaggle_path.as_deref().context(
+                "Path to kaggle dataset must be specified via `--kaggle-path`, \
+                `VNA_KAGGLE_PATH` or `data_sync.kaggle_path` config key",
+            )?;
+
+            let dataset = dataset_opts(&config.data_sync)?;
+            let cleaning = cleaning_opts(&config.data_sync.cleaning)?;
+
             eprintln!("Running data sync task...");
             let time = std::time::Instant::now();
             let stats = vna_data_sync::run(vna_data_sync::RunOpts {
                 elastic,
-                kaggle_dataset_path: &data_source.kaggle_path,
+                kaggle_dataset_path: kaggle_path,
+                dataset: &dataset,
+                strict: config.data_sync.strict,
+                cleaning: &cleaning,
                 scrape_interval,
-                max_news,
-                n_replicas,
-                n_shards,
-                ingest_batch,
-                leave_old_index,
+                max_news: config.data_sync.max_news,
+                n_replicas: config.data_sync.n_replicas,
+                n_shards: config.data_sync.n_shards,
+                ingest_batch: config.data_sync.ingest_batch,
+                keep_versions: config.data_sync.keep_versions,
+                snapshot: if config.data_sync.snapshot {
+                    Some(data_sync_snapshot_opts(&config)?)
+                } else {
+                    None
+                },
             })
             .await?;
             eprintln!(
                 "Data sync task has finished\n\
                 took: {:?},\n\
                 new_index_name: {},\n\
-                total_indexed: {}\n",
+                total_indexed: {},\n\
+                total_skipped: {},\n\
+                total_dropped: {},\n\
+                pruned_versions: [{}],\n\
+                snapshot: {},\n\
+                pruned_snapshots: [{}],\n\
+                warnings: [{}]\n",
                 time.elapsed(),
                 stats.new_index_name,
                 stats.total_indexed,
+                stats.total_skipped,
+                stats.total_dropped,
+                stats.pruned_versions.iter().join(", "),
+                stats.snapshot.as_deref().unwrap_or("-"),
+                stats.pruned_snapshots.join(", "),
+                stats.warnings.join("; "),
             );
         }
         CliSubcommand::Snapshots(it) => match it {
-            Snapshots::RegisterRepo { path, repo_name } => {
-                vna_es::snapshots::register_repo(elastic, &path, &repo_name).await?;
+            Snapshots::RegisterRepo { .. } => {
+                let config = &config.snapshots;
+                let opts = vna_es::snapshots::RegisterRepoOpts {
+                    elastic,
+                    repo: &config.repo_name,
+                    location: snapshot_repo_location(config)?,
+                    settings: vna_es::snapshots::RepoSettings {
+                        compress: config.compress,
+                        chunk_size: config.chunk_size.as_deref(),
+                        readonly: config.readonly,
+                    },
+                };
+                vna_es::snapshots::register_repo(&opts).await?;
+                eprintln!(
+                    "Registered {} snapshot repo '{}' under '{}'",
+                    opts.location.repo_type(),
+                    config.repo_name,
+                    opts.location
+                );
+            }
+            Snapshots::VerifyRepo { .. } => {
+                let repo_name = config.snapshots.repo_name;
+                let nodes = vna_es::snapshots::verify_repo(elastic, &repo_name).await?;
                 eprintln!(
-                    "Registered snapshot repo '{}' under '{}'",
+                    "Snapshot repo '{}' is accessible from nodes: {}",
                     repo_name,
-                    path.display()
+                    nodes.join(", ")
                 );
             }
-            Snapshots::Commit {
-                snapshot_name,
-                repo_name,
-            } => {
-                vna_es::snapshots::take_snapshot(elastic, &repo_name, &snapshot_name).await?;
+            Snapshots::Commit { .. } => {
+                let SnapshotsConfig {
+                    snapshot_name,
+                    repo_name,
+                    timestamped,
+                    ..
+                } = &config.snapshots;
+                let name = if *timestamped {
+                    vna_es::snapshots::timestamped_snapshot_name(snapshot_name)
+                } else {
+                    snapshot_name.to_string()
+                };
+                let info = vna_es::snapshots::take_snapshot(elastic, repo_name, &name, &[]).await?;
                 eprintln!(
-                    "Created a snapshot {} in snapshot repo '{}'",
-                    snapshot_name, repo_name
+                    "Created a snapshot {} in snapshot repo '{}'\n\
+                    state: {},\n\
+                    indices: {},\n\
+                    took: {:?}",
+                    info.snapshot,
+                    repo_name,
+                    info.state,
+                    info.indices.join(", "),
+                    Duration::from_millis(info.duration_in_millis.unwrap_or_default()),
                 );
+                prune_snapshots(elastic, &config.snapshots).await?;
+            }
+            Snapshots::Schedule { .. } => {
+                let schedule: schedule::Schedule = config
+                    .snapshots
+                    .schedule
+                    .as_deref()
+                    .context(
+                        "Snapshot schedule must be specified via `--cron` \
+                        or `snapshots.schedule` config key",
+                    )?
+                    .parse()?;
+                run_snapshot_schedule(elastic, &schedule, &config.snapshots).await?;
+            }
+            Snapshots::List { .. } => {
+                let snapshots =
+                    vna_es::snapshots::list_snapshots(elastic, &config.snapshots.repo_name).await?;
+                for it in &snapshots {
+                    println!(
+                        "{} {:<11} started: {} took: {:?} indices: {}",
+                        it.snapshot,
+                        it.state,
+                        it.start_time.as_deref().unwrap_or("-"),
+                        Duration::from_millis(it.duration_in_millis.unwrap_or_default()),
+                        it.indices.join(", "),
+                    );
+                }
+                eprintln!("Found {} snapshots", snapshots.len());
+            }
+            Snapshots::Status { snapshot, .. } => {
+                let statuses = vna_es::snapshots::snapshot_status(
+                    elastic,
+                    &config.snapshots.repo_name,
+                    snapshot.as_deref(),
+                )
+                .await?;
+                if statuses.is_empty() {
+                    eprintln!("There are no running snapshots");
+                }
+                for it in statuses {
+                    println!(
+                        "{} ({})\n\
+                        shards: {}/{} done, {} failed\n\
+                        files: {} ({}), incremental: {} ({})\n\
+                        took: {:?}",
+                        it.snapshot,
+                        it.state,
+                        it.shards_stats.done,
+                        it.shards_stats.total,
+                        it.shards_stats.failed,
+                        it.stats.total.file_count,
+                        format_bytes(it.stats.total.size_in_bytes),
+                        it.stats.incremental.file_count,
+                        format_bytes(it.stats.incremental.size_in_bytes),
+                        Duration::from_millis(it.stats.time_in_millis),
+                    );
+                }
+            }
+            Snapshots::Delete { snapshot, .. } => {
+                let repo_name = config.snapshots.repo_name;
+                vna_es::snapshots::delete_snapshot(elastic, &repo_name, &snapshot).await?;
+                eprintln!(
+                    "Deleted snapshot '{}' from snapshot repo '{}'",
+                    snapshot, repo_name
+                );
+            }
+            Snapshots::Prune { .. } => {
+                if config.snapshots.keep_last.is_none() && config.snapshots.max_age.is_none() {
+                    bail!(
+                        "Retention policy must be specified via `--keep-last`, `--max-age` \
+                        or `snapshots.keep_last`, `snapshots.max_age` config keys"
+                    );
+                }
+                prune_snapshots(elastic, &config.snapshots).await?;
             }
             Snapshots::RestoreFrom {
-                repo_name,
                 snapshot_name,
-                index_name,
+                index_names,
+                rename_pattern,
+                rename_replacement,
+                include_global_state,
+                on_conflict,
+                yes,
+                point_alias,
+                ..
             } => {
-                vna_es::snapshots::restore_from_snapshot(
+                let repo_name = &config.snapshots.repo_name;
+                let snapshot_name = match snapshot_name {
+                    Some(it) => it.to_string(),
+                    None => {
+                        vna_es::snapshots::latest_snapshot(
+                            elastic,
+                            repo_name,
+                            &config.snapshots.snapshot_name,
+                        )
+                        .await?
+                    }
+                };
+                let opts = vna_es::snapshots::RestoreOpts {
                     elastic,
-                    &repo_name,
-                    &snapshot_name,
-                    &index_name,
-                )
-                .await?;
+                    repo: repo_name,
+                    snapshot: &snapshot_name,
+                    indices: &index_names,
+                    rename_pattern: rename_pattern.as_deref(),
+                    rename_replacement: rename_replacement.as_deref(),
+                    include_global_state,
+                };
+                let plan = vna_es::snapshots::plan_restore(&opts).await?;
+
+                for index in &plan.indices {
+                    eprintln!("Restoring index '{}'", index);
+                }
+                if let (Some(pattern), Some(replacement)) = (&rename_pattern, &rename_replacement) {
+                    eprintln!(
+                        "The indices are renamed via '{}' pattern and '{}' replacement",
+                        pattern, replacement
+                    );
+                }
+
+                let needs_confirmation = !plan.conflicts.is_empty()
+                    && on_conflict != vna_es::snapshots::OnConflict::Fail
+                    && !yes;
+                if needs_confirmation {
+                    let action = match on_conflict {
+                        vna_es::snapshots::OnConflict::Close => "closed",
+                        _ => "deleted",
+                    };
+                    let prompt = format!(
+                        "Existing indices [{}] will be {}, continue?",
+                        plan.conflicts.join(", "),
+                        action
+                    );
+                    if !confirm(&prompt)? {
+                        bail!("Restore was cancelled");
+                    }
+                }
+
+                let info = vna_es::snapshots::restore_snapshot(&opts, &plan, on_conflict).await?;
                 eprintln!(
-                    "Restored the state from the '{}' under '{}' repo",
-                    snapshot_name, repo_name
+                    "Restored the state from the '{}' under '{}' repo\n\
+                    indices: {},\n\
+                    shards: {} successful, {} failed",
+                    snapshot_name,
+                    repo_name,
+                    info.indices.join(", "),
+                    info.shards.successful,
+                    info.shards.failed,
                 );
+
+                if point_alias {
+                    let version =
+                        vna_es::snapshots::point_alias_to_restored(elastic, &info).await?;
+                    eprintln!(
+                        "'{}' alias now points to version {}",
+                        vna_es::Article::INDEX_ALIAS,
+                        version
+                    );
+                }
             }
         },
-        CliSubcommand::Search { field_name, query } => {
+        CliSubcommand::Search {
+            field_name,
+            query,
+            filters,
+        } => {
             eprintln!("Searching for articles...");
 
             let articles = vna_es::Article::fulltext_search(vna_es::FulltextSearchOpts {
                 elastic,
                 field_name: field_name.as_deref(),
                 query: &query,
+                filters: &filters.into_filters(default_timezone(&config.data_sync)?),
             })
             .await?;
 
@@ -274,13 +1314,335 @@ async fn main() -> Result<()> {
                 eprintln!("{:#?}", article);
             }
         }
+        CliSubcommand::Article(it) => match it {
+            ArticleCmd::Get { id } => match vna_es::Article::fetch_by_id(elastic, &id).await? {
+                Some(article) => eprintln!("{:#?}", article),
+                None => bail!("Article with id '{}' was not found", id),
+            },
+            ArticleCmd::Similar { id, max_results } => {
+                let articles = vna_es::Article::find_similar(vna_es::FindSimilarOpts {
+                    elastic,
+                    id: &id,
+                    max_results,
+                })
+                .await?;
+
+                eprintln!("Found similar articles {}", articles.len());
+                for article in articles {
+                    eprintln!("{:#?}", article);
+                }
+            }
+            ArticleCmd::Delete { id } => {
+                if !vna_es::Article::delete_by_id(elastic, &id).await? {
+                    bail!("Article with id '{}' was not found", id);
+                }
+                eprintln!("Deleted article '{}'", id);
+            }
+        },
+        CliSubcommand::Dedup {
+            threshold,
+            max_candidates,
+            mark,
+            remove,
+            filters,
+        } => {
+            eprintln!("Looking for near-duplicate articles...");
+            let clusters = vna_es::Article::find_duplicates(vna_es::FindDuplicatesOpts {
+                elastic,
+                filters: &filters.into_filters(default_timezone(&config.data_sync)?),
+                threshold,
+                max_candidates,
+            })
+            .await?;
+
+            let timezone = default_timezone(&config.data_sync)?;
+            let format_date = |date: chrono::DateTime<chrono::Utc>| {
+                date.with_timezone(&timezone).format("%Y-%m-%d")
+            };
+            for cluster in &clusters {
+                let canonical = cluster.canonical();
+                eprintln!(
+                    "{} {} {}",
+                    canonical.id,
+                    format_date(canonical.doc.date),
+                    canonical.doc.headline
+                );
+                for duplicate in cluster.duplicates() {
+                    eprintln!(
+                        "  {} {} {}",
+                        duplicate.id,
+                        format_date(duplicate.doc.date),
+                        duplicate.doc.headline
+                    );
+                }
+            }
+            eprintln!("Found {} clusters of near-duplicates", clusters.len());
+
+            if mark {
+                let n_marked = vna_es::Article::mark_duplicates(elastic, &clusters).await?;
+                eprintln!("Marked {} duplicates", n_marked);
+            } else if remove {
+                let n_removed = vna_es::Article::remove_duplicates(elastic, &clusters).await?;
+                eprintln!("Removed {} duplicates", n_removed);
+            }
+        }
+        CliSubcommand::Shell => shell::run(elastic, default_timezone(&config.data_sync)?).await?,
+        CliSubcommand::Serve { .. } => {
+            let listen = config.server.listen;
+            let timezone = default_timezone(&config.data_sync)?;
+            eprintln!("Listening for HTTP requests on {}", listen);
+            vna_server::serve(elastic.clone(), listen, timezone).await;
+        }
+        CliSubcommand::Index(it) => match it {
+            IndexCmd::List => {
+                let versions = vna_es::Article::list_index_versions(elastic).await?;
+                for it in &versions {
+                    print_index_version_info(it);
+                }
+                eprintln!("Found {} versions of the index", versions.len());
+            }
+            IndexCmd::Create { .. } => {
+                let version = vna_es::Article::next_index_version(elastic).await?;
+                vna_es::Article::create_index(&vna_es::CreateArticlesIndexOpts {
+                    elastic,
+                    version,
+                    number_of_shards: config.data_sync.n_shards,
+                    number_of_replicas: config.data_sync.n_replicas,
+                })
+                .await?;
+                eprintln!(
+                    "Created index '{}'",
+                    version.attach_to_alias(vna_es::Article::INDEX_ALIAS)
+                );
+            }
+            IndexCmd::Delete { version } => {
+                vna_es::Article::delete_unused_index(elastic, version).await?;
+                eprintln!(
+                    "Deleted index '{}'",
+                    version.attach_to_alias(vna_es::Article::INDEX_ALIAS)
+                );
+            }
+            IndexCmd::Prune { .. } => {
+                let pruned =
+                    vna_es::Article::prune_index_versions(elastic, config.data_sync.keep_versions)
+                        .await?;
+                eprintln!(
+                    "Deleted {} versions of the index: [{}]",
+                    pruned.len(),
+                    pruned.iter().join(", ")
+                );
+            }
+            IndexCmd::Info { version } => {
+                let versions = vna_es::Article::list_index_versions(elastic).await?;
+                let info = match version {
+                    Some(version) => versions.iter().find(|it| it.version == version),
+                    None => versions.iter().find(|it| it.is_current),
+                };
+                let info = match (info, version) {
+                    (Some(it), _) => it,
+                    (None, Some(version)) => bail!(
+                        "Index '{}' does not exist",
+                        version.attach_to_alias(vna_es::Article::INDEX_ALIAS)
+                    ),
+                    (None, None) => bail!(
+                        "'{}' alias does not exist yet",
+                        vna_es::Article::INDEX_ALIAS
+                    ),
+                };
+                print_index_version_info(info);
+
+                let schema_version =
+                    vna_es::Article::fetch_schema_version(elastic, info.version).await?;
+                let latest_schema = vna_es::migrations::latest().schema_version;
+                if schema_version < latest_schema {
+                    println!(
+                        "schema version: {} (outdated, the latest is {}, see `vna index migrate`)",
+                        schema_version, latest_schema
+                    );
+                } else {
+                    println!("schema version: {}", schema_version);
+                }
+
+                let diffs = vna_es::Article::diff_index_mapping(elastic, info.version).await?;
+                if diffs.is_empty() {
+                    println!("mapping: matches the expected one");
+                } else {
+                    println!("mapping diff (- expected, + live, ~ changed):");
+                    for diff in diffs {
+                        println!("  {}", diff);
+                    }
+                }
+            }
+            IndexCmd::Rollback { to } => {
+                let (from, to) = vna_es::Article::rollback_index(elastic, to).await?;
+                eprintln!(
+                    "'{}' alias was moved from version {} to version {}",
+                    vna_es::Article::INDEX_ALIAS,
+                    from,
+                    to
+                );
+            }
+            IndexCmd::Migrate { to, batch, .. } => {
+                let on_progress = |progress: vna_es::MigrationProgress| {
+                    eprint!(
+                        "\rMigrated {}/{} documents",
+                        progress.processed, progress.total
+                    );
+                };
+                let outcome = vna_es::Article::migrate_index(vna_es::MigrateOpts {
+                    elastic,
+                    to_schema: to,
+                    number_of_shards: config.data_sync.n_shards,
+                    number_of_replicas: config.data_sync.n_replicas,
+                    batch_size: batch,
+                    on_progress: &on_progress,
+                })
+                .await;
+                eprintln!();
+                let outcome = outcome?;
+                eprintln!(
+                    "Migrated {} documents from version {} (schema {}) to version {} (schema {}), \
+                    '{}' alias now points to the new version",
+                    outcome.migrated_docs,
+                    outcome.from_version,
+                    outcome.from_schema,
+                    outcome.to_version,
+                    outcome.to_schema,
+                    vna_es::Article::INDEX_ALIAS
+                );
+            }
+            IndexCmd::Template(TemplateCmd::Apply { .. }) => {
+                apply_index_template(elastic, &config.index).await?;
+                eprintln!(
+                    "Applied index template '{}' (version {})",
+                    vna_es::Article::INDEX_TEMPLATE,
+                    vna_es::migrations::latest().schema_version
+                );
+            }
+            IndexCmd::Template(TemplateCmd::Show) => {
+                let template = vna_es::Article::fetch_index_template(elastic)
+                    .await?
+                    .with_context(|| {
+                        format!(
+                            "Index template '{}' doesn't exist, create it via `vna index template apply`",
+                            vna_es::Article::INDEX_TEMPLATE
+                        )
+                    })?;
+                println!("{}", serde_json::to_string_pretty(&template)?);
+
+                let latest = vna_es::migrations::latest().schema_version;
+                if template.version != Some(latest) {
+                    eprintln!(
+                        "The template is outdated, the latest schema version is {}, \
+                        update it via `vna index template apply`",
+                        latest
+                    );
+                }
+            }
+            IndexCmd::Repair { to } => {
+                let repair = vna_es::Article::repair_index_alias(elastic, to).await?;
+                if !repair.repaired {
+                    eprintln!(
+                        "'{}' alias is consistent, it points to version {}",
+                        vna_es::Article::INDEX_ALIAS,
+                        repair.target
+                    );
+                } else {
+                    eprintln!(
+                        "'{}' alias now points only to version {}, removed it from: [{}]",
+                        vna_es::Article::INDEX_ALIAS,
+                        repair.target,
+                        repair.removed_from.join(", ")
+                    );
+                }
+            }
+        },
+        CliSubcommand::Health => {
+            let health = vna_es_utils::cluster_health(elastic).await?;
+            println!(
+                "cluster: {}\n\
+                status: {}\n\
+                nodes: {} (data nodes: {})\n\
+                shards: {} active, {} initializing, {} relocating, {} unassigned",
+                health.cluster_name,
+                health.status,
+                health.number_of_nodes,
+                health.number_of_data_nodes,
+                health.active_shards,
+                health.initializing_shards,
+                health.relocating_shards,
+                health.unassigned_shards,
+            );
+            match vna_es::Article::fetch_index_summary(elastic).await? {
+                Some(summary) => println!(
+                    "'{}' alias: {}\n\
+                    docs: {}\n\
+                    size: {}",
+                    vna_es::Article::INDEX_ALIAS,
+                    summary.index_name,
+                    summary.doc_count,
+                    format_bytes(summary.size_in_bytes),
+                ),
+                None => println!(
+                    "'{}' alias: not found (run `data-sync` to create it)",
+                    vna_es::Article::INDEX_ALIAS
+                ),
+            }
+        }
+        CliSubcommand::Export {
+            out,
+            format,
+            query,
+            batch,
+            filters,
+        } => {
+            let on_progress = |exported: u64| eprint!("\rExported {} articles", exported);
+            let opts = vna_es::ExportOpts {
+                elastic,
+                path: &out,
+                filters: &filters.into_filters(default_timezone(&config.data_sync)?),
+                query: query.as_deref(),
+                batch_size: batch,
+                on_progress: &on_progress,
+            };
+            let exported = match export::ExportFormat::resolve(format, &out)? {
+                export::ExportFormat::Ndjson => vna_es::Article::export_ndjson(opts).await,
+                format => export::export_articles(opts, format).await,
+            };
+            eprintln!();
+            eprintln!("Exported {} articles to {}", exported?, out.display());
+        }
+        CliSubcommand::Import { path, batch, .. } => {
+            let on_progress = |imported: u64| eprint!("\rImported {} articles", imported);
+            let outcome = vna_es::Article::import_ndjson(vna_es::ImportOpts {
+                elastic,
+                path: &path,
+                number_of_shards: config.data_sync.n_shards,
+                number_of_replicas: config.data_sync.n_replicas,
+                batch_size: batch,
+                on_progress: &on_progress,
+            })
+            .await;
+            eprintln!();
+            let outcome = outcome?;
+            eprintln!(
+                "Imported {} articles into version {}, '{}' alias now points to it",
+                outcome.total_imported,
+                outcome.version,
+                vna_es::Article::INDEX_ALIAS
+            );
+        }
+        CliSubcommand::Config(_) => unreachable!("config commands don't need Elasticsearch"),
+        CliSubcommand::Dataset(_) => unreachable!("dataset commands don't need Elasticsearch"),
         CliSubcommand::Stats(stats) => match stats {
             Stats::SignificantWords {
                 query,
                 max_words,
                 field_name,
-                chart_path,
+                output,
+                ..
             } => {
+                let chart_path = config.charts.significant_words_path;
                 let time = std::time::Instant::now();
                 let result: es_types::SignificantTextAggr =
                     vna_es::Article::significant_words(vna_es::SignificantWordsOpts {
@@ -298,6 +1660,11 @@ async fn main() -> Result<()> {
                     result.buckets.len()
                 );
 
+                let table = export::significant_words_table(&result.buckets);
+                if output.write(export::SIGNIFICANT_WORDS_SCHEMA, table)? {
+                    return Ok(());
+                }
+
                 if !result.buckets.is_empty() {
                     create_significant_words_chart(&result.buckets, &query, &chart_path)?;
                     open_svg_in_google_chrome(&chart_path)?;
@@ -306,35 +1673,81 @@ async fn main() -> Result<()> {
             Stats::Sentiment {
                 field_name,
                 query,
-                chart_path,
+                filters,
+                output,
+                ..
             } => {
+                let chart_path = config.charts.sentiment_path;
                 let stats = vna_es::Article::sentiment_stats(vna_es::StatsOpts {
                     elastic,
                     field_name: &field_name,
                     query: &query,
+                    filters: &filters.into_filters(default_timezone(&config.data_sync)?),
                 })
                 .await?;
 
+                if output.write(export::STATS_SCHEMA, export::stats_table(&stats))? {
+                    return Ok(());
+                }
+
                 create_sentiment_analysis_chart(&query, &chart_path, stats)?;
                 open_svg_in_google_chrome(&chart_path)?;
             }
             Stats::Category {
                 field_name,
                 query,
-                chart_path,
+                filters,
+                output,
+                ..
             } => {
+                let chart_path = config.charts.category_path;
                 let stats = vna_es::Article::category_stats(vna_es::StatsOpts {
                     elastic,
                     field_name: &field_name,
                     query: &query,
+                    filters: &filters.into_filters(default_timezone(&config.data_sync)?),
                 })
                 .await?;
 
                 dbg!(&stats.0);
 
+                if output.write(export::STATS_SCHEMA, export::stats_table(&stats))? {
+                    return Ok(());
+                }
+
                 create_category_analysis_chart(&query, &chart_path, stats)?;
                 open_svg_in_google_chrome(&chart_path)?;
             }
+            Stats::Timeline {
+                field_name,
+                query,
+                interval,
+                filters,
+                output,
+                ..
+            } => {
+                let chart_path = config.charts.timeline_path;
+                let stats = vna_es::Article::timeline_stats(
+                    vna_es::StatsOpts {
+                        elastic,
+                        field_name: &field_name,
+                        query: &query,
+                        filters: &filters.into_filters(default_timezone(&config.data_sync)?),
+                    },
+                    interval,
+                    default_timezone(&config.data_sync)?,
+                )
+                .await?;
+
+                if output.write(export::STATS_SCHEMA, export::stats_table(&stats))? {
+                    return Ok(());
+                }
+
+                if !stats.0.is_empty() {
+                    create_timeline_chart(&query, &chart_path, stats)?;
+                    open_svg_in_google_chrome(&chart_path)?;
+                }
+            }
         },
     }
 
@@ -377,6 +1790,24 @@ fn create_sentiment_analysis_chart(
     })
 }
 
+fn create_timeline_chart(
+    query: &Option<stdx::NonHollowString>,
+    file_path: &Path,
+    mut stats: vna_es::Stats,
+) -> Result<()> {
+    create_chart(ChartOpts {
+        title: match query {
+            Some(it) => format!("Timeline stats ({})", it.deref()),
+            None => "Timeline stats".to_owned(),
+        },
+        left_axis_label: "Total news published",
+        bottom_axis_label: "Dates",
+        color: charts::Color::from_vec_of_hex_strings(vec!["#4a7bb7"]),
+        path: file_path,
+        data: stats_to_chart_data(&mut stats),
+    })
+}
+
 fn stats_to_chart_data(stats: &mut vna_es::Stats) -> Vec<(&str, f32)> {
     for (name, _) in stats.0.iter_mut() {
         *name = name.replace("&", "and"); // FIXME: do real XML escaping here
@@ -407,6 +1838,236 @@ fn create_significant_words_chart(
     })
 }
 
+/// Asks the user a yes/no question in the terminal
+fn confirm(prompt: &str) -> Result<bool> {
+    eprint!("{} [y/N] ", prompt);
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn snapshot_repo_location(config: &SnapshotsConfig) -> Result<vna_es::snapshots::RepoLocation<'_>> {
+    use vna_es::snapshots::{RepoLocation, RepoType};
+    Ok(match config.repo_type {
+        RepoType::Fs => RepoLocation::Fs {
+            path: &config.repo_path,
+        },
+        RepoType::Url => RepoLocation::Url {
+            url: config.repo_url.as_deref().context(
+                "Url of the repo must be specified via `--url` or `snapshots.repo_url` config key",
+            )?,
+        },
+        RepoType::S3 => RepoLocation::S3 {
+            bucket: config.s3_bucket.as_deref().context(
+                "Bucket of the repo must be specified via `--bucket` \
+                or `snapshots.s3_bucket` config key",
+            )?,
+            client: config.s3_client.as_deref(),
+            base_path: config.s3_base_path.as_deref(),
+        },
+    })
+}
+
+/// Data sync snapshots have their own prefix, so they aren't pruned by the retention policy
+/// of the whole cluster snapshots and vice versa
+fn data_sync_snapshot_opts(config: &config::Config) -> Result<vna_data_sync::SnapshotOpts<'_>> {
+    let prefix: &str = &config.data_sync.snapshot_prefix;
+    if prefix == &*config.snapshots.snapshot_name {
+        bail!(
+            "`data_sync.snapshot_prefix` must differ from `snapshots.snapshot_name` ('{}'), \
+            otherwise their retention policies would prune each other's snapshots",
+            prefix
+        );
+    }
+    Ok(vna_data_sync::SnapshotOpts {
+        repo: &config.snapshots.repo_name,
+        retention: vna_es::snapshots::RetentionPolicy {
+            prefix,
+            keep_last: config.data_sync.snapshot_keep_last,
+            max_age: config.data_sync.snapshot_max_age,
+        },
+    })
+}
+
+fn snapshots_retention(config: &SnapshotsConfig) -> vna_es::snapshots::RetentionPolicy<'_> {
+    vna_es::snapshots::RetentionPolicy {
+        prefix: &config.snapshot_name,
+        keep_last: config.keep_last,
+        max_age: config.max_age,
+    }
+}
+
+/// Never returns normally, the failed snapshots are logged and retried on the next tick
+async fn run_snapshot_schedule(
+    elastic: &vna_es_utils::elasticsearch::Elasticsearch,
+    schedule: &schedule::Schedule,
+    config: &SnapshotsConfig,
+) -> Result<()> {
+    loop {
+        let now = std::time::SystemTime::now();
+        let next = schedule
+            .next_after(now)
+            .context("Snapshot schedule never fires")?;
+
+        eprintln!(
+            "Next snapshot is scheduled at {}",
+            humantime::format_rfc3339_seconds(next)
+        );
+        tokio::time::delay_for(next.duration_since(now).unwrap_or_default()).await;
+
+        // Scheduled snapshots are always timestamped, otherwise their names would clash
+        let name = vna_es::snapshots::timestamped_snapshot_name(&config.snapshot_name);
+        match vna_es::snapshots::take_snapshot(elastic, &config.repo_name, &name, &[]).await {
+            Ok(info) => eprintln!(
+                "Created a snapshot {} in snapshot repo '{}' (state: {})",
+                info.snapshot, config.repo_name, info.state
+            ),
+            Err(err) => {
+                log::error!("Failed to take a scheduled snapshot: {:?}", err);
+                continue;
+            }
+        }
+        if let Err(err) = prune_snapshots(elastic, config).await {
+            log::error!("Failed to prune the expired snapshots: {:?}", err);
+        }
+    }
+}
+
+/// Deletes the snapshots according to the configured retention policy
+async fn prune_snapshots(
+    elastic: &vna_es_utils::elasticsearch::Elasticsearch,
+    config: &SnapshotsConfig,
+) -> Result<()> {
+    let policy = snapshots_retention(config);
+    if policy.is_empty() {
+        return Ok(());
+    }
+    let pruned = vna_es::snapshots::prune_snapshots(elastic, &config.repo_name, &policy).await?;
+    eprintln!(
+        "Deleted {} expired snapshots: [{}]",
+        pruned.len(),
+        pruned.join(", ")
+    );
+    Ok(())
+}
+
+fn dataset_opts(config: &config::DataSyncConfig) -> Result<vna_data_sync::DatasetOpts> {
+    if !config.csv_delimiter.is_ascii() {
+        bail!(
+            "Csv delimiter must be an ascii character, but got '{}'",
+            config.csv_delimiter
+        );
+    }
+    Ok(vna_data_sync::DatasetOpts {
+        format: config.format,
+        field_mapping: config
+            .field_mapping
+            .iter()
+            .map(|(from, to)| (from.clone(), to.clone()))
+            .collect(),
+        csv_delimiter: config.csv_delimiter as u8,
+        default_timezone: default_timezone(config)?,
+    })
+}
+
+/// Timezone the dataset dates are parsed in, the dates are also displayed and bucketed in it
+fn default_timezone(config: &config::DataSyncConfig) -> Result<chrono::FixedOffset> {
+    vna_es::dates::parse_utc_offset(&config.default_timezone)
+        .context("Invalid `data_sync.default_timezone`")
+}
+
+fn cleaning_opts(config: &config::CleaningConfig) -> Result<vna_data_sync::CleaningOpts> {
+    Ok(vna_data_sync::CleaningOpts {
+        decode_html_entities: config.decode_html_entities,
+        normalize_whitespace: config.normalize_whitespace,
+        author_roles: config.author_roles.clone(),
+        category_aliases: config
+            .category_aliases
+            .iter()
+            .map(|(alias, category)| (alias.clone(), category.clone()))
+            .collect(),
+        canonicalize_urls: config.canonicalize_urls,
+        empty_fields: config
+            .empty_fields
+            .iter()
+            .map(|(field, policy)| -> Result<_> {
+                let field: vna_data_sync::ArticleField = field.parse().map_err(|err| {
+                    anyhow!("Invalid `data_sync.cleaning.empty_fields` key: {}", err)
+                })?;
+                Ok((field, policy.clone()))
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+/// Prints the malformed records to stdout and fails if there are any
+fn validate_dataset(path: &Path, config: &config::DataSyncConfig) -> Result<()> {
+    let opts = dataset_opts(config)?;
+    let cleaning = cleaning_opts(&config.cleaning)?;
+
+    eprintln!("Validating dataset {}...", path.display());
+    let stats = vna_data_sync::validate_dataset(path, &opts, &cleaning, |location, err| {
+        println!("{}: {:#}", location, err);
+    })?;
+
+    eprintln!(
+        "valid: {}, dropped: {}, malformed: {}",
+        stats.total_valid, stats.total_dropped, stats.total_malformed
+    );
+    if stats.total_malformed != 0 {
+        bail!("Dataset has {} malformed records", stats.total_malformed);
+    }
+    Ok(())
+}
+
+async fn apply_index_template(
+    elastic: &vna_es_utils::elasticsearch::Elasticsearch,
+    config: &config::IndexConfig,
+) -> Result<()> {
+    match &config.synonyms_path {
+        Some(path) => {
+            let opts = vna_es::IndexTemplateOpts::with_synonyms_file(path)?;
+            vna_es::Article::put_index_template(elastic, &opts).await
+        }
+        // Keep the synonyms applied via `vna index template apply --synonyms`
+        None => vna_es::Article::ensure_index_template(elastic).await,
+    }
+}
+
+fn print_index_version_info(info: &vna_es::IndexVersionInfo) {
+    let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "-".to_owned());
+    println!(
+        "{} {:>6} health: {:<6} docs: {:>10} size: {:>10}{}",
+        info.index_name,
+        format!("(v{})", info.version),
+        or_unknown(info.health.map(|it| it.to_string())),
+        or_unknown(info.doc_count.map(|it| it.to_string())),
+        or_unknown(info.size_in_bytes.map(format_bytes)),
+        if info.is_current {
+            format!(" <- {}", vna_es::Article::INDEX_ALIAS)
+        } else {
+            String::new()
+        },
+    );
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
 fn open_svg_in_google_chrome(path: &Path) -> Result<()> {
     std::process::Command::new("google-chrome")
         .arg(path)
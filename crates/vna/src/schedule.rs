@@ -0,0 +1,244 @@
+//! Minimal cron-like schedule with the standard 5 fields evaluated in UTC:
+//! `minute hour day-of-month month day-of-week`.
+//!
+//! Each field is `*`, a number, a range `a-b` or a comma-separated list of them,
+//! optionally followed by a `/step`. Day of week is `0-7` where both `0` and `7` are Sunday.
+//! As in cron, if both day fields are restricted a day matches if either of them matches.
+
+use anyhow::{bail, Context, Result};
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+/// Each field is stored as a bit set of the allowed values
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(i) => {
+                let step: u32 = part[i + 1..]
+                    .parse()
+                    .with_context(|| format!("Invalid step in '{}'", part))?;
+                if step == 0 {
+                    bail!("Step must be positive in '{}'", part);
+                }
+                (&part[..i], step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else {
+            let parse = |it: &str| -> Result<u32> {
+                it.parse()
+                    .with_context(|| format!("Invalid value '{}' in '{}'", it, part))
+            };
+            match range.find('-') {
+                Some(i) => (parse(&range[..i])?, parse(&range[i + 1..])?),
+                // `a/step` means from `a` to the max value
+                None if step != 1 => (parse(range)?, max),
+                None => {
+                    let value = parse(range)?;
+                    (value, value)
+                }
+            }
+        };
+
+        if start < min || end > max || start > end {
+            bail!(
+                "Range '{}' is out of bounds, expected values from {} to {}",
+                part,
+                min,
+                max
+            );
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(expr: &str) -> Result<Self> {
+        let fields: Vec<_> = expr.split_whitespace().collect();
+        let [minutes, hours, days_of_month, months, days_of_week] = match fields.as_slice() {
+            [a, b, c, d, e] => [*a, *b, *c, *d, *e],
+            _ => bail!(
+                "Schedule '{}' must have 5 fields: minute hour day-of-month month day-of-week",
+                expr
+            ),
+        };
+
+        let mut days_of_week_bits = parse_field(days_of_week, 0, 7)
+            .with_context(|| format!("Invalid day of week in schedule '{}'", expr))?;
+        // Sunday is both 0 and 7
+        if days_of_week_bits & (1 << 7) != 0 {
+            days_of_week_bits |= 1;
+        }
+
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)
+                .with_context(|| format!("Invalid minute in schedule '{}'", expr))?,
+            hours: parse_field(hours, 0, 23)
+                .with_context(|| format!("Invalid hour in schedule '{}'", expr))?,
+            days_of_month: parse_field(days_of_month, 1, 31)
+                .with_context(|| format!("Invalid day of month in schedule '{}'", expr))?,
+            months: parse_field(months, 1, 12)
+                .with_context(|| format!("Invalid month in schedule '{}'", expr))?,
+            days_of_week: days_of_week_bits,
+            any_day_of_month: days_of_month == "*",
+            any_day_of_week: days_of_week == "*",
+        })
+    }
+}
+
+impl Schedule {
+    fn matches_day(&self, day_of_month: u32, day_of_week: u32) -> bool {
+        let dom = self.days_of_month & (1 << day_of_month) != 0;
+        let dow = self.days_of_week & (1 << day_of_week) != 0;
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => dom || dow,
+            _ => dom && dow,
+        }
+    }
+
+    /// Returns the first time strictly after `after` that matches the schedule,
+    /// `None` if it never matches (e.g. `0 0 31 2 *`)
+    pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+        const MINUTE: u64 = 60;
+        const DAY: u64 = 24 * 60 * MINUTE;
+
+        let secs = after.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let mut minutes = secs / MINUTE + 1;
+
+        // Any valid schedule matches within several years (Feb 29 on a given weekday)
+        let limit = minutes + 8 * 366 * DAY / MINUTE;
+
+        while minutes < limit {
+            let days = minutes * MINUTE / DAY;
+            let (_, month, day) = civil_from_days(days);
+            // 1970-01-01 was Thursday
+            let day_of_week = ((days + 4) % 7) as u32;
+
+            if self.months & (1 << month) == 0 || !self.matches_day(day, day_of_week) {
+                minutes = (days + 1) * DAY / MINUTE;
+                continue;
+            }
+
+            let minute_of_day = minutes % (DAY / MINUTE);
+            let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+
+            if self.hours & (1 << hour) == 0 {
+                minutes += 60 - minute;
+                continue;
+            }
+            if self.minutes & (1 << minute) == 0 {
+                minutes += 1;
+                continue;
+            }
+            return Some(UNIX_EPOCH + Duration::from_secs(minutes * MINUTE));
+        }
+        None
+    }
+}
+
+/// Converts the number of days since the unix epoch to (year, month, day),
+/// see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: u64) -> (u64, u32, u32) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn next(expr: &str, after: u64) -> u64 {
+        let schedule: Schedule = expr.parse().unwrap();
+        schedule
+            .next_after(at(after))
+            .unwrap()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    // 2020-06-15T10:30:15Z, Monday
+    const NOW: u64 = 1_592_217_015;
+
+    #[test]
+    fn converts_days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(NOW / 86400), (2020, 6, 15));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn computes_next_fire_time() {
+        // Every minute
+        assert_eq!(next("* * * * *", NOW), 1_592_217_060);
+        // Every 15 minutes
+        assert_eq!(next("*/15 * * * *", NOW), 1_592_217_900);
+        // Daily at 03:00, tomorrow
+        assert_eq!(next("0 3 * * *", NOW), 1_592_276_400);
+        // Sundays at 00:00 (both 0 and 7 mean Sunday)
+        assert_eq!(next("0 0 * * 0", NOW), 1_592_697_600);
+        assert_eq!(next("0 0 * * 7", NOW), 1_592_697_600);
+        // 1st of the month or Friday, whichever comes first
+        assert_eq!(next("0 0 1 * 5", NOW), 1_592_524_800);
+        // Leap day
+        assert_eq!(next("0 12 29 2 *", NOW), 1_709_208_000);
+    }
+
+    #[test]
+    fn parses_lists_ranges_and_steps() {
+        let schedule: Schedule = "0,30 9-17/4 * * 1-5".parse().unwrap();
+        assert_eq!(schedule.minutes, 1 | 1 << 30);
+        assert_eq!(schedule.hours, 1 << 9 | 1 << 13 | 1 << 17);
+        assert_eq!(schedule.days_of_week, 0b11_1110);
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        for expr in &[
+            "* * * *",
+            "60 * * * *",
+            "* 5-2 * * *",
+            "*/0 * * * *",
+            "a * * * *",
+        ] {
+            assert!(expr.parse::<Schedule>().is_err(), "{}", expr);
+        }
+        let never: Schedule = "0 0 31 2 *".parse().unwrap();
+        assert_eq!(never.next_after(at(NOW)), None);
+    }
+}
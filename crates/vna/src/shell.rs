@@ -0,0 +1,446 @@
+//! Interactive REPL that keeps the Elasticsearch client and the current
+//! query/filter context alive between the commands
+
+use anyhow::{bail, Result};
+use chrono::FixedOffset;
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Editor, Helper,
+};
+use std::path::PathBuf;
+use structopt::{clap::AppSettings, StructOpt};
+use vna_es_utils::elasticsearch::Elasticsearch;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "",
+    setting = AppSettings::NoBinaryName,
+    setting = AppSettings::DisableVersion,
+    setting = AppSettings::VersionlessSubcommands,
+)]
+enum ShellCommand {
+    /// Issue a fulltext search thru all the news. The query becomes the current one.
+    /// If no query is specified the current one is used.
+    Search {
+        /// Words to search for in Elasticsearch
+        query: Vec<String>,
+    },
+    /// View various statistics about the news for the current query and filters
+    Stats(StatsKind),
+    /// Show the article with the given id
+    Show {
+        /// Id of the article in Elasticsearch
+        id: String,
+    },
+    /// Set the value of the current context (query, field, category, sentiment, date-from, date-to)
+    Set { key: ContextKey, value: Vec<String> },
+    /// Reset the value of the current context to its default
+    Unset { key: ContextKey },
+    /// Print the current context
+    Context,
+    /// Leave the shell
+    Exit,
+}
+
+#[derive(Debug, StructOpt)]
+enum StatsKind {
+    /// Display the sentiment analysis statistics
+    Sentiment,
+    /// Display the category statistics
+    Category,
+    /// Display the number of news published over time
+    Timeline {
+        /// Size of the date buckets (day|week|month|quarter|year)
+        #[structopt(long, default_value = "month")]
+        interval: vna_es::CalendarInterval,
+    },
+    /// Display significant words statistics for the current query
+    SignificantWords {
+        /// Maximym number of significant words to return
+        #[structopt(long, default_value = "15")]
+        max_words: u32,
+    },
+}
+
+const COMMANDS: &[&str] = &[
+    "search", "stats", "show", "set", "unset", "context", "exit", "help",
+];
+const STATS_KINDS: &[&str] = &["sentiment", "category", "timeline", "significant-words"];
+const CONTEXT_KEYS: &[&str] = &[
+    "query",
+    "field",
+    "category",
+    "sentiment",
+    "date-from",
+    "date-to",
+];
+const FIELD_NAMES: &[&str] = &["headline", "short_description", "authors"];
+const SENTIMENTS: &[&str] = &["positive", "negative"];
+
+#[derive(Debug, Copy, Clone)]
+enum ContextKey {
+    Query,
+    Field,
+    Category,
+    Sentiment,
+    DateFrom,
+    DateTo,
+}
+
+impl std::str::FromStr for ContextKey {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "query" => Self::Query,
+            "field" => Self::Field,
+            "category" => Self::Category,
+            "sentiment" => Self::Sentiment,
+            "date-from" => Self::DateFrom,
+            "date-to" => Self::DateTo,
+            _ => {
+                return Err(format!(
+                    "context key must be one of: {}",
+                    CONTEXT_KEYS.join(", ")
+                ))
+            }
+        })
+    }
+}
+
+/// State that persists between the shell commands
+#[derive(Debug, Default)]
+struct Context {
+    query: Option<stdx::NonHollowString>,
+    field_name: Option<String>,
+    filters: vna_es::ArticleFilters,
+}
+
+impl Context {
+    fn set(&mut self, key: ContextKey, value: String) -> Result<()> {
+        match key {
+            ContextKey::Query => self.query = Some(non_hollow(value)?),
+            ContextKey::Field => self.field_name = Some(value),
+            ContextKey::Category => self.filters.category = Some(value),
+            ContextKey::Sentiment => {
+                self.filters.sentiment_polarity = Some(value.parse().map_err(anyhow::Error::msg)?)
+            }
+            ContextKey::DateFrom => self.filters.date_from = Some(value),
+            ContextKey::DateTo => self.filters.date_to = Some(value),
+        }
+        Ok(())
+    }
+
+    fn unset(&mut self, key: ContextKey) {
+        match key {
+            ContextKey::Query => self.query = None,
+            ContextKey::Field => self.field_name = None,
+            ContextKey::Category => self.filters.category = None,
+            ContextKey::Sentiment => self.filters.sentiment_polarity = None,
+            ContextKey::DateFrom => self.filters.date_from = None,
+            ContextKey::DateTo => self.filters.date_to = None,
+        }
+    }
+
+    fn stats_field_name(&self) -> &str {
+        self.field_name.as_deref().unwrap_or("short_description")
+    }
+
+    fn print(&self) {
+        fn or_none(it: Option<&str>) -> &str {
+            it.unwrap_or("<none>")
+        }
+        println!("query:     {}", or_none(self.query.as_deref()));
+        println!("field:     {}", or_none(self.field_name.as_deref()));
+        println!("category:  {}", or_none(self.filters.category.as_deref()));
+        println!(
+            "sentiment: {}",
+            self.filters
+                .sentiment_polarity
+                .map(|it| format!("{:?}", it))
+                .as_deref()
+                .unwrap_or("<none>")
+        );
+        println!("date-from: {}", or_none(self.filters.date_from.as_deref()));
+        println!("date-to:   {}", or_none(self.filters.date_to.as_deref()));
+    }
+}
+
+/// Runs the interactive shell until the user exits it,
+/// the article dates are displayed and bucketed in `timezone`
+pub async fn run(elastic: &Elasticsearch, timezone: FixedOffset) -> Result<()> {
+    let categories = vna_es::Article::fetch_categories(elastic)
+        .await
+        .unwrap_or_else(|err| {
+            log::warn!("Failed to fetch categories for completion: {:?}", err);
+            vec![]
+        });
+
+    let mut editor = Editor::<ShellHelper>::new();
+    editor.set_helper(Some(ShellHelper { categories }));
+
+    let history_path = history_path();
+    if let Some(path) = &history_path {
+        if let Err(err) = editor.load_history(path) {
+            log::debug!("Shell history could not be loaded: {:?}", err);
+        }
+    }
+
+    eprintln!("Welcome to vna shell, type `help` to list the available commands");
+
+    let mut ctx = Context::default();
+    ctx.filters.time_zone = Some(timezone);
+
+    loop {
+        let line = match editor.readline("vna> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str());
+
+        let words = match split_words(&line) {
+            Ok(it) => it,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        let cmd = match ShellCommand::from_iter_safe(words) {
+            Ok(it) => it,
+            Err(err) => {
+                eprintln!("{}", err.message);
+                continue;
+            }
+        };
+        if let ShellCommand::Exit = cmd {
+            break;
+        }
+        if let Err(err) = run_command(elastic, timezone, &mut ctx, cmd).await {
+            eprintln!("Error: {:?}", err);
+        }
+    }
+
+    if let Some(path) = &history_path {
+        if let Err(err) = editor.save_history(path) {
+            log::warn!("Shell history could not be saved: {:?}", err);
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_command(
+    elastic: &Elasticsearch,
+    timezone: FixedOffset,
+    ctx: &mut Context,
+    cmd: ShellCommand,
+) -> Result<()> {
+    match cmd {
+        ShellCommand::Search { query } => {
+            if !query.is_empty() {
+                ctx.query = Some(non_hollow(query.join(" "))?);
+            }
+            let query = match &ctx.query {
+                Some(it) => it,
+                None => bail!("There is no current query, specify it via `search <query>`"),
+            };
+            let articles = vna_es::Article::fulltext_search(vna_es::FulltextSearchOpts {
+                elastic,
+                query,
+                field_name: ctx.field_name.as_deref(),
+                filters: &ctx.filters,
+            })
+            .await?;
+
+            println!("Found articles {}", articles.len());
+            for vna_es::WithId { id, doc } in articles {
+                println!(
+                    "{}  {}  {:<16} {}",
+                    id,
+                    doc.date.with_timezone(&timezone).format("%Y-%m-%d"),
+                    doc.category,
+                    doc.headline
+                );
+            }
+        }
+        ShellCommand::Stats(kind) => {
+            let opts = vna_es::StatsOpts {
+                elastic,
+                query: &ctx.query,
+                field_name: ctx.stats_field_name(),
+                filters: &ctx.filters,
+            };
+            let stats = match kind {
+                StatsKind::Sentiment => vna_es::Article::sentiment_stats(opts).await?,
+                StatsKind::Category => vna_es::Article::category_stats(opts).await?,
+                StatsKind::Timeline { interval } => {
+                    vna_es::Article::timeline_stats(opts, interval, timezone).await?
+                }
+                StatsKind::SignificantWords { max_words } => {
+                    let query = match &ctx.query {
+                        Some(it) => it,
+                        None => bail!("Significant words require the current query to be set"),
+                    };
+                    let words = vna_es::Article::significant_words(vna_es::SignificantWordsOpts {
+                        elastic,
+                        query,
+                        field_name: ctx.stats_field_name(),
+                        max_words,
+                    })
+                    .await?;
+                    let words = words
+                        .buckets
+                        .into_iter()
+                        .map(|it| (it.key, it.doc_count))
+                        .collect();
+                    vna_es::Stats(words)
+                }
+            };
+            print_stats(&stats);
+        }
+        ShellCommand::Show { id } => match vna_es::Article::fetch_by_id(elastic, &id).await? {
+            Some(article) => println!("{:#?}", article),
+            None => bail!("Article with id '{}' was not found", id),
+        },
+        ShellCommand::Set { key, value } => ctx.set(key, value.join(" "))?,
+        ShellCommand::Unset { key } => ctx.unset(key),
+        ShellCommand::Context => ctx.print(),
+        ShellCommand::Exit => {}
+    }
+    Ok(())
+}
+
+fn print_stats(stats: &vna_es::Stats) {
+    let key_width = stats.0.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, count) in &stats.0 {
+        println!("{:width$}  {}", key, count, width = key_width);
+    }
+}
+
+fn non_hollow(string: String) -> Result<stdx::NonHollowString> {
+    string.parse().map_err(anyhow::Error::msg)
+}
+
+fn history_path() -> Option<PathBuf> {
+    Some(dirs::home_dir()?.join(".vna_history"))
+}
+
+/// Splits the line into words separated by whitespace.
+/// Words may be quoted with `'` or `"` to include whitespace in them.
+fn split_words(line: &str) -> Result<Vec<String>, &'static str> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+
+    for ch in line.chars() {
+        match quote {
+            Some(q) if ch == q => quote = None,
+            Some(_) => word.get_or_insert_with(String::new).push(ch),
+            None if ch == '"' || ch == '\'' => {
+                quote = Some(ch);
+                word.get_or_insert_with(String::new);
+            }
+            None if ch.is_whitespace() => words.extend(word.take()),
+            None => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated quote");
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+struct ShellHelper {
+    categories: Vec<String>,
+}
+
+impl ShellHelper {
+    fn candidates<'a>(&'a self, prev_words: &[&str]) -> Vec<&'a str> {
+        match prev_words {
+            [] => COMMANDS.to_vec(),
+            ["stats"] => STATS_KINDS.to_vec(),
+            ["set"] | ["unset"] => CONTEXT_KEYS.to_vec(),
+            ["set", "field"] => FIELD_NAMES.to_vec(),
+            ["set", "sentiment"] => SENTIMENTS.to_vec(),
+            ["set", "category"] => self.categories.iter().map(String::as_str).collect(),
+            ["stats", "timeline", "--interval"] => vec!["day", "week", "month", "quarter", "year"],
+            _ => vec![],
+        }
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |it| it + 1);
+        let prefix = &line[start..];
+        let prev_words: Vec<_> = line[..start].split_whitespace().collect();
+
+        let candidates = self
+            .candidates(&prev_words)
+            .into_iter()
+            .filter(|it| it.starts_with(prefix))
+            .map(|it| Pair {
+                display: it.to_owned(),
+                replacement: it.to_owned(),
+            })
+            .collect();
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {}
+impl Highlighter for ShellHelper {}
+impl Validator for ShellHelper {}
+impl Helper for ShellHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_words_by_whitespace() {
+        assert_eq!(
+            split_words("  search  foo bar ").unwrap(),
+            ["search", "foo", "bar"]
+        );
+        assert_eq!(split_words("").unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn splits_quoted_words() {
+        assert_eq!(
+            split_words(r#"set category "THE WORLDPOST""#).unwrap(),
+            ["set", "category", "THE WORLDPOST"]
+        );
+        assert_eq!(split_words("set query ''").unwrap(), ["set", "query", ""]);
+        assert!(split_words("set query 'foo").is_err());
+    }
+
+    #[test]
+    fn completes_by_context() {
+        let helper = ShellHelper {
+            categories: vec!["POLITICS".to_owned()],
+        };
+        assert_eq!(helper.candidates(&["stats"]), STATS_KINDS);
+        assert_eq!(helper.candidates(&["set", "category"]), ["POLITICS"]);
+        assert!(helper.candidates(&["search", "foo"]).is_empty());
+    }
+}
@@ -0,0 +1,376 @@
+//! Cleaning of the dataset articles before they are analyzed and ingested.
+//!
+//! The steps are applied in the following order: HTML entity decoding and whitespace
+//! normalisation of all the fields, author normalisation, category alias mapping,
+//! URL canonicalisation and empty field policies.
+
+use crate::data_source::kaggle::Article;
+use itertools::Itertools;
+use serde::Deserialize;
+use std::{borrow::Cow, collections::HashMap, convert::TryFrom, fmt};
+use url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArticleField {
+    Category,
+    Headline,
+    Authors,
+    Link,
+    ShortDescription,
+    Date,
+}
+
+impl std::str::FromStr for ArticleField {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "category" => Ok(Self::Category),
+            "headline" => Ok(Self::Headline),
+            "authors" => Ok(Self::Authors),
+            "link" => Ok(Self::Link),
+            "short_description" => Ok(Self::ShortDescription),
+            "date" => Ok(Self::Date),
+            _ => Err("article field must be one of: \
+                category, headline, authors, link, short_description, date"),
+        }
+    }
+}
+
+/// What to do with the article if the field is empty after cleaning
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum EmptyFieldPolicy {
+    /// Ingest the article with the empty field
+    Keep,
+    /// Don't ingest the article
+    Skip,
+    /// Replace the empty value with the given one, written as `fill:<value>`
+    Fill(String),
+}
+
+impl std::str::FromStr for EmptyFieldPolicy {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "skip" => Ok(Self::Skip),
+            _ if s.starts_with("fill:") => Ok(Self::Fill(s["fill:".len()..].to_owned())),
+            _ => Err("empty field policy must be one of: keep, skip, fill:<value>"),
+        }
+    }
+}
+
+impl TryFrom<String> for EmptyFieldPolicy {
+    type Error = &'static str;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for EmptyFieldPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keep => f.write_str("keep"),
+            Self::Skip => f.write_str("skip"),
+            Self::Fill(value) => write!(f, "fill:{}", value),
+        }
+    }
+}
+
+/// The default value disables all the cleaning steps
+#[derive(Debug, Clone, Default)]
+pub struct CleaningOpts {
+    pub decode_html_entities: bool,
+    /// Trim the values and collapse the runs of whitespace into a single space
+    pub normalize_whitespace: bool,
+    /// Author roles to strip with everything after them, e.g. `Contributor`
+    /// turns "Ron Dicker, ContributorWriter at ..." into "Ron Dicker"
+    pub author_roles: Vec<String>,
+    /// Categories and the canonical categories they are renamed to,
+    /// e.g. `THE WORLDPOST -> WORLDPOST`
+    pub category_aliases: HashMap<String, String>,
+    /// Drop the fragment and the tracking query parameters (`utm_*`, `ncid`, etc.)
+    /// and normalise the case of the scheme and the host
+    pub canonicalize_urls: bool,
+    /// Fields that are not mentioned are kept empty
+    pub empty_fields: HashMap<ArticleField, EmptyFieldPolicy>,
+}
+
+impl CleaningOpts {
+    /// Returns `None` if the article must be skipped according to the empty field policies
+    pub(crate) fn clean(&self, mut article: Article) -> Option<Article> {
+        for field in &mut [
+            &mut article.category,
+            &mut article.headline,
+            &mut article.authors,
+            &mut article.link,
+            &mut article.short_description,
+            &mut article.date,
+        ] {
+            if self.decode_html_entities {
+                let decoded = match decode_html_entities(field) {
+                    Cow::Owned(it) => Some(it),
+                    Cow::Borrowed(_) => None,
+                };
+                if let Some(decoded) = decoded {
+                    **field = decoded;
+                }
+            }
+            if self.normalize_whitespace {
+                **field = field.split_whitespace().join(" ");
+            }
+        }
+
+        if !self.author_roles.is_empty() {
+            article.authors = normalize_authors(&article.authors, &self.author_roles);
+        }
+        if let Some(category) = self.category_aliases.get(&article.category) {
+            article.category = category.clone();
+        }
+        if self.canonicalize_urls {
+            if let Some(link) = canonicalize_url(&article.link) {
+                article.link = link;
+            }
+        }
+
+        for (field, policy) in &self.empty_fields {
+            let value = match field {
+                ArticleField::Category => &mut article.category,
+                ArticleField::Headline => &mut article.headline,
+                ArticleField::Authors => &mut article.authors,
+                ArticleField::Link => &mut article.link,
+                ArticleField::ShortDescription => &mut article.short_description,
+                ArticleField::Date => &mut article.date,
+            };
+            if !value.trim().is_empty() {
+                continue;
+            }
+            match policy {
+                EmptyFieldPolicy::Keep => {}
+                EmptyFieldPolicy::Skip => return None,
+                EmptyFieldPolicy::Fill(fill) => *value = fill.clone(),
+            }
+        }
+
+        Some(article)
+    }
+}
+
+/// Decodes the numeric character references and the most common named entities,
+/// unknown entities are left as is
+fn decode_html_entities(text: &str) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return Cow::Borrowed(text);
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        // The longest supported entity is `&#x10FFFF;`
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&len| len <= 8)
+            .and_then(|len| Some((decode_entity(&rest[1..=len])?, len + 2)));
+
+        match entity {
+            Some((ch, len)) => {
+                decoded.push(ch);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if entity.starts_with("#x") || entity.starts_with("#X") {
+        return std::char::from_u32(u32::from_str_radix(&entity[2..], 16).ok()?);
+    }
+    if entity.starts_with('#') {
+        return std::char::from_u32(entity[1..].parse().ok()?);
+    }
+    Some(match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "hellip" => '…',
+        "eacute" => 'é',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        _ => return None,
+    })
+}
+
+fn normalize_authors(authors: &str, roles: &[String]) -> String {
+    let mut authors = authors.trim();
+    if authors.starts_with("By ") {
+        authors = &authors["By ".len()..];
+    }
+    for role in roles {
+        if let Some(i) = authors.find(&format!(", {}", role)) {
+            authors = &authors[..i];
+        }
+    }
+    authors.trim().trim_end_matches(',').to_owned()
+}
+
+fn is_tracking_param(name: &str) -> bool {
+    name.starts_with("utm_") || matches!(name, "ncid" | "fbclid" | "gclid" | "yclid")
+}
+
+/// Returns `None` if the url is invalid
+fn canonicalize_url(link: &str) -> Option<String> {
+    let mut url = Url::parse(link).ok()?;
+    url.set_fragment(None);
+
+    if url.query_pairs().any(|(name, _)| is_tracking_param(&name)) {
+        let query: Vec<_> = url
+            .query_pairs()
+            .filter(|(name, _)| !is_tracking_param(name))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        if query.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(&query);
+        }
+    }
+    Some(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(category: &str, authors: &str, link: &str, short_description: &str) -> Article {
+        Article {
+            category: category.to_owned(),
+            headline: " Trump&#39;s  &quot;Big&quot;\n Day &amp; Night ".to_owned(),
+            authors: authors.to_owned(),
+            link: link.to_owned(),
+            short_description: short_description.to_owned(),
+            date: "2018-05-26".to_owned(),
+        }
+    }
+
+    fn opts() -> CleaningOpts {
+        CleaningOpts {
+            decode_html_entities: true,
+            normalize_whitespace: true,
+            author_roles: vec!["Contributor".to_owned()],
+            category_aliases: vec![("THE WORLDPOST".to_owned(), "WORLDPOST".to_owned())]
+                .into_iter()
+                .collect(),
+            canonicalize_urls: true,
+            empty_fields: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn decodes_html_entities() {
+        assert_eq!(decode_html_entities("no entities"), "no entities");
+        assert_eq!(
+            decode_html_entities("&lt;b&gt; &#8217;&#x2019; &amp;amp; &unknown; & &#xZZ;"),
+            "<b> ’’ &amp; &unknown; & &#xZZ;"
+        );
+        assert_eq!(decode_html_entities("trailing &amp"), "trailing &amp");
+    }
+
+    #[test]
+    fn normalizes_authors() {
+        let roles = ["Contributor".to_owned()];
+        assert_eq!(normalize_authors("Ron Dicker", &roles), "Ron Dicker");
+        assert_eq!(
+            normalize_authors("Ron Dicker, Contributor", &roles),
+            "Ron Dicker"
+        );
+        assert_eq!(
+            normalize_authors("By Jane Doe, ContributorWriter and mother of two", &roles),
+            "Jane Doe"
+        );
+        assert_eq!(
+            normalize_authors("Jane Doe, John Doe", &roles),
+            "Jane Doe, John Doe"
+        );
+    }
+
+    #[test]
+    fn canonicalizes_urls() {
+        assert_eq!(
+            canonicalize_url(
+                "HTTPS://www.HuffingtonPost.com:443/entry/a_us_5b?ncid=x&utm_source=y#comments"
+            )
+            .as_deref(),
+            Some("https://www.huffingtonpost.com/entry/a_us_5b")
+        );
+        assert_eq!(
+            canonicalize_url("https://example.com/a?id=1&utm_medium=z").as_deref(),
+            Some("https://example.com/a?id=1")
+        );
+        assert_eq!(
+            canonicalize_url("https://example.com/a?q=a%20b").as_deref(),
+            Some("https://example.com/a?q=a%20b")
+        );
+        assert_eq!(canonicalize_url("not a url"), None);
+    }
+
+    #[test]
+    fn cleans_articles() {
+        let cleaned = opts()
+            .clean(article(
+                "THE WORLDPOST",
+                " Ron Dicker, Contributor ",
+                "https://example.com/a#top",
+                "",
+            ))
+            .unwrap();
+        assert_eq!(cleaned.category, "WORLDPOST");
+        assert_eq!(cleaned.headline, "Trump's \"Big\" Day & Night");
+        assert_eq!(cleaned.authors, "Ron Dicker");
+        assert_eq!(cleaned.link, "https://example.com/a");
+        assert_eq!(cleaned.short_description, "");
+
+        let untouched = CleaningOpts::default()
+            .clean(article("THE WORLDPOST", "Ron Dicker, Contributor", "x", ""))
+            .unwrap();
+        assert_eq!(untouched.category, "THE WORLDPOST");
+        assert_eq!(untouched.authors, "Ron Dicker, Contributor");
+    }
+
+    #[test]
+    fn applies_empty_field_policies() {
+        let mut opts = opts();
+        opts.empty_fields
+            .insert(ArticleField::Authors, "fill:Unknown".parse().unwrap());
+        let cleaned = opts.clean(article("CRIME", " ", "x", "")).unwrap();
+        assert_eq!(cleaned.authors, "Unknown");
+
+        opts.empty_fields
+            .insert(ArticleField::ShortDescription, EmptyFieldPolicy::Skip);
+        assert!(opts.clean(article("CRIME", "", "x", "")).is_none());
+        assert!(opts.clean(article("CRIME", "", "x", "Text")).is_some());
+
+        assert!("drop".parse::<EmptyFieldPolicy>().is_err());
+        assert_eq!(
+            EmptyFieldPolicy::Fill("N/A".to_owned()).to_string(),
+            "fill:N/A"
+        );
+    }
+}
@@ -1,13 +1,12 @@
 pub mod kaggle {
+    use crate::dataset::{self, DatasetOpts, DatasetRecords};
     use anyhow::Result;
     use serde::Deserialize;
-    use std::{
-        fs,
-        io::{self, BufRead},
-        path::Path,
-    };
+    use serde_json::Value;
+    use std::path::Path;
 
-    /// Article from Kaggle news dataset
+    /// Article from Kaggle news dataset. Other datasets are mapped to it via
+    /// [`DatasetOpts::field_mapping`].
     #[derive(Deserialize)]
     pub struct Article {
         /// Category article belongs to
@@ -15,21 +14,30 @@ pub mod kaggle {
         /// Headline of the article
         pub headline: String,
         /// Person authored the article
+        #[serde(default)]
         pub authors: String,
         /// Link to the post
         pub link: String,
         /// Short description of the article
+        #[serde(default)]
         pub short_description: String,
         /// Date the article was published
         pub date: String,
     }
 
-    /// Returns an iterator thru all the articles at the specified `dataset_path`
-    /// The file must be in `ndjson` format (i.e. it contains `\n`-delimited json objects).
-    /// Each object in the dataset file must conform to the given `Article`
-    pub fn read_articles(dataset_path: &Path) -> Result<impl Iterator<Item = Article>> {
-        Ok(io::BufReader::new(fs::File::open(dataset_path)?)
-            .lines()
-            .filter_map(|line| serde_json::from_str(&line.ok()?).ok()?))
+    /// Returns an iterator thru all the articles at the specified `dataset_path`.
+    /// See [`dataset`] for the supported formats. Records that don't conform to
+    /// the given `Article` after the field mapping is applied are yielded as errors.
+    pub fn read_articles(
+        dataset_path: &Path,
+        opts: &DatasetOpts,
+    ) -> Result<DatasetRecords<Article>> {
+        Ok(Box::new(dataset::read_records(dataset_path, opts)?.map(
+            |record| {
+                record.map(|record| {
+                    record.and_then(|fields| Ok(serde_json::from_value(Value::Object(fields))?))
+                })
+            },
+        )))
     }
 }
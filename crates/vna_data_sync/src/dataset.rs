@@ -0,0 +1,509 @@
+//! Reading of the news datasets in different formats and compressions.
+//!
+//! The compression is detected by the file extension (`.gz`, `.zst` or `.zip`),
+//! the format is specified explicitly or detected by the extension of the
+//! decompressed file. JSON files whose first non-whitespace character is `[`
+//! are read as JSON arrays, other JSON files are read as newline-delimited JSON.
+//!
+//! Malformed records are yielded as errors with their location in the dataset,
+//! errors after which the dataset can't be read further end the iteration.
+
+use anyhow::{bail, Context, Result};
+use chrono::FixedOffset;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read},
+    path::Path,
+};
+
+pub type Record = Map<String, Value>;
+
+/// Location of the record in the dataset file used in error messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordLocation {
+    /// 1-based line number in the decompressed file
+    Line(u64),
+    /// 1-based index of the element of the JSON array
+    Element(u64),
+}
+
+impl fmt::Display for RecordLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Line(line) => write!(f, "line {}", line),
+            Self::Element(index) => write!(f, "element #{}", index),
+        }
+    }
+}
+
+/// Record of the dataset or the error of parsing it
+#[derive(Debug)]
+pub struct DatasetRecord<T> {
+    pub location: RecordLocation,
+    pub value: Result<T>,
+}
+
+impl<T> DatasetRecord<T> {
+    pub fn and_then<U>(self, f: impl FnOnce(T) -> Result<U>) -> DatasetRecord<U> {
+        DatasetRecord {
+            location: self.location,
+            value: self.value.and_then(f),
+        }
+    }
+}
+
+pub type DatasetRecords<T> = Box<dyn Iterator<Item = Result<DatasetRecord<T>>> + Send>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatasetFormat {
+    /// Newline-delimited JSON objects
+    Ndjson,
+    /// JSON array of objects
+    Json,
+    /// CSV with the header row
+    Csv,
+}
+
+impl std::str::FromStr for DatasetFormat {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" => Ok(Self::Ndjson),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err("dataset format must be one of: ndjson, json, csv"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Zip,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatasetOpts {
+    /// Format of the records, detected automatically if `None`
+    pub format: Option<DatasetFormat>,
+    /// Names of the dataset fields and the names of the article fields they are renamed to,
+    /// e.g. `title -> headline`. Fields that are not mentioned keep their names.
+    pub field_mapping: HashMap<String, String>,
+    pub csv_delimiter: u8,
+    /// Timezone of the dates without an explicit offset
+    pub default_timezone: FixedOffset,
+}
+
+impl Default for DatasetOpts {
+    fn default() -> Self {
+        Self {
+            format: None,
+            field_mapping: HashMap::new(),
+            csv_delimiter: b',',
+            default_timezone: FixedOffset::east(0),
+        }
+    }
+}
+
+/// Splits the file name into the name without the compression extension and the compression
+fn detect_compression(file_name: &str) -> (&str, Compression) {
+    for (ext, compression) in &[
+        (".gz", Compression::Gzip),
+        (".zst", Compression::Zstd),
+        (".zip", Compression::Zip),
+    ] {
+        if file_name.ends_with(ext) {
+            return (&file_name[..file_name.len() - ext.len()], *compression);
+        }
+    }
+    (file_name, Compression::None)
+}
+
+/// Returns `None` for `.json` files since they may be both JSON arrays and ndjson
+fn detect_format(file_name: &str) -> Option<DatasetFormat> {
+    let ext = &file_name[file_name.rfind('.')? + 1..];
+    match ext {
+        "ndjson" | "jsonl" => Some(DatasetFormat::Ndjson),
+        "csv" => Some(DatasetFormat::Csv),
+        _ => None,
+    }
+}
+
+/// Opens the dataset file decompressing it if needed. Returns the reader and the name
+/// of the decompressed file that is used to detect the format.
+fn open_decompressed(path: &Path) -> Result<(Box<dyn BufRead + Send>, String)> {
+    let file_name = path
+        .file_name()
+        .and_then(|it| it.to_str())
+        .with_context(|| format!("Invalid dataset file name {}", path.display()))?;
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    let (inner_name, compression) = detect_compression(file_name);
+
+    let reader: Box<dyn BufRead + Send> = match compression {
+        Compression::None => Box::new(BufReader::new(file)),
+        Compression::Gzip => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+        Compression::Zip => {
+            // The entry borrows the archive, so it is decompressed into memory
+            let mut archive = zip::ZipArchive::new(file)
+                .with_context(|| format!("Failed to read zip archive {}", path.display()))?;
+            let mut entries = (0..archive.len()).filter(|&i| {
+                archive.by_index(i).map_or(false, |it| {
+                    !it.is_dir() && !it.name().starts_with("__MACOSX")
+                })
+            });
+            let index = match (entries.next(), entries.next()) {
+                (Some(index), None) => index,
+                (None, _) => bail!("Zip archive {} is empty", path.display()),
+                (Some(_), Some(_)) => bail!(
+                    "Zip archive {} must contain exactly one dataset file",
+                    path.display()
+                ),
+            };
+            let mut entry = archive.by_index(index)?;
+            let mut content = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut content)?;
+            let name = entry.name().to_owned();
+            return Ok((Box::new(Cursor::new(content)), name));
+        }
+    };
+    Ok((reader, inner_name.to_owned()))
+}
+
+/// Returns an iterator thru all the records of the dataset with the field mapping applied.
+/// The item is an error if the dataset can't be read further, e.g. because of
+/// a JSON syntax error or a corrupted archive, it is the last item then.
+pub fn read_records(path: &Path, opts: &DatasetOpts) -> Result<DatasetRecords<Record>> {
+    let (mut reader, name) = open_decompressed(path)?;
+
+    let format = match opts.format.or_else(|| detect_format(&name)) {
+        Some(it) => it,
+        None => {
+            if first_non_whitespace(&mut reader)? == Some(b'[') {
+                DatasetFormat::Json
+            } else {
+                DatasetFormat::Ndjson
+            }
+        }
+    };
+
+    let records: DatasetRecords<Record> = match format {
+        DatasetFormat::Ndjson => Box::new(
+            reader
+                .lines()
+                .zip(1..)
+                .filter(|(line, _)| line.as_ref().map_or(true, |it| !it.trim().is_empty()))
+                .map(|(line, line_no)| -> Result<DatasetRecord<Record>> {
+                    let line = line.with_context(|| format!("Failed to read line {}", line_no))?;
+                    Ok(DatasetRecord {
+                        location: RecordLocation::Line(line_no),
+                        value: serde_json::from_str(&line).map_err(Into::into),
+                    })
+                }),
+        ),
+        DatasetFormat::Json => Box::new(JsonArrayRecords::new(reader)),
+        DatasetFormat::Csv => {
+            let mut csv = csv::ReaderBuilder::new()
+                .delimiter(opts.csv_delimiter)
+                .from_reader(reader);
+            let headers = csv.headers()?.clone();
+            Box::new(csv.into_records().zip(2..).map(
+                move |(record, line_no)| -> Result<DatasetRecord<Record>> {
+                    let record = match record {
+                        Ok(it) => it,
+                        Err(err) if err.is_io_error() => return Err(err.into()),
+                        Err(err) => {
+                            let line = err.position().map_or(line_no, |it| it.line());
+                            return Ok(DatasetRecord {
+                                location: RecordLocation::Line(line),
+                                value: Err(err.into()),
+                            });
+                        }
+                    };
+                    Ok(DatasetRecord {
+                        // Quoted values may span multiple lines
+                        location: RecordLocation::Line(
+                            record.position().map_or(line_no, |it| it.line()),
+                        ),
+                        value: Ok(headers
+                            .iter()
+                            .zip(record.iter())
+                            .map(|(header, value)| {
+                                (header.to_owned(), Value::String(value.to_owned()))
+                            })
+                            .collect()),
+                    })
+                },
+            ))
+        }
+    };
+
+    let field_mapping = opts.field_mapping.clone();
+    let mut failed = false;
+    Ok(Box::new(
+        records
+            // Stop after the first fatal error
+            .take_while(move |record| !std::mem::replace(&mut failed, record.is_err()))
+            .map(move |record| {
+                record.map(|record| {
+                    record.and_then(|fields| Ok(apply_field_mapping(fields, &field_mapping)))
+                })
+            }),
+    ))
+}
+
+fn apply_field_mapping(record: Record, field_mapping: &HashMap<String, String>) -> Record {
+    if field_mapping.is_empty() {
+        return record;
+    }
+    record
+        .into_iter()
+        .map(|(key, value)| match field_mapping.get(&key) {
+            Some(mapped) => (mapped.clone(), value),
+            None => (key, value),
+        })
+        .collect()
+}
+
+/// Skips the leading whitespace and returns the next byte without consuming it
+fn first_non_whitespace(reader: &mut impl BufRead) -> io::Result<Option<u8>> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        match buf.iter().position(|it| !it.is_ascii_whitespace()) {
+            Some(i) => {
+                let byte = buf[i];
+                reader.consume(i);
+                return Ok(Some(byte));
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// Streams the objects of the top-level JSON array without reading the whole array into memory
+struct JsonArrayRecords<R> {
+    reader: R,
+    state: JsonArrayState,
+    /// Number of the elements read so far
+    index: u64,
+}
+
+#[derive(PartialEq)]
+enum JsonArrayState {
+    /// Before `[`
+    Start,
+    /// After `[` or `,`
+    Element,
+    /// After an element
+    Separator,
+    Done,
+}
+
+impl<R: BufRead> JsonArrayRecords<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            state: JsonArrayState::Start,
+            index: 0,
+        }
+    }
+
+    fn expect(&mut self, expected: &[u8]) -> Result<u8> {
+        match first_non_whitespace(&mut self.reader)? {
+            Some(byte) if expected.contains(&byte) => {
+                self.reader.consume(1);
+                Ok(byte)
+            }
+            Some(byte) => bail!(
+                "Expected one of `{}` in JSON array, but got `{}`",
+                String::from_utf8_lossy(expected),
+                byte as char
+            ),
+            None => bail!("Unexpected end of JSON array"),
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<DatasetRecord<Record>>> {
+        loop {
+            match self.state {
+                JsonArrayState::Done => return Ok(None),
+                JsonArrayState::Start => {
+                    self.expect(b"[")?;
+                    self.state = JsonArrayState::Element;
+                    if first_non_whitespace(&mut self.reader)? == Some(b']') {
+                        self.reader.consume(1);
+                        self.state = JsonArrayState::Done;
+                    }
+                }
+                JsonArrayState::Element => {
+                    self.index += 1;
+                    let index = self.index;
+                    // Other values can't be skipped reliably, e.g. the deserializer
+                    // consumes the byte after a number to find its end
+                    if first_non_whitespace(&mut self.reader)? != Some(b'{') {
+                        bail!("Expected an object at element #{} of the array", index);
+                    }
+                    // The deserializer stops right after the closing `}` of the object
+                    let mut de = serde_json::Deserializer::from_reader(&mut self.reader);
+                    let record = Record::deserialize(&mut de).with_context(|| {
+                        format!("Invalid JSON at element #{} of the array", index)
+                    })?;
+                    self.state = JsonArrayState::Separator;
+                    return Ok(Some(DatasetRecord {
+                        location: RecordLocation::Element(index),
+                        value: Ok(record),
+                    }));
+                }
+                JsonArrayState::Separator => {
+                    self.state = match self.expect(b",]")? {
+                        b',' => JsonArrayState::Element,
+                        _ => JsonArrayState::Done,
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for JsonArrayRecords<R> {
+    type Item = Result<DatasetRecord<Record>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_record() {
+            Ok(record) => record.map(Ok),
+            Err(err) => {
+                // The position in the stream is unknown after a syntax error
+                self.state = JsonArrayState::Done;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn collect(records: impl Iterator<Item = Result<DatasetRecord<Record>>>) -> Vec<Value> {
+        records
+            .map(|it| Value::Object(it.unwrap().value.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn detects_compression_and_format() {
+        assert_eq!(
+            detect_compression("news.csv.gz"),
+            ("news.csv", Compression::Gzip)
+        );
+        assert_eq!(
+            detect_compression("news.json.zst"),
+            ("news.json", Compression::Zstd)
+        );
+        assert_eq!(detect_compression("news.zip"), ("news", Compression::Zip));
+        assert_eq!(
+            detect_compression("news.json"),
+            ("news.json", Compression::None)
+        );
+
+        assert_eq!(detect_format("news.csv"), Some(DatasetFormat::Csv));
+        assert_eq!(detect_format("news.jsonl"), Some(DatasetFormat::Ndjson));
+        assert_eq!(detect_format("news.json"), None);
+    }
+
+    #[test]
+    fn streams_json_array() {
+        let input = r#" [ {"a": 1}, {"b": "]"} ,{"c": [1, 2]}] "#;
+        let records = JsonArrayRecords::new(input.as_bytes());
+        assert_eq!(
+            collect(records),
+            [json!({"a": 1}), json!({"b": "]"}), json!({"c": [1, 2]})]
+        );
+
+        assert!(collect(JsonArrayRecords::new(&b"[]"[..])).is_empty());
+
+        let mut records = JsonArrayRecords::new(&br#"[{"a": 1} {"b": 2}]"#[..]);
+        assert!(records.next().unwrap().is_ok());
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+
+        let mut records = JsonArrayRecords::new(&br#"[{"a": 1}, 2, {"b": 2}]"#[..]);
+        let record = records.next().unwrap().unwrap();
+        assert_eq!(record.location, RecordLocation::Element(1));
+        assert!(record.value.is_ok());
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn reports_locations_of_malformed_records() {
+        let dir = std::env::temp_dir().join(format!("vna_dataset_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let read = |name: &str, content: &str| -> Vec<(RecordLocation, bool)> {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            read_records(&path, &DatasetOpts::default())
+                .unwrap()
+                .map(|it| {
+                    let it = it.unwrap();
+                    (it.location, it.value.is_ok())
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            read("news.ndjson", "{\"a\": 1}\n\nnot json\n{\"a\": 2}\n"),
+            [
+                (RecordLocation::Line(1), true),
+                (RecordLocation::Line(3), false),
+                (RecordLocation::Line(4), true),
+            ]
+        );
+        assert_eq!(
+            read("news.csv", "a,b\n1,2\n3\n\"4\n\",5\n"),
+            [
+                (RecordLocation::Line(2), true),
+                (RecordLocation::Line(3), false),
+                (RecordLocation::Line(4), true),
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn applies_field_mapping() {
+        let record = json!({ "title": "Headline", "url": "https://example.com", "date": "2020" });
+        let mapping: HashMap<_, _> = vec![
+            ("title".to_owned(), "headline".to_owned()),
+            ("url".to_owned(), "link".to_owned()),
+        ]
+        .into_iter()
+        .collect();
+
+        let record = match record {
+            Value::Object(it) => apply_field_mapping(it, &mapping),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            Value::Object(record),
+            json!({ "headline": "Headline", "link": "https://example.com", "date": "2020" })
+        );
+    }
+}
@@ -1,25 +1,63 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
 use elasticsearch::{params::Refresh, Elasticsearch};
 use itertools::Itertools;
 use std::{iter, num::NonZeroU32, path::Path, time::Duration};
+use vna_es_utils::es_types;
 
+pub mod cleaning;
 mod data_source;
+pub mod dataset;
+
+use dataset::DatasetRecord;
+
+pub use cleaning::{ArticleField, CleaningOpts, EmptyFieldPolicy};
+pub use dataset::{DatasetFormat, DatasetOpts, RecordLocation};
 
 pub struct RunOpts<'a> {
     pub elastic: &'a Elasticsearch,
     pub kaggle_dataset_path: &'a Path,
+    /// Format and field mapping of the dataset at `kaggle_dataset_path`
+    pub dataset: &'a DatasetOpts,
+    /// Abort on the first malformed record instead of skipping it
+    pub strict: bool,
+    /// Applied to each article before it is analyzed
+    pub cleaning: &'a CleaningOpts,
     pub scrape_interval: Option<u32>,
     pub max_news: u64,
     pub n_shards: NonZeroU32,
     pub n_replicas: u32,
     pub ingest_batch: NonZeroU32,
-    pub leave_old_index: bool,
+    /// Number of the previous index versions to keep to be able to roll back to them
+    pub keep_versions: u32,
+    /// Take a snapshot of the new index after each successful sync
+    pub snapshot: Option<SnapshotOpts<'a>>,
+}
+
+pub struct SnapshotOpts<'a> {
+    pub repo: &'a stdx::NonHollowString,
+    /// Applied to the data sync snapshots after the new one is taken, its prefix
+    /// is the prefix of their names (the current time is appended to it)
+    pub retention: vna_es::snapshots::RetentionPolicy<'a>,
 }
 
 #[derive(Default)]
 pub struct Stats {
     pub total_indexed: u64,
+    /// Number of malformed dataset records that were skipped
+    pub total_skipped: u64,
+    /// Number of articles that were skipped because of the empty field policies
+    pub total_dropped: u64,
     pub new_index_name: String,
+    /// Old index versions that were deleted after the alias swap
+    pub pruned_versions: Vec<vna_es::IndexVersion>,
+    /// Name of the snapshot of the new index
+    pub snapshot: Option<String>,
+    /// Expired snapshots that were deleted after the new one was taken
+    pub pruned_snapshots: Vec<String>,
+    /// Failures of the snapshot and pruning steps. They don't fail the sync,
+    /// since the alias already points to the new index at that point.
+    pub warnings: Vec<String>,
 }
 
 pub async fn run(
@@ -29,9 +67,13 @@ pub async fn run(
         n_replicas,
         n_shards,
         kaggle_dataset_path,
+        dataset,
+        strict,
+        cleaning,
         scrape_interval,
         ingest_batch,
-        leave_old_index,
+        keep_versions,
+        snapshot,
     }: RunOpts<'_>,
 ) -> Result<Stats> {
     let n_cpus = num_cpus::get();
@@ -39,10 +81,7 @@ pub async fn run(
     let scrape = || async {
         let _t = stdx::debug_time_it("Scraping datasources");
 
-        let prev_index_version = vna_es::Article::fetch_index_version(elastic).await?;
-        let new_index_version = prev_index_version
-            .map(vna_es::IndexVersion::incremented)
-            .unwrap_or_default();
+        let new_index_version = vna_es::Article::next_index_version(elastic).await?;
 
         vna_es::Article::create_index(&vna_es::CreateArticlesIndexOpts {
             elastic,
@@ -52,72 +91,177 @@ pub async fn run(
         })
         .await?;
 
-        let mut stats = Stats {
-            total_indexed: 0,
-            new_index_name: new_index_version.attach_to_alias(vna_es::Article::INDEX_ALIAS),
-        };
-        let articles = data_source::kaggle::read_articles(kaggle_dataset_path)?
-            .chunks(ingest_batch.get() as usize);
-
-        for batch in &articles {
-            let _ti = stdx::debug_time_it("Ingesting a batch");
-
-            let bulk_body: Vec<_> = {
-                let _ta = stdx::debug_time_it("Analyzing batch");
-                let max_take = (max_news - stats.total_indexed) as usize;
-
-                let per_thread_chunks = batch
-                    .take(max_take)
-                    .chunks(ingest_batch.get() as usize / n_cpus);
-
-                let tasks = per_thread_chunks.into_iter().map(|per_thread_batch| {
-                    let docs: Vec<_> = per_thread_batch.collect();
-
-                    tokio::task::spawn_blocking(move || {
-                        docs.into_iter()
-                            .map(kaggle_article_to_es_document)
-                            .collect::<Vec<_>>()
-                    })
-                });
-
-                futures::future::join_all(tasks)
-                    .await
-                    .into_iter()
-                    .map(Result::unwrap)
-                    .flatten()
-                    .map(|doc| {
-                        let header = "{\"index\":{}}".to_owned();
-                        let doc = serde_json::to_string(&doc).unwrap();
-                        iter::once(header).chain(iter::once(doc))
-                    })
-                    .flatten()
-                    .collect()
+        let new_index_name = new_index_version.attach_to_alias(vna_es::Article::INDEX_ALIAS);
+        let ingested_at = Utc::now();
+
+        let ingestion = async {
+            let mut stats = Stats {
+                total_indexed: 0,
+                total_skipped: 0,
+                total_dropped: 0,
+                new_index_name: new_index_name.clone(),
+                pruned_versions: vec![],
+                snapshot: None,
+                pruned_snapshots: vec![],
+                warnings: vec![],
             };
+            let mut articles = data_source::kaggle::read_articles(kaggle_dataset_path, dataset)?;
+
+            loop {
+                let batch = next_batch(NextBatchOpts {
+                    articles: &mut articles,
+                    size: ingest_batch.get() as usize,
+                    strict,
+                    cleaning,
+                    default_timezone: dataset.default_timezone,
+                    stats: &mut stats,
+                })?;
+                if batch.is_empty() {
+                    break;
+                }
+
+                let _ti = stdx::debug_time_it("Ingesting a batch");
+
+                let bulk_body: Vec<_> = {
+                    let _ta = stdx::debug_time_it("Analyzing batch");
+                    let max_take = (max_news - stats.total_indexed) as usize;
+
+                    let per_thread_chunks = batch
+                        .into_iter()
+                        .take(max_take)
+                        .chunks(ingest_batch.get() as usize / n_cpus);
+
+                    let tasks = per_thread_chunks.into_iter().map(|per_thread_batch| {
+                        let docs: Vec<_> = per_thread_batch.collect();
+
+                        tokio::task::spawn_blocking(move || {
+                            docs.into_iter()
+                                .map(|(article, date)| {
+                                    kaggle_article_to_es_document(article, date, ingested_at)
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    });
+
+                    futures::future::join_all(tasks)
+                        .await
+                        .into_iter()
+                        .map(Result::unwrap)
+                        .flatten()
+                        .map(|doc| {
+                            let header = "{\"index\":{}}".to_owned();
+                            let doc = serde_json::to_string(&doc).unwrap();
+                            iter::once(header).chain(iter::once(doc))
+                        })
+                        .flatten()
+                        .collect()
+                };
+
+                let n_bulk_docs = bulk_body.len() / 2;
+
+                log::debug!("Ingesting {} documents", n_bulk_docs);
 
-            let n_bulk_docs = bulk_body.len() / 2;
+                if !bulk_body.is_empty() {
+                    let response = elastic
+                        .bulk(elasticsearch::BulkParts::Index(&stats.new_index_name))
+                        .body(bulk_body)
+                        .refresh(Refresh::WaitFor)
+                        .send()
+                        .await?;
 
-            log::debug!("Ingesting {} documents", n_bulk_docs);
+                    let response: es_types::BulkResponse =
+                        vna_es_utils::check_status(response).await?.json().await?;
 
-            stats.total_indexed += n_bulk_docs as u64; // FIXME: be more pessimistic (check response)
+                    // A partially ingested index must not replace the previous one
+                    if let Some((item, error)) = response.failed_items().next() {
+                        bail!(
+                            "Failed to index {} of {} articles, the error for '{}': {}",
+                            response.failed_items().count(),
+                            n_bulk_docs,
+                            item._id.as_deref().unwrap_or_default(),
+                            error,
+                        );
+                    }
+                    stats.total_indexed += n_bulk_docs as u64;
+                }
 
-            if !bulk_body.is_empty() {
-                elastic
-                    .bulk(elasticsearch::BulkParts::Index(&stats.new_index_name))
-                    .body(bulk_body)
-                    .refresh(Refresh::WaitFor)
-                    .send()
-                    .await?;
+                if stats.total_indexed >= max_news {
+                    break;
+                }
             }
 
-            if stats.total_indexed >= max_news {
-                break;
+            vna_es::Article::validate_index(vna_es::ValidateIndexOpts {
+                elastic,
+                version: new_index_version,
+                expected_docs: stats.total_indexed,
+            })
+            .await?;
+
+            Ok::<_, anyhow::Error>(stats)
+        };
+
+        let mut stats = match ingestion.await {
+            Ok(it) => it,
+            Err(err) => {
+                // Don't leave the partial index behind, the alias still points to the previous one
+                if let Err(delete_err) =
+                    vna_es::Article::delete_index(elastic, new_index_version).await
+                {
+                    log::error!(
+                        "Failed to delete invalid index '{}': {:?}",
+                        new_index_name,
+                        delete_err
+                    );
+                }
+                return Err(err.context(format!(
+                    "Index '{}' was discarded, '{}' alias was left intact",
+                    new_index_name,
+                    vna_es::Article::INDEX_ALIAS
+                )));
             }
-        }
+        };
+
+        vna_es::Article::update_index_alias(elastic, new_index_version).await?;
 
-        vna_es::Article::update_index_alias(elastic, prev_index_version, new_index_version).await?;
+        // The snapshot is taken before the old versions are deleted, so if it fails
+        // they are still there to roll back to
+        let snapshot_taken = match &snapshot {
+            None => true,
+            Some(snapshot) => match snapshot_index(elastic, snapshot, &new_index_name).await {
+                Ok(name) => {
+                    stats.snapshot = Some(name);
+                    let retention = &snapshot.retention;
+                    match vna_es::snapshots::prune_snapshots(elastic, snapshot.repo, retention)
+                        .await
+                    {
+                        Ok(pruned) => stats.pruned_snapshots = pruned,
+                        Err(err) => warn(
+                            &mut stats,
+                            err.context("Failed to prune the expired snapshots"),
+                        ),
+                    }
+                    true
+                }
+                Err(err) => {
+                    warn(&mut stats, err);
+                    false
+                }
+            },
+        };
 
-        if let (false, Some(version)) = (leave_old_index, prev_index_version) {
-            vna_es::Article::delete_index(elastic, version).await?;
+        if snapshot_taken {
+            match vna_es::Article::prune_index_versions(elastic, keep_versions).await {
+                Ok(pruned) => stats.pruned_versions = pruned,
+                Err(err) => warn(
+                    &mut stats,
+                    err.context("Failed to prune the old index versions"),
+                ),
+            }
+        } else {
+            warn(
+                &mut stats,
+                anyhow!("The old index versions were kept since the new index has no snapshot"),
+            );
         }
 
         Ok(stats)
@@ -137,7 +281,160 @@ pub async fn run(
     }
 }
 
-fn kaggle_article_to_es_document(article: data_source::kaggle::Article) -> vna_es::Article {
+/// Takes the snapshot of the index, returns its name
+async fn snapshot_index(
+    elastic: &Elasticsearch,
+    opts: &SnapshotOpts<'_>,
+    index_name: &str,
+) -> Result<String> {
+    let name = vna_es::snapshots::timestamped_snapshot_name(opts.retention.prefix);
+    let info = vna_es::snapshots::take_snapshot(elastic, opts.repo, &name, &[index_name])
+        .await
+        .with_context(|| format!("Failed to take a snapshot of index '{}'", index_name))?;
+    if info.state != "SUCCESS" {
+        bail!(
+            "Snapshot '{}' of index '{}' has finished in {} state",
+            info.snapshot,
+            index_name,
+            info.state
+        );
+    }
+    Ok(info.snapshot)
+}
+
+/// Logs and records the failure of the step that must not fail the sync
+fn warn(stats: &mut Stats, err: anyhow::Error) {
+    log::warn!("{:#}", err);
+    stats.warnings.push(format!("{:#}", err));
+}
+
+struct NextBatchOpts<'a, I> {
+    articles: &'a mut I,
+    size: usize,
+    strict: bool,
+    cleaning: &'a CleaningOpts,
+    default_timezone: chrono::FixedOffset,
+    stats: &'a mut Stats,
+}
+
+/// Collects up to `size` cleaned articles with their parsed dates. Malformed records
+/// and the records with invalid dates are logged and skipped unless `strict`,
+/// articles are dropped according to the empty field policies.
+fn next_batch<I>(
+    NextBatchOpts {
+        articles,
+        size,
+        strict,
+        cleaning,
+        default_timezone,
+        stats,
+    }: NextBatchOpts<'_, I>,
+) -> Result<Vec<(data_source::kaggle::Article, DateTime<Utc>)>>
+where
+    I: Iterator<Item = Result<DatasetRecord<data_source::kaggle::Article>>>,
+{
+    let mut batch = Vec::with_capacity(size);
+    for record in articles {
+        let record = record?;
+        let article = match record.value {
+            Ok(it) => it,
+            Err(err) => {
+                skip_malformed(record.location, err, strict, stats)?;
+                continue;
+            }
+        };
+        match clean_and_parse_date(article, cleaning, default_timezone) {
+            Ok(Some(it)) => batch.push(it),
+            Ok(None) => {
+                log::debug!("Dropping empty article at {}", record.location);
+                stats.total_dropped += 1;
+                continue;
+            }
+            Err(err) => {
+                skip_malformed(record.location, err, strict, stats)?;
+                continue;
+            }
+        }
+        if batch.len() == size {
+            break;
+        }
+    }
+    Ok(batch)
+}
+
+/// Cleans the article and parses its date after that, so that the cleaned date is parsed.
+/// Returns `None` if the article is dropped according to the empty field policies.
+fn clean_and_parse_date(
+    article: data_source::kaggle::Article,
+    cleaning: &CleaningOpts,
+    default_timezone: chrono::FixedOffset,
+) -> Result<Option<(data_source::kaggle::Article, DateTime<Utc>)>> {
+    let article = match cleaning.clean(article) {
+        Some(it) => it,
+        None => return Ok(None),
+    };
+    let date = vna_es::dates::parse_date(&article.date, default_timezone)?;
+    Ok(Some((article, date)))
+}
+
+/// Logs and counts the malformed record, fails instead in strict mode
+fn skip_malformed(
+    location: RecordLocation,
+    err: anyhow::Error,
+    strict: bool,
+    stats: &mut Stats,
+) -> Result<()> {
+    if strict {
+        return Err(err.context(format!(
+            "Malformed record at {}, aborting because of strict mode",
+            location
+        )));
+    }
+    log::warn!("Skipping malformed record at {}: {:#}", location, err);
+    stats.total_skipped += 1;
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationStats {
+    pub total_valid: u64,
+    pub total_malformed: u64,
+    /// Articles that would be dropped according to the empty field policies
+    pub total_dropped: u64,
+}
+
+/// Reads all the records of the dataset, cleans them and parses their dates the same way
+/// `data-sync` does. Reports the malformed records and the ones with invalid dates
+/// via `on_malformed`. Returns an error if the dataset can't be read till the end.
+pub fn validate_dataset(
+    dataset_path: &Path,
+    opts: &DatasetOpts,
+    cleaning: &CleaningOpts,
+    mut on_malformed: impl FnMut(RecordLocation, anyhow::Error),
+) -> Result<ValidationStats> {
+    let mut stats = ValidationStats::default();
+    for record in data_source::kaggle::read_articles(dataset_path, opts)? {
+        let record = record?;
+        let article = record
+            .value
+            .and_then(|article| clean_and_parse_date(article, cleaning, opts.default_timezone));
+        match article {
+            Ok(Some(_)) => stats.total_valid += 1,
+            Ok(None) => stats.total_dropped += 1,
+            Err(err) => {
+                stats.total_malformed += 1;
+                on_malformed(record.location, err);
+            }
+        }
+    }
+    Ok(stats)
+}
+
+fn kaggle_article_to_es_document(
+    article: data_source::kaggle::Article,
+    date: DateTime<Utc>,
+    ingested_at: DateTime<Utc>,
+) -> vna_es::Article {
     let sent = sentiment::analyze(article.short_description.clone());
 
     let (score, polarity) = if sent.negative.score > sent.positive.score {
@@ -152,8 +449,64 @@ fn kaggle_article_to_es_document(article: data_source::kaggle::Article) -> vna_e
         authors: article.authors,
         link: article.link,
         short_description: article.short_description,
-        date: article.date,
+        date,
+        ingested_at: Some(ingested_at),
         sentiment_score: score,
         sentiment_polarity: polarity,
+        duplicate_of: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn validation_cleans_articles_before_parsing_dates() {
+        let path =
+            std::env::temp_dir().join(format!("vna_validate_test_{}.ndjson", std::process::id()));
+        let article = |headline: &str, date: &str| {
+            json!({
+                "category": "CRIME",
+                "headline": headline,
+                "authors": "Ron Dicker",
+                "link": "https://example.com",
+                "short_description": "Description",
+                "date": date,
+            })
+            .to_string()
+        };
+        let records = [
+            article("Valid", "2018-05-26"),
+            article("Filled date", " "),
+            article(" ", "2018-05-26"),
+            article("Invalid date", "yesterday"),
+        ];
+        std::fs::write(&path, records.join("\n")).unwrap();
+
+        let cleaning = CleaningOpts {
+            empty_fields: vec![
+                (
+                    ArticleField::Date,
+                    EmptyFieldPolicy::Fill("2018-01-01".to_owned()),
+                ),
+                (ArticleField::Headline, EmptyFieldPolicy::Skip),
+            ]
+            .into_iter()
+            .collect(),
+            ..CleaningOpts::default()
+        };
+        let mut malformed = vec![];
+        let stats = validate_dataset(&path, &DatasetOpts::default(), &cleaning, |location, _| {
+            malformed.push(location.to_string())
+        });
+        std::fs::remove_file(&path).unwrap();
+        let stats = stats.unwrap();
+
+        assert_eq!(stats.total_valid, 2);
+        assert_eq!(stats.total_dropped, 1);
+        assert_eq!(stats.total_malformed, 1);
+        assert_eq!(malformed.len(), 1);
     }
 }
@@ -0,0 +1,103 @@
+use http::{Method, StatusCode};
+use serde_json::json;
+use std::{
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use vna_data_sync::{CleaningOpts, DatasetOpts, RunOpts};
+use vna_test_utils::mock_elasticsearch;
+
+#[tokio::test]
+async fn sync_discards_new_index_when_some_articles_fail_to_be_indexed() {
+    let dataset_path =
+        std::env::temp_dir().join(format!("vna_data_sync_test_{}.ndjson", std::process::id()));
+    let article = |headline: &str| {
+        json!({
+            "category": "CRIME",
+            "headline": headline,
+            "authors": "Ron Dicker",
+            "link": "https://example.com",
+            "short_description": "Description",
+            "date": "2018-05-26",
+        })
+        .to_string()
+    };
+    std::fs::write(
+        &dataset_path,
+        [article("Indexed"), article("Rejected")].join("\n"),
+    )
+    .unwrap();
+
+    let index_deleted = Arc::new(AtomicBool::new(false));
+    let alias_updated = Arc::new(AtomicBool::new(false));
+    let elastic = mock_elasticsearch({
+        let index_deleted = index_deleted.clone();
+        let alias_updated = alias_updated.clone();
+        move |method, path, _| {
+            let body = if path.starts_with("/_cat/indices") {
+                json!([])
+            } else if path.starts_with("/_template/") && method == Method::GET {
+                return (StatusCode::NOT_FOUND, json!({}));
+            } else if path.starts_with("/_template/") {
+                json!({ "acknowledged": true })
+            } else if path.ends_with("/_bulk") {
+                json!({
+                    "took": 1,
+                    "errors": true,
+                    "items": [
+                        { "index": { "_index": "articles_1", "_id": "1", "status": 201 } },
+                        {
+                            "index": {
+                                "_index": "articles_1",
+                                "_id": "2",
+                                "status": 400,
+                                "error": {
+                                    "type": "mapper_parsing_exception",
+                                    "reason": "failed to parse field [date]",
+                                }
+                            }
+                        },
+                    ]
+                })
+            } else if method == Method::PUT {
+                json!({
+                    "acknowledged": true,
+                    "shards_acknowledged": true,
+                    "index": path.trim_start_matches('/'),
+                })
+            } else if method == Method::DELETE {
+                index_deleted.store(true, Ordering::SeqCst);
+                json!({ "acknowledged": true })
+            } else {
+                alias_updated.store(true, Ordering::SeqCst);
+                json!({ "acknowledged": true })
+            };
+            (StatusCode::OK, body)
+        }
+    });
+
+    let result = vna_data_sync::run(RunOpts {
+        elastic: &elastic,
+        kaggle_dataset_path: &dataset_path,
+        dataset: &DatasetOpts::default(),
+        strict: false,
+        cleaning: &CleaningOpts::default(),
+        scrape_interval: None,
+        max_news: 100,
+        n_shards: NonZeroU32::new(1).unwrap(),
+        n_replicas: 0,
+        ingest_batch: NonZeroU32::new(1000).unwrap(),
+        keep_versions: 1,
+        snapshot: None,
+    })
+    .await;
+    std::fs::remove_file(&dataset_path).unwrap();
+
+    let err = format!("{:#}", result.err().unwrap());
+    assert!(err.contains("Failed to index 1 of 2 articles"), "{}", err);
+    assert!(index_deleted.load(Ordering::SeqCst));
+    assert!(!alias_updated.load(Ordering::SeqCst));
+}
@@ -0,0 +1,127 @@
+use crate::SentimentPolarity;
+use chrono::FixedOffset;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Filters that narrow down the set of articles the queries are run against.
+/// Empty filters (the default) don't exclude any articles.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ArticleFilters {
+    /// Take only the articles that belong to this category
+    pub category: Option<String>,
+    /// Take only the articles with this sentiment polarity
+    pub sentiment_polarity: Option<SentimentPolarity>,
+    /// Take only the articles published at this date or later
+    pub date_from: Option<String>,
+    /// Take only the articles published at this date or earlier
+    pub date_to: Option<String>,
+    /// Timezone `date_from` and `date_to` without an explicit offset are interpreted in,
+    /// UTC if `None`. It is not a filter itself, so it isn't taken from the query params.
+    #[serde(skip)]
+    pub time_zone: Option<FixedOffset>,
+}
+
+impl ArticleFilters {
+    pub fn is_empty(&self) -> bool {
+        self.category.is_none()
+            && self.sentiment_polarity.is_none()
+            && self.date_from.is_none()
+            && self.date_to.is_none()
+    }
+
+    /// Wraps the given query into `bool` query that applies the filters.
+    /// Returns the query as it is if the filters are empty.
+    pub(crate) fn apply_to(&self, query: serde_json::Value) -> serde_json::Value {
+        if self.is_empty() {
+            return query;
+        }
+        json!({ "bool": { "must": [query], "filter": self.to_es_filters() } })
+    }
+
+    fn to_es_filters(&self) -> Vec<serde_json::Value> {
+        let mut filters = vec![];
+
+        if let Some(category) = &self.category {
+            filters.push(json!({ "term": { "category": category } }));
+        }
+        if let Some(polarity) = &self.sentiment_polarity {
+            filters.push(json!({ "term": { "sentiment_polarity": polarity } }));
+        }
+        if self.date_from.is_some() || self.date_to.is_some() {
+            let mut range = serde_json::Map::new();
+            if let Some(date_from) = &self.date_from {
+                range.insert("gte".to_owned(), json!(date_from));
+            }
+            if let Some(date_to) = &self.date_to {
+                range.insert("lte".to_owned(), json!(date_to));
+            }
+            if let Some(time_zone) = &self.time_zone {
+                range.insert("time_zone".to_owned(), json!(time_zone.to_string()));
+            }
+            filters.push(json!({ "range": { "date": range } }));
+        }
+
+        filters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_filters_leave_query_as_is() {
+        let query = json!({ "match_all": {} });
+        assert_eq!(ArticleFilters::default().apply_to(query.clone()), query);
+    }
+
+    #[test]
+    fn filters_are_applied_in_bool_query() {
+        let filters = ArticleFilters {
+            category: Some("POLITICS".to_owned()),
+            sentiment_polarity: Some(SentimentPolarity::Negative),
+            date_from: Some("2018-01-01".to_owned()),
+            date_to: None,
+            time_zone: None,
+        };
+
+        assert_eq!(
+            filters.apply_to(json!({ "match_all": {} })),
+            json!({
+                "bool": {
+                    "must": [{ "match_all": {} }],
+                    "filter": [
+                        { "term": { "category": "POLITICS" } },
+                        { "term": { "sentiment_polarity": "Negative" } },
+                        { "range": { "date": { "gte": "2018-01-01" } } },
+                    ]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn date_range_is_interpreted_in_time_zone() {
+        let filters = ArticleFilters {
+            date_from: Some("2018-01-01".to_owned()),
+            date_to: Some("2018-12-31".to_owned()),
+            time_zone: Some(FixedOffset::east(2 * 3600)),
+            ..ArticleFilters::default()
+        };
+
+        assert_eq!(
+            filters.to_es_filters(),
+            [json!({
+                "range": {
+                    "date": { "gte": "2018-01-01", "lte": "2018-12-31", "time_zone": "+02:00" }
+                }
+            })]
+        );
+
+        let filters = ArticleFilters {
+            time_zone: Some(FixedOffset::east(2 * 3600)),
+            ..ArticleFilters::default()
+        };
+        assert!(filters.is_empty());
+    }
+}
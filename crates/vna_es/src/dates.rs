@@ -0,0 +1,147 @@
+//! Parsing of the article dates in the formats used by the news sources.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Deserializer};
+
+/// Formats of the dates with time but without an offset
+const NAIVE_DATE_TIME_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Parses the date in one of the formats:
+/// - ISO-8601 with an offset, e.g. `2018-05-26T10:00:00+02:00` or `2018-05-26T10:00:00Z`
+/// - RFC 2822 used by RSS feeds, e.g. `Sat, 26 May 2018 10:00:00 GMT`
+/// - ISO-8601 without an offset, e.g. `2018-05-26T10:00:00` or `2018-05-26 10:00:00`
+/// - `YYYY-MM-DD`, the time is midnight
+///
+/// Dates without an offset are interpreted in `default_timezone`.
+pub fn parse_date(date: &str, default_timezone: FixedOffset) -> Result<DateTime<Utc>> {
+    let date = date.trim();
+
+    let with_offset = DateTime::parse_from_rfc3339(date)
+        // ISO-8601 allows the offset without a colon
+        .or_else(|_| DateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .or_else(|_| DateTime::parse_from_rfc2822(date));
+    if let Ok(it) = with_offset {
+        return Ok(it.with_timezone(&Utc));
+    }
+
+    let naive = NAIVE_DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .map(|it| it.and_hms(0, 0, 0))
+        });
+
+    match naive {
+        Some(naive) => Ok(default_timezone
+            .from_local_datetime(&naive)
+            .single()
+            .with_context(|| format!("Date '{}' is out of range", date))?
+            .with_timezone(&Utc)),
+        None => bail!(
+            "Unsupported date format '{}', expected ISO-8601, RFC 2822 or YYYY-MM-DD",
+            date
+        ),
+    }
+}
+
+/// Parses `UTC`, `Z` or the offset from UTC like `+02:00`, `-0530` or `+03`
+pub fn parse_utc_offset(offset: &str) -> Result<FixedOffset> {
+    let offset = offset.trim();
+    if offset.eq_ignore_ascii_case("utc") || offset == "Z" {
+        return Ok(FixedOffset::east(0));
+    }
+
+    let invalid = || {
+        format!(
+            "Invalid UTC offset '{}', expected e.g. UTC or +02:00",
+            offset
+        )
+    };
+
+    let (sign, digits) = match offset.as_bytes().first() {
+        Some(b'+') => (1, offset[1..].replace(':', "")),
+        Some(b'-') => (-1, offset[1..].replace(':', "")),
+        _ => bail!(invalid()),
+    };
+    if !digits.bytes().all(|it| it.is_ascii_digit()) || !matches!(digits.len(), 2 | 4) {
+        bail!(invalid());
+    }
+    let hours: i32 = digits[..2].parse().with_context(invalid)?;
+    let minutes: i32 = match digits.len() {
+        4 => digits[2..].parse().with_context(invalid)?,
+        _ => 0,
+    };
+    if hours > 23 || minutes > 59 {
+        bail!(invalid());
+    }
+    Ok(FixedOffset::east(sign * (hours * 3600 + minutes * 60)))
+}
+
+/// Accepts the dates stored before they were normalised to full timestamps, e.g. `2018-05-26`
+pub(crate) fn deserialize_stored<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let date = String::deserialize(deserializer)?;
+    parse_date(&date, FixedOffset::east(0)).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(date: &str) -> String {
+        parse_date(date, FixedOffset::east(2 * 3600))
+            .unwrap()
+            .to_rfc3339()
+    }
+
+    #[test]
+    fn parses_common_formats() {
+        assert_eq!(parse("2018-05-26"), "2018-05-25T22:00:00+00:00");
+        assert_eq!(parse(" 2018-05-26 10:30:00 "), "2018-05-26T08:30:00+00:00");
+        assert_eq!(parse("2018-05-26T10:30"), "2018-05-26T08:30:00+00:00");
+        assert_eq!(parse("2018-05-26T10:30:00Z"), "2018-05-26T10:30:00+00:00");
+        assert_eq!(
+            parse("2018-05-26T10:30:00.250-05:00"),
+            "2018-05-26T15:30:00.250+00:00"
+        );
+        assert_eq!(
+            parse("2018-05-26T10:30:00+0100"),
+            "2018-05-26T09:30:00+00:00"
+        );
+        assert_eq!(
+            parse("Sat, 26 May 2018 10:30:00 GMT"),
+            "2018-05-26T10:30:00+00:00"
+        );
+        assert_eq!(
+            parse("Sat, 26 May 2018 10:30:00 +0300"),
+            "2018-05-26T07:30:00+00:00"
+        );
+
+        for date in &["", "26.05.2018", "2018-13-01", "yesterday"] {
+            assert!(parse_date(date, FixedOffset::east(0)).is_err(), "{}", date);
+        }
+    }
+
+    #[test]
+    fn parses_utc_offsets() {
+        let offset = |it: &str| parse_utc_offset(it).map(|it| it.local_minus_utc()).ok();
+        assert_eq!(offset("UTC"), Some(0));
+        assert_eq!(offset("Z"), Some(0));
+        assert_eq!(offset("+02:00"), Some(7200));
+        assert_eq!(offset("-0530"), Some(-19800));
+        assert_eq!(offset("+03"), Some(10800));
+        assert_eq!(offset("02:00"), None);
+        assert_eq!(offset("+24:00"), None);
+        assert_eq!(offset("+2:00"), None);
+    }
+}
@@ -0,0 +1,376 @@
+//! Near-duplicate articles detection.
+//!
+//! Candidates for each article are retrieved via `more_like_this` queries that
+//! are batched into `_msearch` requests, then the candidates are compared to the
+//! article by Jaccard similarity of the word shingles of their headlines and
+//! descriptions. Articles that are similar enough are merged into clusters.
+
+use crate::{scroll, Article, ArticleFilters, ArticlesScroll, WithId};
+use anyhow::{anyhow, bail, Result};
+use elasticsearch::{params::Refresh, BulkParts, Elasticsearch, MsearchParts};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+};
+use vna_es_utils::es_types;
+
+/// Number of articles fetched at once while scanning the index
+const SCROLL_BATCH_SIZE: u32 = 1000;
+
+/// Number of `more_like_this` searches sent in a single `_msearch` request
+const CANDIDATES_BATCH_SIZE: usize = 100;
+
+/// Group of articles that are near-duplicates of each other.
+/// The first article is the canonical one (the earliest published).
+pub struct DuplicatesCluster {
+    pub articles: Vec<WithId<Article>>,
+}
+
+impl DuplicatesCluster {
+    pub fn canonical(&self) -> &WithId<Article> {
+        &self.articles[0]
+    }
+
+    pub fn duplicates(&self) -> &[WithId<Article>] {
+        &self.articles[1..]
+    }
+}
+
+pub struct FindDuplicatesOpts<'a> {
+    pub elastic: &'a Elasticsearch,
+    pub filters: &'a ArticleFilters,
+    /// Minimal similarity (from 0 to 1) of the articles to consider them duplicates
+    pub threshold: f64,
+    /// Maximum number of `more_like_this` candidates to compare each article with
+    pub max_candidates: u32,
+}
+
+impl Article {
+    /// Scans all the articles that match the filters and groups near-duplicates into clusters
+    pub async fn find_duplicates(opts: FindDuplicatesOpts<'_>) -> Result<Vec<DuplicatesCluster>> {
+        let mut scroll = ArticlesScroll::new(opts.elastic, opts.filters, SCROLL_BATCH_SIZE);
+
+        let clusters = Self::cluster_duplicates(&opts, &mut scroll).await;
+        let clusters = scroll::with_cleared(clusters, scroll.clear().await)?;
+
+        Ok(clusters.into_clusters())
+    }
+
+    async fn cluster_duplicates(
+        opts: &FindDuplicatesOpts<'_>,
+        scroll: &mut ArticlesScroll<'_>,
+    ) -> Result<Clusters> {
+        let mut clusters = Clusters::default();
+        loop {
+            let batch = scroll.next_batch().await?;
+            if batch.is_empty() {
+                return Ok(clusters);
+            }
+            for articles in batch.chunks(CANDIDATES_BATCH_SIZE) {
+                let candidates = Self::fetch_duplicate_candidates(opts, articles).await?;
+
+                for (article, candidates) in articles.iter().zip(candidates) {
+                    let article_shingles = shingles(&article.doc);
+
+                    for candidate in candidates {
+                        let similarity =
+                            jaccard_similarity(&article_shingles, &shingles(&candidate.doc));
+                        if similarity >= opts.threshold {
+                            clusters.union(article, candidate);
+                        }
+                    }
+                }
+            }
+            for article in batch {
+                clusters.add(article);
+            }
+        }
+    }
+
+    /// Retrieves the candidates for each of the articles with a single `_msearch` request.
+    /// Returns the candidates in the order of the articles.
+    async fn fetch_duplicate_candidates(
+        opts: &FindDuplicatesOpts<'_>,
+        articles: &[WithId<Article>],
+    ) -> Result<Vec<Vec<WithId<Article>>>> {
+        let body: Vec<_> = articles
+            .iter()
+            .flat_map(|article| {
+                let query = json!({
+                    "bool": {
+                        "must": [{
+                            "more_like_this": {
+                                "fields": ["headline", "short_description"],
+                                "like": format!("{} {}", article.doc.headline, article.doc.short_description),
+                                "min_term_freq": 1,
+                                "max_query_terms": 25,
+                            }
+                        }],
+                        "must_not": [{ "ids": { "values": [article.id] } }],
+                    }
+                });
+                let search = json!({
+                    "size": opts.max_candidates,
+                    "query": opts.filters.apply_to(query),
+                });
+                iter::once(json!({}).to_string()).chain(iter::once(search.to_string()))
+            })
+            .collect();
+
+        let response = opts
+            .elastic
+            .msearch(MsearchParts::Index(&[Self::INDEX_ALIAS]))
+            .body(body)
+            .send()
+            .await?;
+
+        let response: es_types::MultiSearchResponse<Article> =
+            vna_es_utils::check_status(response).await?.json().await?;
+
+        if response.responses.len() != articles.len() {
+            bail!(
+                "Expected {} responses to the duplicate candidates searches, but got {}",
+                articles.len(),
+                response.responses.len()
+            );
+        }
+
+        response
+            .responses
+            .into_iter()
+            .map(|it| match it {
+                es_types::MultiSearchItem::Ok(it) => {
+                    Ok(it.hits.hits.into_iter().map(WithId::from).collect())
+                }
+                es_types::MultiSearchItem::Err { error, .. } => Err(anyhow!(
+                    "Failed to search for duplicate candidates: {}",
+                    error
+                )),
+            })
+            .collect()
+    }
+
+    /// Sets `duplicate_of` field of the non-canonical articles to the id of the canonical one.
+    /// Returns the number of marked articles.
+    pub async fn mark_duplicates(
+        elastic: &Elasticsearch,
+        clusters: &[DuplicatesCluster],
+    ) -> Result<usize> {
+        let body: Vec<_> = clusters
+            .iter()
+            .flat_map(|cluster| {
+                let canonical = &cluster.canonical().id;
+                cluster.duplicates().iter().flat_map(move |duplicate| {
+                    let header = json!({ "update": { "_id": duplicate.id } }).to_string();
+                    let doc = json!({ "doc": { "duplicate_of": canonical } }).to_string();
+                    iter::once(header).chain(iter::once(doc))
+                })
+            })
+            .collect();
+
+        Self::send_bulk(elastic, body).await?;
+
+        Ok(count_duplicates(clusters))
+    }
+
+    /// Deletes the non-canonical articles from the index.
+    /// Returns the number of removed articles.
+    pub async fn remove_duplicates(
+        elastic: &Elasticsearch,
+        clusters: &[DuplicatesCluster],
+    ) -> Result<usize> {
+        let body: Vec<_> = clusters
+            .iter()
+            .flat_map(|cluster| cluster.duplicates())
+            .map(|duplicate| json!({ "delete": { "_id": duplicate.id } }).to_string())
+            .collect();
+
+        Self::send_bulk(elastic, body).await?;
+
+        Ok(count_duplicates(clusters))
+    }
+
+    async fn send_bulk(elastic: &Elasticsearch, body: Vec<String>) -> Result<()> {
+        if body.is_empty() {
+            return Ok(());
+        }
+        let response = elastic
+            .bulk(BulkParts::Index(Self::INDEX_ALIAS))
+            .body(body)
+            .refresh(Refresh::WaitFor)
+            .send()
+            .await?;
+
+        let response: es_types::BulkResponse =
+            vna_es_utils::check_status(response).await?.json().await?;
+
+        if let Some((item, error)) = response.failed_items().next() {
+            bail!(
+                "Failed to update {} articles, the error for '{}': {}",
+                response.failed_items().count(),
+                item._id.as_deref().unwrap_or_default(),
+                error,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn count_duplicates(clusters: &[DuplicatesCluster]) -> usize {
+    clusters.iter().map(|it| it.duplicates().len()).sum()
+}
+
+/// Union-find of the articles by their ids
+#[derive(Default)]
+struct Clusters {
+    parents: HashMap<String, String>,
+    articles: HashMap<String, WithId<Article>>,
+}
+
+impl Clusters {
+    fn root(&self, id: &str) -> String {
+        let mut id = id;
+        while let Some(parent) = self.parents.get(id) {
+            if parent == id {
+                break;
+            }
+            id = parent.as_str();
+        }
+        id.to_owned()
+    }
+
+    fn union(&mut self, article: &WithId<Article>, duplicate: WithId<Article>) {
+        let (a, b) = (self.root(&article.id), self.root(&duplicate.id));
+        self.parents.entry(a.clone()).or_insert_with(|| a.clone());
+        self.parents.insert(b, a);
+        self.articles
+            .entry(duplicate.id.clone())
+            .or_insert(duplicate);
+    }
+
+    /// Remembers the article if it has been merged into some cluster
+    fn add(&mut self, article: WithId<Article>) {
+        if self.parents.contains_key(&article.id) {
+            self.articles.insert(article.id.clone(), article);
+        }
+    }
+
+    fn into_clusters(mut self) -> Vec<DuplicatesCluster> {
+        let ids: Vec<_> = self.parents.keys().cloned().collect();
+        let mut groups: HashMap<String, Vec<WithId<Article>>> = HashMap::new();
+
+        for id in ids {
+            let root = self.root(&id);
+            if let Some(article) = self.articles.remove(&id) {
+                groups.entry(root).or_default().push(article);
+            }
+        }
+
+        let mut clusters: Vec<_> = groups
+            .into_iter()
+            .map(|(_, mut articles)| {
+                articles.sort_by(|a, b| (&a.doc.date, &a.id).cmp(&(&b.doc.date, &b.id)));
+                DuplicatesCluster { articles }
+            })
+            .filter(|cluster| cluster.articles.len() > 1)
+            .collect();
+
+        clusters.sort_by(|a, b| a.canonical().id.cmp(&b.canonical().id));
+        clusters
+    }
+}
+
+/// Returns the set of pairs of consecutive normalized words of the article text
+fn shingles(article: &Article) -> HashSet<(String, String)> {
+    let words: Vec<_> = article
+        .headline
+        .split(|c: char| !c.is_alphanumeric())
+        .chain(
+            article
+                .short_description
+                .split(|c: char| !c.is_alphanumeric()),
+        )
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    if words.len() == 1 {
+        return iter::once((words[0].clone(), String::new())).collect();
+    }
+
+    words
+        .windows(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect()
+}
+
+fn jaccard_similarity<T: Eq + std::hash::Hash>(a: &HashSet<T>, b: &HashSet<T>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SentimentPolarity;
+
+    fn article(id: &str, date: &str, headline: &str) -> WithId<Article> {
+        WithId {
+            id: id.to_owned(),
+            doc: Article {
+                category: "POLITICS".to_owned(),
+                headline: headline.to_owned(),
+                authors: String::new(),
+                link: String::new(),
+                short_description: String::new(),
+                date: crate::dates::parse_date(date, chrono::FixedOffset::east(0)).unwrap(),
+                ingested_at: None,
+                sentiment_score: 0.0,
+                sentiment_polarity: SentimentPolarity::Positive,
+                duplicate_of: None,
+            },
+        }
+    }
+
+    #[test]
+    fn similarity_ignores_case_and_punctuation() {
+        let a = shingles(&article("1", "2018-01-01", "Trump Says He Will Meet Kim").doc);
+        let b = shingles(&article("2", "2018-01-01", "trump says: he will meet kim!").doc);
+        assert_eq!(jaccard_similarity(&a, &b), 1.0);
+
+        let c = shingles(&article("3", "2018-01-01", "Something completely different").doc);
+        assert_eq!(jaccard_similarity(&a, &c), 0.0);
+    }
+
+    #[test]
+    fn clusters_merge_transitively() {
+        let mut clusters = Clusters::default();
+
+        clusters.union(
+            &article("b", "2018-01-02", ""),
+            article("c", "2018-01-03", ""),
+        );
+        clusters.add(article("b", "2018-01-02", ""));
+        clusters.union(
+            &article("c", "2018-01-03", ""),
+            article("a", "2018-01-01", ""),
+        );
+        clusters.add(article("c", "2018-01-03", ""));
+        clusters.add(article("lonely", "2018-01-01", ""));
+
+        let clusters = clusters.into_clusters();
+        assert_eq!(clusters.len(), 1);
+
+        let ids: Vec<_> = clusters[0]
+            .articles
+            .iter()
+            .map(|it| it.id.as_str())
+            .collect();
+        assert_eq!(ids, ["a", "b", "c"]);
+    }
+}
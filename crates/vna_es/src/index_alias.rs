@@ -0,0 +1,339 @@
+//! Resolution and repair of the articles alias.
+//!
+//! Normally the alias points to exactly one `articles_<version>` index, but
+//! interrupted syncs or manual changes may leave it pointing to several indices
+//! or to an index that doesn't follow the naming scheme.
+
+use crate::{Article, IndexVersion};
+use anyhow::{bail, Result};
+use elasticsearch::{indices::IndicesGetAliasParts, Elasticsearch};
+use serde_json::json;
+use std::fmt;
+use vna_es_utils::{es_types, EsError};
+
+/// Index the articles alias points to
+#[derive(Debug, Clone, PartialEq)]
+pub struct AliasTarget {
+    pub index_name: String,
+    /// `None` if the index name doesn't have `articles_<version>` form
+    pub version: Option<IndexVersion>,
+    pub is_write_index: bool,
+}
+
+/// Inconsistent state of the articles alias that can't be resolved automatically
+#[derive(Debug, PartialEq)]
+pub enum IndexAliasError {
+    /// None of the indices the alias points to has `articles_<version>` name
+    NoVersionedTarget { indices: Vec<String> },
+    /// The alias is marked as the write alias of several indices
+    MultipleWriteIndices { indices: Vec<String> },
+}
+
+impl fmt::Display for IndexAliasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoVersionedTarget { indices } => write!(
+                f,
+                "'{}' alias points to indices that are not versioned: [{}], \
+                run `vna index repair --to <version>` to fix it",
+                Article::INDEX_ALIAS,
+                indices.join(", ")
+            ),
+            Self::MultipleWriteIndices { indices } => write!(
+                f,
+                "'{}' alias has several write indices: [{}], \
+                run `vna index repair` to fix it",
+                Article::INDEX_ALIAS,
+                indices.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IndexAliasError {}
+
+/// Chooses the version of the index the alias should be considered to point to:
+/// the write index if there is one, otherwise the index with the highest version.
+pub fn resolve_alias_target(
+    targets: &[AliasTarget],
+) -> Result<Option<IndexVersion>, IndexAliasError> {
+    if targets.is_empty() {
+        return Ok(None);
+    }
+
+    let write_indices: Vec<_> = targets.iter().filter(|it| it.is_write_index).collect();
+
+    match write_indices.as_slice() {
+        [] => {}
+        [write_index] => {
+            if let Some(version) = write_index.version {
+                return Ok(Some(version));
+            }
+        }
+        _ => {
+            return Err(IndexAliasError::MultipleWriteIndices {
+                indices: write_indices
+                    .iter()
+                    .map(|it| it.index_name.clone())
+                    .collect(),
+            })
+        }
+    }
+
+    match targets.iter().filter_map(|it| it.version).max() {
+        Some(version) => Ok(Some(version)),
+        None => Err(IndexAliasError::NoVersionedTarget {
+            indices: targets.iter().map(|it| it.index_name.clone()).collect(),
+        }),
+    }
+}
+
+/// Result of [`Article::repair_index_alias`]
+#[derive(Debug)]
+pub struct AliasRepair {
+    /// Version the alias points to after the repair
+    pub target: IndexVersion,
+    /// Indices the alias was removed from
+    pub removed_from: Vec<String>,
+    /// Whether the alias was changed at all
+    pub repaired: bool,
+}
+
+impl Article {
+    /// Returns all the indices the articles alias points to sorted by their names
+    pub async fn fetch_alias_targets(elastic: &Elasticsearch) -> Result<Vec<AliasTarget>> {
+        let response = elastic
+            .indices()
+            .get_alias(IndicesGetAliasParts::Name(&[Self::INDEX_ALIAS]))
+            .send()
+            .await?;
+
+        let response = match vna_es_utils::check_status(response).await {
+            Ok(it) => it,
+            Err(EsError::NotFound { .. }) | Err(EsError::IndexNotFound { .. }) => {
+                return Ok(vec![])
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let response: es_types::GetAliasesResponse = response.json().await?;
+
+        let mut targets: Vec<_> = response
+            .0
+            .into_iter()
+            .map(|(index_name, mut aliases)| {
+                let alias = aliases
+                    .aliases
+                    .remove(Self::INDEX_ALIAS)
+                    .unwrap_or_default();
+                AliasTarget {
+                    version: IndexVersion::from_index_name_of_alias(&index_name, Self::INDEX_ALIAS),
+                    is_write_index: alias.is_write_index == Some(true),
+                    index_name,
+                }
+            })
+            .collect();
+
+        targets.sort_by(|a, b| a.index_name.cmp(&b.index_name));
+        Ok(targets)
+    }
+
+    /// Returns the version of the index the articles alias points to, `None` if there
+    /// is no such alias yet. See [`resolve_alias_target`] for the case when the alias
+    /// points to several indices.
+    pub async fn fetch_index_version(elastic: &Elasticsearch) -> Result<Option<IndexVersion>> {
+        let targets = Self::fetch_alias_targets(elastic).await?;
+        let version = resolve_alias_target(&targets)?;
+
+        if targets.len() > 1 {
+            log::warn!(
+                "'{}' alias points to several indices: [{}], using version {}",
+                Self::INDEX_ALIAS,
+                targets
+                    .iter()
+                    .map(|it| it.index_name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                version.map(|it| it.to_string()).unwrap_or_default()
+            );
+        }
+
+        Ok(version)
+    }
+
+    /// Atomically points the articles alias exclusively to the given version of the index
+    pub async fn update_index_alias(
+        elastic: &Elasticsearch,
+        new_version: IndexVersion,
+    ) -> Result<()> {
+        let targets = Self::fetch_alias_targets(elastic).await?;
+        Self::point_alias_exclusively(elastic, &targets, new_version).await
+    }
+
+    /// Makes the articles alias point to exactly one versioned index.
+    /// The index is `to` if specified, otherwise it is chosen via [`resolve_alias_target`]
+    /// falling back to the highest version if there are several write indices.
+    pub async fn repair_index_alias(
+        elastic: &Elasticsearch,
+        to: Option<IndexVersion>,
+    ) -> Result<AliasRepair> {
+        let targets = Self::fetch_alias_targets(elastic).await?;
+
+        let target = match to {
+            Some(to) => {
+                if !Self::fetch_index_versions(elastic).await?.contains(&to) {
+                    bail!(
+                        "Index '{}' does not exist",
+                        to.attach_to_alias(Self::INDEX_ALIAS)
+                    );
+                }
+                to
+            }
+            None => {
+                let resolved = match resolve_alias_target(&targets) {
+                    Err(IndexAliasError::MultipleWriteIndices { .. }) => {
+                        let unmarked: Vec<_> = targets
+                            .iter()
+                            .map(|it| AliasTarget {
+                                is_write_index: false,
+                                ..it.clone()
+                            })
+                            .collect();
+                        resolve_alias_target(&unmarked)
+                    }
+                    it => it,
+                };
+                match resolved? {
+                    Some(it) => it,
+                    None => bail!(
+                        "'{}' alias does not exist, specify the version to point it to via `--to`",
+                        Self::INDEX_ALIAS
+                    ),
+                }
+            }
+        };
+
+        let removed_from: Vec<_> = targets
+            .iter()
+            .filter(|it| it.version != Some(target))
+            .map(|it| it.index_name.clone())
+            .collect();
+
+        let is_consistent = matches!(
+            targets.as_slice(),
+            [it] if it.version == Some(target) && !it.is_write_index
+        );
+        if is_consistent {
+            return Ok(AliasRepair {
+                target,
+                removed_from,
+                repaired: false,
+            });
+        }
+
+        Self::point_alias_exclusively(elastic, &targets, target).await?;
+
+        Ok(AliasRepair {
+            target,
+            removed_from,
+            repaired: true,
+        })
+    }
+
+    async fn point_alias_exclusively(
+        elastic: &Elasticsearch,
+        targets: &[AliasTarget],
+        version: IndexVersion,
+    ) -> Result<()> {
+        let mut actions: Vec<_> = targets
+            .iter()
+            .map(|it| {
+                json!({
+                    "remove": {
+                        "index": it.index_name,
+                        "alias": Self::INDEX_ALIAS,
+                    }
+                })
+            })
+            .collect();
+
+        actions.push(json!({
+            "add": {
+                "index": version.attach_to_alias(Self::INDEX_ALIAS),
+                "alias": Self::INDEX_ALIAS,
+            }
+        }));
+
+        let response = elastic
+            .indices()
+            .update_aliases()
+            .body(json!({ "actions": actions }))
+            .send()
+            .await?;
+
+        let response: es_types::AcknowledgedResponse =
+            vna_es_utils::check_status(response).await?.json().await?;
+
+        if !response.acknowledged {
+            bail!(
+                "Elasticsearch didn't acknowledge the update of '{}' alias",
+                Self::INDEX_ALIAS
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(index_name: &str, is_write_index: bool) -> AliasTarget {
+        AliasTarget {
+            index_name: index_name.to_owned(),
+            version: IndexVersion::from_index_name_of_alias(index_name, Article::INDEX_ALIAS),
+            is_write_index,
+        }
+    }
+
+    fn version(version: &str) -> Option<IndexVersion> {
+        Some(version.parse().unwrap())
+    }
+
+    #[test]
+    fn resolves_single_and_missing_targets() {
+        assert_eq!(resolve_alias_target(&[]), Ok(None));
+        assert_eq!(
+            resolve_alias_target(&[target("articles_3", false)]),
+            Ok(version("3"))
+        );
+    }
+
+    #[test]
+    fn prefers_write_index_then_highest_version() {
+        let targets = [
+            target("articles_2", false),
+            target("articles_10", false),
+            target("articles_backup", false),
+        ];
+        assert_eq!(resolve_alias_target(&targets), Ok(version("10")));
+
+        let targets = [target("articles_2", true), target("articles_10", false)];
+        assert_eq!(resolve_alias_target(&targets), Ok(version("2")));
+    }
+
+    #[test]
+    fn fails_on_unresolvable_targets() {
+        assert_eq!(
+            resolve_alias_target(&[target("articles_backup", false)]),
+            Err(IndexAliasError::NoVersionedTarget {
+                indices: vec!["articles_backup".to_owned()]
+            })
+        );
+        assert!(matches!(
+            resolve_alias_target(&[target("articles_1", true), target("articles_2", true)]),
+            Err(IndexAliasError::MultipleWriteIndices { .. })
+        ));
+    }
+}
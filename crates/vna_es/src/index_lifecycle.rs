@@ -0,0 +1,307 @@
+//! Blue/green management of the versioned articles indices.
+//!
+//! Each data sync creates a new `articles_<version>` index, validates it and only
+//! then atomically moves the `articles` alias to it. A few previous versions
+//! are kept around to be able to roll back to them.
+
+use crate::{mapping_diff, migrations, Article, IndexVersion};
+use anyhow::{bail, Context, Result};
+use elasticsearch::{
+    cat::CatIndicesParts,
+    cluster::ClusterHealthParts,
+    indices::{IndicesGetMappingParts, IndicesRefreshParts},
+    params::{Bytes, WaitForStatus},
+    CountParts, Elasticsearch,
+};
+use std::collections::BTreeMap;
+use vna_es_utils::{es_types, HealthStatus};
+
+/// Summary of a single version of the articles index
+#[derive(Debug)]
+pub struct IndexVersionInfo {
+    pub version: IndexVersion,
+    pub index_name: String,
+    /// `None` if the index is closed
+    pub health: Option<HealthStatus>,
+    /// Number of documents in the primary shards, `None` if the index is closed
+    pub doc_count: Option<u64>,
+    /// Size of all the shards including replicas, `None` if the index is closed
+    pub size_in_bytes: Option<u64>,
+    /// Whether the articles alias points to this index
+    pub is_current: bool,
+}
+
+pub struct ValidateIndexOpts<'a> {
+    pub elastic: &'a Elasticsearch,
+    pub version: IndexVersion,
+    /// Number of documents that were successfully ingested into the index
+    pub expected_docs: u64,
+}
+
+impl Article {
+    /// Checks that the index is ready to be pointed to by the articles alias:
+    /// it has the expected number of documents and mapping, and its health is not red
+    pub async fn validate_index(opts: ValidateIndexOpts<'_>) -> Result<()> {
+        let index_name = opts.version.attach_to_alias(Self::INDEX_ALIAS);
+
+        let response = opts
+            .elastic
+            .cluster()
+            .health(ClusterHealthParts::Index(&[&index_name]))
+            .wait_for_status(WaitForStatus::Yellow)
+            .timeout("30s")
+            .send()
+            .await?;
+        // 408 is returned if the status was not reached, its body is a regular health response
+        if response.status_code() != http::StatusCode::REQUEST_TIMEOUT {
+            response.error_for_status_code_ref()?;
+        }
+        let health: es_types::ClusterHealthResponse = response.json().await?;
+        if health.status < HealthStatus::Yellow {
+            bail!("Index '{}' health is {}", index_name, health.status);
+        }
+
+        vna_es_utils::check_status(
+            opts.elastic
+                .indices()
+                .refresh(IndicesRefreshParts::Index(&[&index_name]))
+                .send()
+                .await?,
+        )
+        .await?;
+
+        let response = opts
+            .elastic
+            .count(CountParts::Index(&[&index_name]))
+            .send()
+            .await?;
+        let count: es_types::CountResponse =
+            vna_es_utils::check_status(response).await?.json().await?;
+
+        if count.count == 0 {
+            bail!("Index '{}' is empty", index_name);
+        }
+        if count.count != opts.expected_docs {
+            bail!(
+                "Index '{}' contains {} documents while {} were ingested",
+                index_name,
+                count.count,
+                opts.expected_docs
+            );
+        }
+
+        let diffs = Self::diff_index_mapping(opts.elastic, opts.version).await?;
+        if !diffs.is_empty() {
+            let diffs: Vec<_> = diffs.iter().map(ToString::to_string).collect();
+            bail!(
+                "Index '{}' mapping differs from the expected one:\n{}",
+                index_name,
+                diffs.join("\n")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Compares the live mapping of the index with the mapping of its schema version
+    pub async fn diff_index_mapping(
+        elastic: &Elasticsearch,
+        version: IndexVersion,
+    ) -> Result<Vec<mapping_diff::MappingDiff>> {
+        let mapping = Self::fetch_index_mapping(elastic, version).await?;
+        let schema = migrations::find(migrations::schema_version_of(&mapping)?)?;
+
+        let expected: BTreeMap<String, serde_json::Value> =
+            serde_json::from_value((schema.properties)())?;
+
+        let mut diffs = mapping_diff::diff_mappings(&expected, &mapping.properties);
+
+        if schema.schema_version != migrations::latest().schema_version {
+            // Indices of the older schema versions created via `vna index migrate --to`
+            // also get the fields of the latest schema version from the index template
+            let latest: BTreeMap<String, serde_json::Value> =
+                serde_json::from_value((migrations::latest().properties)())?;
+            let template_diffs = mapping_diff::diff_mappings(&latest, &mapping.properties);
+
+            diffs.retain(|diff| match diff {
+                mapping_diff::MappingDiff::Unexpected { field, .. } => {
+                    template_diffs.iter().any(|it| it.field() == field)
+                }
+                _ => true,
+            });
+        }
+
+        Ok(diffs)
+    }
+
+    pub(crate) async fn fetch_index_mapping(
+        elastic: &Elasticsearch,
+        version: IndexVersion,
+    ) -> Result<es_types::TypeMapping> {
+        let index_name = version.attach_to_alias(Self::INDEX_ALIAS);
+
+        let response = elastic
+            .indices()
+            .get_mapping(IndicesGetMappingParts::Index(&[&index_name]))
+            .send()
+            .await?;
+        let mut response: es_types::GetMappingResponse =
+            vna_es_utils::check_status(response).await?.json().await?;
+
+        Ok(response
+            .0
+            .remove(&index_name)
+            .with_context(|| format!("No mapping was returned for index '{}'", index_name))?
+            .mappings)
+    }
+
+    /// Returns the records of all `articles_<version>` indices sorted by version
+    pub async fn fetch_index_records(
+        elastic: &Elasticsearch,
+    ) -> Result<Vec<(IndexVersion, es_types::CatIndicesRecord)>> {
+        let pattern = format!("{}_*", Self::INDEX_ALIAS);
+        let response = elastic
+            .cat()
+            .indices(CatIndicesParts::Index(&[&pattern]))
+            .format("json")
+            .bytes(Bytes::B)
+            .send()
+            .await?;
+
+        let records: Vec<es_types::CatIndicesRecord> =
+            vna_es_utils::check_status(response).await?.json().await?;
+
+        let mut records: Vec<_> = records
+            .into_iter()
+            .filter_map(|record| {
+                let version =
+                    IndexVersion::from_index_name_of_alias(&record.index, Self::INDEX_ALIAS)?;
+                Some((version, record))
+            })
+            .collect();
+
+        records.sort_by_key(|(version, _)| *version);
+        Ok(records)
+    }
+
+    /// Returns the info about all the existing articles indices in ascending order of their versions
+    pub async fn list_index_versions(elastic: &Elasticsearch) -> Result<Vec<IndexVersionInfo>> {
+        let current = Self::fetch_index_version(elastic).await?;
+        let records = Self::fetch_index_records(elastic).await?;
+
+        records
+            .into_iter()
+            .map(|(version, record)| {
+                let parse = |value: Option<String>, name: &str| {
+                    value
+                        .map(|it| it.parse::<u64>())
+                        .transpose()
+                        .with_context(|| format!("Invalid {} of index '{}'", name, record.index))
+                };
+                Ok(IndexVersionInfo {
+                    version,
+                    health: record.health,
+                    doc_count: parse(record.docs_count.clone(), "docs count")?,
+                    size_in_bytes: parse(record.store_size.clone(), "store size")?,
+                    is_current: Some(version) == current,
+                    index_name: record.index,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the version the next created articles index should have.
+    /// It is greater than all the existing ones, because the alias may point
+    /// to an older version after a rollback.
+    pub async fn next_index_version(elastic: &Elasticsearch) -> Result<IndexVersion> {
+        Ok(Self::fetch_index_versions(elastic)
+            .await?
+            .into_iter()
+            .max()
+            .map(IndexVersion::incremented)
+            .unwrap_or_default())
+    }
+
+    /// Deletes the given version of the articles index, refuses to delete
+    /// the one the alias points to
+    pub async fn delete_unused_index(elastic: &Elasticsearch, version: IndexVersion) -> Result<()> {
+        if Self::fetch_index_version(elastic).await? == Some(version) {
+            bail!(
+                "Index '{}' is used by '{}' alias, roll back to another version first",
+                version.attach_to_alias(Self::INDEX_ALIAS),
+                Self::INDEX_ALIAS
+            );
+        }
+        Self::delete_index(elastic, version).await
+    }
+
+    /// Returns the versions of all the existing articles indices in ascending order
+    pub async fn fetch_index_versions(elastic: &Elasticsearch) -> Result<Vec<IndexVersion>> {
+        let records = Self::fetch_index_records(elastic).await?;
+        Ok(records.into_iter().map(|(version, _)| version).collect())
+    }
+
+    /// Deletes all the versions of the articles index except the one the alias points to
+    /// and `keep` most recent other ones. Returns the deleted versions.
+    pub async fn prune_index_versions(
+        elastic: &Elasticsearch,
+        keep: u32,
+    ) -> Result<Vec<IndexVersion>> {
+        let current = Self::fetch_index_version(elastic).await?;
+        let versions = Self::fetch_index_versions(elastic).await?;
+
+        let to_delete: Vec<_> = versions
+            .into_iter()
+            .rev()
+            .filter(|version| Some(*version) != current)
+            .skip(keep as usize)
+            .collect();
+
+        for &version in &to_delete {
+            Self::delete_index(elastic, version).await?;
+        }
+
+        Ok(to_delete)
+    }
+
+    /// Points the articles alias to the given version or to the latest version that
+    /// precedes the current one if `target` is `None`.
+    /// Returns the previous and the new versions the alias pointed to.
+    pub async fn rollback_index(
+        elastic: &Elasticsearch,
+        target: Option<IndexVersion>,
+    ) -> Result<(IndexVersion, IndexVersion)> {
+        let current = Self::fetch_index_version(elastic)
+            .await?
+            .with_context(|| format!("'{}' alias does not exist yet", Self::INDEX_ALIAS))?;
+
+        let versions = Self::fetch_index_versions(elastic).await?;
+
+        let target = match target {
+            Some(target) if target == current => bail!(
+                "'{}' alias already points to version {}",
+                Self::INDEX_ALIAS,
+                target
+            ),
+            Some(target) if !versions.contains(&target) => bail!(
+                "Index '{}' does not exist",
+                target.attach_to_alias(Self::INDEX_ALIAS)
+            ),
+            Some(target) => target,
+            None => versions
+                .into_iter()
+                .filter(|version| *version < current)
+                .max()
+                .with_context(|| {
+                    format!(
+                        "There is no index version preceding the current one ({}) to roll back to",
+                        current
+                    )
+                })?,
+        };
+
+        Self::update_index_alias(elastic, target).await?;
+
+        Ok((current, target))
+    }
+}
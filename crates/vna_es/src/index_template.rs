@@ -0,0 +1,286 @@
+//! Index template that applies the analyzers and the mapping of the latest
+//! schema version to every `articles_*` index, including the ones created manually.
+
+use crate::{migrations, Article};
+use anyhow::{bail, Context, Result};
+use elasticsearch::{
+    http::StatusCode,
+    indices::{IndicesGetTemplateParts, IndicesPutTemplateParts},
+    Elasticsearch,
+};
+use serde_json::{json, Value};
+use std::path::Path;
+use vna_es_utils::{es_types, EsError};
+
+/// Analyzer of the text fields at index time
+pub const TEXT_ANALYZER: &str = "articles_text";
+/// Analyzer of the text fields at search time, it additionally expands the synonyms
+pub const TEXT_SEARCH_ANALYZER: &str = "articles_text_search";
+
+#[derive(Debug, Default)]
+pub struct IndexTemplateOpts {
+    /// Synonym rules in Solr format (e.g. `usa, united states` or `u.s. => usa`)
+    pub synonyms: Vec<String>,
+}
+
+impl IndexTemplateOpts {
+    /// Reads the synonym rules from the file, one rule per line.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn with_synonyms_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read synonyms file {}", path.display()))?;
+        Ok(Self {
+            synonyms: parse_synonyms(&content),
+        })
+    }
+}
+
+fn parse_synonyms(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+impl Article {
+    pub const INDEX_TEMPLATE: &'static str = "articles";
+
+    /// Creates the index template declaration object. Its version is the latest schema version.
+    pub fn index_template(opts: &IndexTemplateOpts) -> Value {
+        let schema = migrations::latest();
+        json!({
+            "index_patterns": [format!("{}_*", Self::INDEX_ALIAS)],
+            "version": schema.schema_version,
+            "settings": {
+                "index": {
+                    "analysis": Self::index_analysis(opts),
+                }
+            },
+            "mappings": {
+                "_meta": { "schema_version": schema.schema_version },
+                "properties": (schema.properties)(),
+            },
+        })
+    }
+
+    fn index_analysis(opts: &IndexTemplateOpts) -> Value {
+        let mut search_filters = vec![
+            "english_possessive_stemmer",
+            "lowercase",
+            "asciifolding",
+            "english_stop",
+        ];
+        // Synonym graph filter must be used only at search time
+        if !opts.synonyms.is_empty() {
+            search_filters.push("articles_synonyms");
+        }
+        search_filters.push("english_stemmer");
+
+        let mut analysis = json!({
+            "filter": {
+                "english_possessive_stemmer": {
+                    "type": "stemmer",
+                    "language": "possessive_english",
+                },
+                "english_stop": {
+                    "type": "stop",
+                    "stopwords": "_english_",
+                },
+                "english_stemmer": {
+                    "type": "stemmer",
+                    "language": "english",
+                },
+            },
+            "analyzer": {
+                TEXT_ANALYZER: {
+                    "type": "custom",
+                    "char_filter": ["html_strip"],
+                    "tokenizer": "standard",
+                    "filter": [
+                        "english_possessive_stemmer",
+                        "lowercase",
+                        "asciifolding",
+                        "english_stop",
+                        "english_stemmer",
+                    ],
+                },
+                TEXT_SEARCH_ANALYZER: {
+                    "type": "custom",
+                    "char_filter": ["html_strip"],
+                    "tokenizer": "standard",
+                    "filter": search_filters,
+                },
+            },
+        });
+        if !opts.synonyms.is_empty() {
+            analysis["filter"]["articles_synonyms"] = json!({
+                "type": "synonym_graph",
+                "synonyms": opts.synonyms,
+            });
+        }
+        analysis
+    }
+
+    /// Creates or replaces the index template. It affects only the indices created after that.
+    pub async fn put_index_template(
+        elastic: &Elasticsearch,
+        opts: &IndexTemplateOpts,
+    ) -> Result<()> {
+        let response = elastic
+            .indices()
+            .put_template(IndicesPutTemplateParts::Name(Self::INDEX_TEMPLATE))
+            .body(Self::index_template(opts))
+            .send()
+            .await?;
+
+        let response: es_types::AcknowledgedResponse =
+            vna_es_utils::check_status(response).await?.json().await?;
+
+        if !response.acknowledged {
+            bail!(
+                "Elasticsearch didn't acknowledge the update of '{}' index template",
+                Self::INDEX_TEMPLATE
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the index template registered in Elasticsearch, `None` if it doesn't exist
+    pub async fn fetch_index_template(
+        elastic: &Elasticsearch,
+    ) -> Result<Option<es_types::IndexTemplate>> {
+        let response = elastic
+            .indices()
+            .get_template(IndicesGetTemplateParts::Name(&[Self::INDEX_TEMPLATE]))
+            .send()
+            .await?;
+
+        let response = match vna_es_utils::check_status(response).await {
+            Ok(it) => it,
+            // Elasticsearch returns an empty object with 404 status for a missing template
+            Err(EsError::NotFound { .. }) => return Ok(None),
+            Err(EsError::Other { status, .. }) if status == StatusCode::NOT_FOUND => {
+                return Ok(None)
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut response: es_types::GetTemplatesResponse = response.json().await?;
+        Ok(response.0.remove(Self::INDEX_TEMPLATE))
+    }
+
+    /// Creates the index template if it doesn't exist or updates it if it has an older
+    /// schema version, otherwise the new indices would get a wrong mapping.
+    /// The synonyms of the outdated template are preserved.
+    pub async fn ensure_index_template(elastic: &Elasticsearch) -> Result<()> {
+        let latest = migrations::latest().schema_version;
+        let opts = match Self::fetch_index_template(elastic).await? {
+            Some(template) if template.version == Some(latest) => return Ok(()),
+            Some(template) if template.version > Some(latest) => bail!(
+                "Index template '{}' has version {}, which is newer than the latest \
+                schema version {} known to this version of vna",
+                Self::INDEX_TEMPLATE,
+                template.version.unwrap_or_default(),
+                latest
+            ),
+            Some(template) => {
+                log::info!(
+                    "Updating index template '{}' to schema version {}",
+                    Self::INDEX_TEMPLATE,
+                    latest
+                );
+                IndexTemplateOpts {
+                    synonyms: template_synonyms(&template),
+                }
+            }
+            None => {
+                log::info!("Creating index template '{}'", Self::INDEX_TEMPLATE);
+                IndexTemplateOpts::default()
+            }
+        };
+        Self::put_index_template(elastic, &opts).await
+    }
+}
+
+/// Returns the synonym rules the template was created with
+fn template_synonyms(template: &es_types::IndexTemplate) -> Vec<String> {
+    let synonyms = &template.settings["index"]["analysis"]["filter"]["articles_synonyms"];
+    synonyms["synonyms"]
+        .as_array()
+        .map(|rules| {
+            rules
+                .iter()
+                .filter_map(Value::as_str)
+                .map(ToOwned::to_owned)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_synonyms_file() {
+        let content = "
+            # Countries
+            usa, united states, u.s.
+
+            uk => united kingdom
+        ";
+        assert_eq!(
+            parse_synonyms(content),
+            ["usa, united states, u.s.", "uk => united kingdom"]
+        );
+    }
+
+    #[test]
+    fn reads_synonyms_of_existing_template() {
+        let template = |opts: &IndexTemplateOpts| -> es_types::IndexTemplate {
+            let mut template = Article::index_template(opts);
+            template["order"] = json!(0);
+            template["aliases"] = json!({});
+            serde_json::from_value(template).unwrap()
+        };
+
+        assert!(template_synonyms(&template(&IndexTemplateOpts::default())).is_empty());
+
+        let opts = IndexTemplateOpts {
+            synonyms: vec![
+                "usa, united states".to_owned(),
+                "uk => united kingdom".to_owned(),
+            ],
+        };
+        assert_eq!(template_synonyms(&template(&opts)), opts.synonyms);
+    }
+
+    #[test]
+    fn adds_synonyms_filter_only_at_search_time() {
+        let template = Article::index_template(&IndexTemplateOpts::default());
+        let analysis = &template["settings"]["index"]["analysis"];
+        assert!(analysis["filter"].get("articles_synonyms").is_none());
+
+        let template = Article::index_template(&IndexTemplateOpts {
+            synonyms: vec!["usa, united states".to_owned()],
+        });
+        let analysis = &template["settings"]["index"]["analysis"];
+        assert_eq!(
+            analysis["filter"]["articles_synonyms"]["synonyms"],
+            json!(["usa, united states"])
+        );
+
+        let filters = |analyzer: &str| analysis["analyzer"][analyzer]["filter"].clone();
+        let filters: [Value; 2] = [filters(TEXT_ANALYZER), filters(TEXT_SEARCH_ANALYZER)];
+        assert!(!filters[0]
+            .as_array()
+            .unwrap()
+            .contains(&json!("articles_synonyms")));
+        assert!(filters[1]
+            .as_array()
+            .unwrap()
+            .contains(&json!("articles_synonyms")));
+    }
+}
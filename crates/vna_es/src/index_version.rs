@@ -1,6 +1,21 @@
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct IndexVersion(u32);
 
+impl fmt::Display for IndexVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::str::FromStr for IndexVersion {
+    type Err = std::num::ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.parse()?))
+    }
+}
+
 impl Default for IndexVersion {
     fn default() -> Self {
         Self(1)
@@ -17,6 +32,17 @@ impl IndexVersion {
         Some(Self(name[index + 1..].parse().ok()?))
     }
 
+    /// Same as [`from_index_name`](Self::from_index_name), but also checks that
+    /// the name has exactly `<alias>_<version>` form
+    pub fn from_index_name_of_alias(name: &str, alias: &str) -> Option<Self> {
+        let version = Self::from_index_name(name)?;
+        if version.attach_to_alias(alias) == name {
+            Some(version)
+        } else {
+            None
+        }
+    }
+
     pub fn attach_to_alias(self, alias: &str) -> String {
         format!("{}_{}", alias, self.0)
     }
@@ -40,6 +66,15 @@ mod tests {
         assert_parses("blah_blah_bruh_42", Some(42));
     }
 
+    #[test]
+    fn parses_only_index_names_of_the_given_alias() {
+        let parse = |name| IndexVersion::from_index_name_of_alias(name, "articles");
+        assert_eq!(parse("articles_3"), Some(IndexVersion(3)));
+        assert_eq!(parse("articles_03"), None);
+        assert_eq!(parse("articles_backup_3"), None);
+        assert_eq!(parse("other_3"), None);
+    }
+
     #[test]
     fn returns_none_on_invalid_index_name_with_version() {
         assert_parses("", None);
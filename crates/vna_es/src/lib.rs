@@ -1,26 +1,55 @@
+mod article_filters;
+pub mod dates;
+mod dedup;
+mod index_alias;
+mod index_lifecycle;
+pub mod index_template;
 mod index_version;
-
-use anyhow::{Context, Result};
+mod mapping_diff;
+pub mod migrations;
+mod ndjson;
+mod scroll;
+pub mod snapshots;
+
+use anyhow::{bail, Context, Result};
+pub use article_filters::ArticleFilters;
+use chrono::{DateTime, FixedOffset, Utc};
+pub use dedup::{DuplicatesCluster, FindDuplicatesOpts};
 use elasticsearch::{
     http::response::Response as ElasticsearchResponse,
-    indices::{IndicesCreateParts, IndicesDeleteParts, IndicesGetAliasParts},
-    snapshot::SnapshotCreateRepositoryParts,
-    Elasticsearch, SearchParts,
+    indices::{IndicesCreateParts, IndicesDeleteParts, IndicesStatsParts},
+    params::Refresh,
+    DeleteParts, Elasticsearch, GetParts, SearchParts,
 };
+pub use index_alias::{resolve_alias_target, AliasRepair, AliasTarget, IndexAliasError};
+pub use index_lifecycle::{IndexVersionInfo, ValidateIndexOpts};
+pub use index_template::IndexTemplateOpts;
 pub use index_version::IndexVersion;
-use itertools::Itertools;
+pub use mapping_diff::MappingDiff;
+pub use migrations::{MigrateOpts, MigrationOutcome, MigrationProgress};
+pub use ndjson::{ExportOpts, ImportOpts, ImportOutcome};
+pub use scroll::ArticlesScroll;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::num::NonZeroU32;
-use std::{ops::Deref, path::Path};
-use vna_es_utils::{es_types, Success};
+use std::ops::Deref;
+use vna_es_utils::{es_types, EsError, Success};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct WithId<T> {
     pub id: String,
     pub doc: T,
 }
 
+impl<T> From<es_types::Doc<T>> for WithId<T> {
+    fn from(doc: es_types::Doc<T>) -> Self {
+        Self {
+            id: doc._id,
+            doc: doc._source,
+        }
+    }
+}
+
 /// Main document type which is stored in Elasticsearch
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Article {
@@ -29,83 +58,146 @@ pub struct Article {
     pub authors: String,
     pub link: String,
     pub short_description: String,
-    pub date: String,
+    #[serde(deserialize_with = "dates::deserialize_stored")]
+    pub date: DateTime<Utc>,
+    /// When the article was ingested, `None` for the articles ingested before
+    /// the field was introduced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingested_at: Option<DateTime<Utc>>,
     pub sentiment_score: f32,
     pub sentiment_polarity: SentimentPolarity,
+    /// Id of the article this one is a near-duplicate of (see `vna dedup --mark`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum SentimentPolarity {
     Positive,
     Negative,
 }
 
+impl std::str::FromStr for SentimentPolarity {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "positive" => Ok(Self::Positive),
+            "negative" => Ok(Self::Negative),
+            _ => Err("sentiment polarity must be either 'positive' or 'negative'"),
+        }
+    }
+}
+
+/// Size of the date buckets to split the articles into
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CalendarInterval {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl CalendarInterval {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Quarter => "quarter",
+            Self::Year => "year",
+        }
+    }
+}
+
+impl std::str::FromStr for CalendarInterval {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "day" => Self::Day,
+            "week" => Self::Week,
+            "month" => Self::Month,
+            "quarter" => Self::Quarter,
+            "year" => Self::Year,
+            _ => return Err("calendar interval must be one of: day, week, month, quarter, year"),
+        })
+    }
+}
+
 impl Article {
     pub const INDEX_ALIAS: &'static str = "articles";
 
-    /// Creates Elasticsearch article documents mapping declaration object
-    fn index_definition(opts: &CreateArticlesIndexOpts<'_>) -> impl Serialize {
-        json!({
+    /// Creates Elasticsearch article documents index declaration object.
+    /// The analyzers and the mapping of the latest schema version come from the index template.
+    fn index_definition(
+        opts: &CreateArticlesIndexOpts<'_>,
+        schema: &migrations::Migration,
+    ) -> impl Serialize {
+        let mut definition = json!({
             "settings": {
                 "index": {
                     "number_of_shards": opts.number_of_shards,
                     "number_of_replicas": opts.number_of_replicas,
                 }
             },
-            "mappings": {
-                "properties": {
-                    "category": {
-                        "type": "keyword",
-                        "index": true,
-                    },
-                    "headline": {
-                        "type": "text",
-                        "index": true,
-                    },
-                    "authors": {
-                        "type": "text",
-                        "index": true,
-                    },
-                    "link": {
-                        "type": "keyword",
-                        "index": true,
-                    },
-                    "short_description": {
-                        "type": "text",
-                        "index": true,
-                    },
-                    "date": {
-                        "type": "date",
-                        "index": true,
-                    },
-                    "sentiment_score": {
-                        "type": "float",
-                        "index": false,
-                    },
-                    "sentiment_polarity": {
-                        "type": "keyword",
-                        "index": true,
-                    }
-                }
-            },
-        })
+        });
+        if schema.schema_version != migrations::latest().schema_version {
+            definition["mappings"] = json!({
+                "_meta": { "schema_version": schema.schema_version },
+                "properties": (schema.properties)(),
+            });
+        }
+        definition
+    }
+
+    /// Returns the mappings of the articles fields of the latest schema version
+    pub fn index_properties() -> serde_json::Value {
+        (migrations::latest().properties)()
     }
 
     /// Crates articles index in elasticsearch.
     pub async fn create_index(opts: &CreateArticlesIndexOpts<'_>) -> Result<()> {
+        Self::create_index_with_schema(opts, migrations::latest()).await
+    }
+
+    pub(crate) async fn create_index_with_schema(
+        opts: &CreateArticlesIndexOpts<'_>,
+        schema: &migrations::Migration,
+    ) -> Result<()> {
+        Self::ensure_index_template(opts.elastic).await?;
+
         let index_name = opts.version.attach_to_alias(Article::INDEX_ALIAS);
-        opts.elastic
+        let response = opts
+            .elastic
             .indices()
             .create(IndicesCreateParts::Index(&index_name))
-            .body(Article::index_definition(opts))
+            .body(Article::index_definition(opts, schema))
             .wait_for_active_shards("all")
             .send()
             .await
-            .with_context(|| format!("Failed to create index '{}'", index_name))?
-            .success()
-            .await?;
-
-        Ok(())
+            .with_context(|| format!("Failed to create index '{}'", index_name))?;
+
+        match vna_es_utils::check_status(response).await {
+            Ok(response) => {
+                let response: es_types::CreateIndexResponse = response.json().await?;
+                if !response.shards_acknowledged {
+                    log::warn!(
+                        "Index '{}' was created, but not all of its shards became active in time",
+                        response.index
+                    );
+                }
+                Ok(())
+            }
+            Err(EsError::ResourceAlreadyExists { .. }) => bail!(
+                "Index '{}' already exists, it was probably left over by an interrupted \
+                data sync, delete it and try again",
+                index_name
+            ),
+            Err(err) => {
+                Err(err).with_context(|| format!("Failed to create index '{}'", index_name))
+            }
+        }
     }
 
     pub async fn delete_index(elastic: &Elasticsearch, version: IndexVersion) -> Result<()> {
@@ -121,75 +213,102 @@ impl Article {
         Ok(())
     }
 
-    pub async fn update_index_alias(
-        elastic: &Elasticsearch,
-        prev_version: Option<IndexVersion>,
-        new_version: IndexVersion,
-    ) -> Result<()> {
-        let mut actions = vec![];
-
-        if let Some(prev_version) = prev_version {
-            actions.push(json!({
-                "remove": {
-                    "index": prev_version.attach_to_alias(Self::INDEX_ALIAS),
-                    "alias": Self::INDEX_ALIAS,
-                }
-            }));
-        }
-
-        actions.push(json!({
-            "add": {
-                "index": new_version.attach_to_alias(Self::INDEX_ALIAS),
-                "alias": Self::INDEX_ALIAS,
-            }
-        }));
+    /// Returns the stats of the index the articles alias points to,
+    /// `None` if there is no such alias yet. If the alias points to several
+    /// indices the one chosen by [`fetch_index_version`] is summarized.
+    pub async fn fetch_index_summary(elastic: &Elasticsearch) -> Result<Option<IndexSummary>> {
+        let index_name = match Self::fetch_index_version(elastic).await? {
+            Some(version) => version.attach_to_alias(Self::INDEX_ALIAS),
+            None => return Ok(None),
+        };
 
-        elastic
+        let response: ElasticsearchResponse = elastic
             .indices()
-            .update_aliases()
-            .body(json!({ "actions": actions }))
+            .stats(IndicesStatsParts::IndexMetric(
+                &[&index_name],
+                &["docs", "store"],
+            ))
             .send()
-            .await?
-            .success()
             .await?;
 
-        Ok(())
+        let response = match vna_es_utils::check_status(response).await {
+            Ok(it) => it,
+            Err(EsError::IndexNotFound { .. }) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut response = response.json::<es_types::IndicesStatsResponse>().await?;
+
+        let summary = response
+            .indices
+            .remove(&index_name)
+            .map(|stats| IndexSummary {
+                index_name,
+                doc_count: stats.primaries.docs.map(|it| it.count).unwrap_or(0),
+                size_in_bytes: stats.total.store.map(|it| it.size_in_bytes).unwrap_or(0),
+            });
+
+        Ok(summary)
     }
 
-    pub async fn fetch_index_version(elastic: &Elasticsearch) -> Result<Option<IndexVersion>> {
+    /// Fetches the article by its id, returns `None` if there is no such article
+    pub async fn fetch_by_id(elastic: &Elasticsearch, id: &str) -> Result<Option<Article>> {
         let response: ElasticsearchResponse = elastic
-            .indices()
-            .get_alias(IndicesGetAliasParts::Name(&[Self::INDEX_ALIAS]))
+            .get(GetParts::IndexId(Self::INDEX_ALIAS, id))
             .send()
             .await?;
 
-        if response.status_code() == http::StatusCode::NOT_FOUND {
-            return Ok(None);
+        let response = match vna_es_utils::check_document_status(response).await? {
+            Some(it) => it,
+            None => return Ok(None),
+        };
+
+        let response = response.json::<es_types::GetResponse<Article>>().await?;
+
+        Ok(response._source)
+    }
+
+    /// Returns the names of all the categories the articles belong to
+    pub async fn fetch_categories(elastic: &Elasticsearch) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        pub struct Aggrs {
+            pub aggr: es_types::TermsAggr,
         }
-        response.error_for_status_code_ref()?;
 
-        let response = response.json::<es_types::GetAliasesResponse>().await?;
+        let result: es_types::AggrsResponse<Aggrs> = elastic
+            .search(SearchParts::Index(&[Self::INDEX_ALIAS]))
+            .body(json!({
+                // Don't return the document hits array, only the aggregration info
+                "size": 0,
+                "aggs": {
+                    "aggr": {
+                        "terms": {
+                            "field": "category",
+                            "size": 1000,
+                        }
+                    }
+                }
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
 
-        let (index_name,) = response
-            .0
+        Ok(result
+            .aggregations
+            .aggr
+            .buckets
             .into_iter()
-            .map(|(index_name, _aliases)| index_name)
-            .collect_tuple()
-            .unwrap_or_else(|| {
-                panic!("expected only one index with alias '{}'", Self::INDEX_ALIAS);
-            });
-
-        Ok(Some(
-            IndexVersion::from_index_name(&index_name)
-                .unwrap_or_else(|| panic!("Invalid index name: {}", &index_name)),
-        ))
+            .map(|it| it.key)
+            .collect())
     }
 
-    pub async fn fulltext_search(opts: FulltextSearchOpts<'_>) -> Result<Vec<WithId<Article>>> {
-        let query = match opts.field_name {
+    /// Creates the query that matches the given text by the field or by all text fields
+    pub(crate) fn fulltext_query(query: &str, field_name: Option<&str>) -> serde_json::Value {
+        match field_name {
             None => json!({
                 "multi_match": {
-                    "query": opts.query.deref(),
+                    "query": query,
                     "fields": [
                         "headline^2",
                         "authors",
@@ -197,27 +316,65 @@ impl Article {
                     ]
                 }
             }),
-            Some(field_name) => json!({ "match": { field_name: opts.query.deref() } }),
-        };
+            Some(field_name) => json!({ "match": { field_name: query } }),
+        }
+    }
+
+    pub async fn fulltext_search(opts: FulltextSearchOpts<'_>) -> Result<Vec<WithId<Article>>> {
+        let query = Self::fulltext_query(opts.query, opts.field_name);
+
+        let query = opts
+            .filters
+            .apply_to(json!({ "bool": { "should": [query] } }));
 
         let response: es_types::SearchResponse<Article> = opts
             .elastic
             .search(SearchParts::Index(&[Self::INDEX_ALIAS]))
-            .body(json!({ "query": { "bool": { "should": [query] } } }))
+            .body(json!({ "query": query }))
             .send()
             .await?
             .json()
             .await?;
 
-        Ok(response
-            .hits
-            .hits
-            .into_iter()
-            .map(|it| WithId {
-                id: it._id,
-                doc: it._source,
-            })
-            .collect())
+        Ok(response.hits.hits.into_iter().map(WithId::from).collect())
+    }
+
+    /// Finds the articles that are similar to the article with the given id
+    /// by their headline and short description
+    pub async fn find_similar(opts: FindSimilarOpts<'_>) -> Result<Vec<WithId<Article>>> {
+        let response: es_types::SearchResponse<Article> = opts
+            .elastic
+            .search(SearchParts::Index(&[Self::INDEX_ALIAS]))
+            .body(json!({
+                "size": opts.max_results,
+                "query": {
+                    "more_like_this": {
+                        "fields": ["headline", "short_description"],
+                        "like": [{ "_index": Self::INDEX_ALIAS, "_id": opts.id }],
+                        "min_term_freq": 1,
+                        "max_query_terms": 25,
+                    }
+                }
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        Ok(response.hits.hits.into_iter().map(WithId::from).collect())
+    }
+
+    /// Deletes the article by its id, returns `false` if there was no such article
+    pub async fn delete_by_id(elastic: &Elasticsearch, id: &str) -> Result<bool> {
+        let response: ElasticsearchResponse = elastic
+            .delete(DeleteParts::IndexId(Self::INDEX_ALIAS, id))
+            .refresh(Refresh::WaitFor)
+            .send()
+            .await?;
+
+        Ok(vna_es_utils::check_document_status(response)
+            .await?
+            .is_some())
     }
 
     pub async fn significant_words(
@@ -262,16 +419,67 @@ impl Article {
         Self::fetch_stats(opts, "category").await
     }
 
-    async fn fetch_stats(opts: StatsOpts<'_>, aggr_field: &str) -> Result<Stats> {
+    /// Returns the number of articles published in each date bucket of the given interval,
+    /// the buckets start at midnight in `timezone`
+    pub async fn timeline_stats(
+        opts: StatsOpts<'_>,
+        interval: CalendarInterval,
+        timezone: FixedOffset,
+    ) -> Result<Stats> {
         #[derive(Deserialize)]
         pub struct Aggrs {
-            pub aggr: es_types::TermsAggr,
+            pub aggr: es_types::DateHistogramAggr,
         }
 
+        let result: es_types::AggrsResponse<Aggrs> = opts
+            .elastic
+            .search(SearchParts::Index(&[Self::INDEX_ALIAS]))
+            .body(json!({
+                // Don't return the document hits array, only the aggregration info
+                "size": 0,
+                "query": Self::stats_query(&opts),
+                "aggs": {
+                    "aggr": {
+                        "date_histogram": {
+                            "field": "date",
+                            "calendar_interval": interval.as_str(),
+                            "time_zone": timezone.to_string(),
+                            "format": "yyyy-MM-dd",
+                        }
+                    }
+                }
+            }))
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        let stats = result
+            .aggregations
+            .aggr
+            .buckets
+            .into_iter()
+            .map(|it| (it.key_as_string, it.doc_count))
+            .collect();
+
+        Ok(Stats(stats))
+    }
+
+    fn stats_query(opts: &StatsOpts<'_>) -> serde_json::Value {
         let query = match opts.query {
             Some(query) => json!({ "match": { opts.field_name: query.deref() } }),
             None => json!({ "match_all": {} }),
         };
+        opts.filters.apply_to(query)
+    }
+
+    async fn fetch_stats(opts: StatsOpts<'_>, aggr_field: &str) -> Result<Stats> {
+        #[derive(Deserialize)]
+        pub struct Aggrs {
+            pub aggr: es_types::TermsAggr,
+        }
+
+        let query = Self::stats_query(&opts);
 
         let result: es_types::AggrsResponse<Aggrs> = opts
             .elastic
@@ -306,10 +514,21 @@ impl Article {
 
 pub struct Stats(pub Vec<(String, u64)>);
 
+/// Basic info about the index behind the articles alias
+#[derive(Debug)]
+pub struct IndexSummary {
+    pub index_name: String,
+    /// Number of documents in the primary shards
+    pub doc_count: u64,
+    /// Size of all the shards including replicas
+    pub size_in_bytes: u64,
+}
+
 pub struct StatsOpts<'a> {
     pub elastic: &'a Elasticsearch,
     pub query: &'a Option<stdx::NonHollowString>,
     pub field_name: &'a str,
+    pub filters: &'a ArticleFilters,
 }
 
 pub struct SignificantWordsOpts<'a> {
@@ -323,6 +542,13 @@ pub struct FulltextSearchOpts<'a> {
     pub elastic: &'a Elasticsearch,
     pub query: &'a stdx::NonHollowString,
     pub field_name: Option<&'a str>,
+    pub filters: &'a ArticleFilters,
+}
+
+pub struct FindSimilarOpts<'a> {
+    pub elastic: &'a Elasticsearch,
+    pub id: &'a str,
+    pub max_results: u32,
 }
 
 pub struct CreateArticlesIndexOpts<'a> {
@@ -331,80 +557,3 @@ pub struct CreateArticlesIndexOpts<'a> {
     pub number_of_shards: NonZeroU32,
     pub number_of_replicas: u32,
 }
-
-pub mod snapshots {
-    use super::*;
-    use elasticsearch::snapshot::SnapshotCreateParts;
-
-    pub async fn register_repo(
-        elastic: &Elasticsearch,
-        fs_path: &Path,
-        repo: &stdx::NonHollowString,
-    ) -> Result<()> {
-        let fs_path = fs_path
-            .to_str()
-            .context("Snapshot repo path contains invalid UTF8 characters")?;
-
-        elastic
-            .snapshot()
-            .create_repository(SnapshotCreateRepositoryParts::Repository(repo))
-            .body(json!({
-                "type": "fs",
-                "settings": { "location": fs_path, }
-            }))
-            .send()
-            .await?
-            .success()
-            .await?;
-
-        Ok(())
-    }
-
-    pub async fn take_snapshot(
-        elastic: &Elasticsearch,
-        repo: &stdx::NonHollowString,
-        snapshot: &stdx::NonHollowString,
-    ) -> Result<()> {
-        elastic
-            .snapshot()
-            .create(SnapshotCreateParts::RepositorySnapshot(repo, snapshot))
-            .wait_for_completion(true)
-            .send()
-            .await?
-            .success()
-            .await?;
-        Ok(())
-    }
-
-    pub async fn restore_from_snapshot(
-        elastic: &Elasticsearch,
-        repo: &stdx::NonHollowString,
-        snapshot: &stdx::NonHollowString,
-        index_name: &Option<stdx::NonHollowString>,
-    ) -> Result<()> {
-        let snap = elastic.snapshot();
-        let restore = snap.restore(
-            elasticsearch::snapshot::SnapshotRestoreParts::RepositorySnapshot(repo, snapshot),
-        );
-
-        if let Some(index_name) = index_name {
-            // comma-separated list of indices
-            restore
-                .body(json!({ "indices": index_name.deref() }))
-                .wait_for_completion(true)
-                .send()
-                .await?
-                .success()
-                .await?;
-        } else {
-            restore
-                .wait_for_completion(true)
-                .send()
-                .await?
-                .success()
-                .await?;
-        }
-
-        Ok(())
-    }
-}
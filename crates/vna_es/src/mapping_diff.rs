@@ -0,0 +1,135 @@
+//! Comparison of the live index mapping with the expected one
+
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt};
+
+/// Difference in the mapping of a single field
+#[derive(Debug, PartialEq)]
+pub enum MappingDiff {
+    /// The field is expected, but absent in the live mapping
+    Missing { field: String, expected: Value },
+    /// The field is present in the live mapping, but not expected
+    Unexpected { field: String, actual: Value },
+    Changed {
+        field: String,
+        expected: Value,
+        actual: Value,
+    },
+}
+
+impl MappingDiff {
+    pub fn field(&self) -> &str {
+        match self {
+            Self::Missing { field, .. }
+            | Self::Unexpected { field, .. }
+            | Self::Changed { field, .. } => field,
+        }
+    }
+}
+
+impl fmt::Display for MappingDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing { field, expected } => write!(f, "- {}: {}", field, expected),
+            Self::Unexpected { field, actual } => write!(f, "+ {}: {}", field, actual),
+            Self::Changed {
+                field,
+                expected,
+                actual,
+            } => write!(f, "~ {}: {} -> {}", field, expected, actual),
+        }
+    }
+}
+
+/// Returns the differences between the field mappings. Parameters that
+/// Elasticsearch omits when they have default values are not taken into account.
+pub fn diff_mappings(
+    expected: &BTreeMap<String, Value>,
+    actual: &BTreeMap<String, Value>,
+) -> Vec<MappingDiff> {
+    let mut diffs = vec![];
+
+    for (field, expected) in expected {
+        let expected = without_defaults(expected);
+        match actual.get(field) {
+            None => diffs.push(MappingDiff::Missing {
+                field: field.clone(),
+                expected,
+            }),
+            Some(actual) => {
+                let actual = without_defaults(actual);
+                if actual != expected {
+                    diffs.push(MappingDiff::Changed {
+                        field: field.clone(),
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+    }
+
+    for (field, actual) in actual {
+        if !expected.contains_key(field) {
+            diffs.push(MappingDiff::Unexpected {
+                field: field.clone(),
+                actual: without_defaults(actual),
+            });
+        }
+    }
+
+    diffs
+}
+
+fn without_defaults(mapping: &Value) -> Value {
+    let mut mapping = mapping.clone();
+    if let Value::Object(params) = &mut mapping {
+        if params.get("index") == Some(&Value::Bool(true)) {
+            params.remove("index");
+        }
+    }
+    mapping
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn properties(value: Value) -> BTreeMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn ignores_default_parameters() {
+        let expected = properties(json!({ "category": { "type": "keyword", "index": true } }));
+        let actual = properties(json!({ "category": { "type": "keyword" } }));
+        assert_eq!(diff_mappings(&expected, &actual), []);
+    }
+
+    #[test]
+    fn reports_all_kinds_of_differences() {
+        let expected = properties(json!({
+            "category": { "type": "keyword" },
+            "date": { "type": "date" },
+        }));
+        let actual = properties(json!({
+            "category": { "type": "text" },
+            "bruh": { "type": "long" },
+        }));
+
+        let diffs: Vec<_> = diff_mappings(&expected, &actual)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            diffs,
+            [
+                r#"~ category: {"type":"keyword"} -> {"type":"text"}"#,
+                r#"- date: {"type":"date"}"#,
+                r#"+ bruh: {"type":"long"}"#,
+            ]
+        );
+    }
+}
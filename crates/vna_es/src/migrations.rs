@@ -0,0 +1,634 @@
+//! Versioned schema migrations of the articles index.
+//!
+//! Each schema version declares the mapping of the articles fields and the transform
+//! of the documents from the previous version. `vna index migrate` copies the documents
+//! from the current index into the next version of the index applying the pending
+//! transforms and then moves the alias to it, so changing the mapping doesn't
+//! require re-ingesting the raw dataset.
+//!
+//! To change the mapping append a new [`Migration`] to [`MIGRATIONS`].
+
+use crate::{
+    index_template,
+    scroll::{self, SCROLL_KEEP_ALIVE},
+    Article, CreateArticlesIndexOpts, IndexVersion, ValidateIndexOpts,
+};
+use anyhow::{bail, Context, Result};
+use elasticsearch::{
+    tasks::{TasksCancelParts, TasksGetParts},
+    BulkParts, CountParts, Elasticsearch, ScrollParts, SearchParts,
+};
+use serde_json::{json, Map, Value};
+use std::{convert::TryFrom, num::NonZeroU32, time::Duration};
+use vna_es_utils::{es_types, Success};
+
+/// How often the progress of the `_reindex` task is checked
+const TASK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Transform of the article documents from the previous schema version
+pub enum Transform {
+    /// The documents are copied as is, e.g. when only the mapping parameters change
+    None,
+    /// Painless script run by the `_reindex` API with the document in `ctx._source`.
+    /// Scripts of several pending versions are concatenated, so they must not declare
+    /// variables with the same names.
+    Painless(&'static str),
+    /// Function applied to the document source on the client side
+    Rust(fn(&mut Map<String, Value>)),
+}
+
+pub struct Migration {
+    pub schema_version: u32,
+    pub description: &'static str,
+    /// Mapping of the articles fields as of this schema version
+    pub properties: fn() -> Value,
+    pub transform: Transform,
+}
+
+/// All the schema versions in ascending order
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        schema_version: 1,
+        description: "Initial schema",
+        properties: v1_properties,
+        transform: Transform::None,
+    },
+    Migration {
+        schema_version: 2,
+        description: "English analyzers with synonyms for the headline and the short description",
+        properties: v2_properties,
+        transform: Transform::None,
+    },
+    Migration {
+        schema_version: 3,
+        description: "Full timestamps in `date` and `ingested_at` timestamp",
+        properties: v3_properties,
+        // Dates were stored as `YYYY-MM-DD` before
+        transform: Transform::Painless(
+            "if (ctx._source.date instanceof String && ctx._source.date.length() == 10) { \
+                ctx._source.date += 'T00:00:00Z'; \
+            }",
+        ),
+    },
+];
+
+fn v1_properties() -> Value {
+    json!({
+        "category": {
+            "type": "keyword",
+            "index": true,
+        },
+        "headline": {
+            "type": "text",
+            "index": true,
+        },
+        "authors": {
+            "type": "text",
+            "index": true,
+        },
+        "link": {
+            "type": "keyword",
+            "index": true,
+        },
+        "short_description": {
+            "type": "text",
+            "index": true,
+        },
+        "date": {
+            "type": "date",
+            "index": true,
+        },
+        "sentiment_score": {
+            "type": "float",
+            "index": false,
+        },
+        "sentiment_polarity": {
+            "type": "keyword",
+            "index": true,
+        },
+        "duplicate_of": {
+            "type": "keyword",
+            "index": true,
+        }
+    })
+}
+
+fn v2_properties() -> Value {
+    let mut properties = v1_properties();
+    for field in &["headline", "short_description"] {
+        properties[*field] = json!({
+            "type": "text",
+            "analyzer": index_template::TEXT_ANALYZER,
+            "search_analyzer": index_template::TEXT_SEARCH_ANALYZER,
+        });
+    }
+    properties
+}
+
+fn v3_properties() -> Value {
+    let mut properties = v2_properties();
+    properties["ingested_at"] = json!({
+        "type": "date",
+        "index": true,
+    });
+    properties
+}
+
+pub fn latest() -> &'static Migration {
+    MIGRATIONS
+        .last()
+        .expect("there is at least one schema version")
+}
+
+pub fn find(schema_version: u32) -> Result<&'static Migration> {
+    MIGRATIONS
+        .iter()
+        .find(|it| it.schema_version == schema_version)
+        .with_context(|| {
+            format!(
+                "Unknown schema version {}, the latest one is {}",
+                schema_version,
+                latest().schema_version
+            )
+        })
+}
+
+/// Returns the schema version stored in the `_meta` of the mapping.
+/// Indices created before the migrations were introduced don't have it,
+/// they have the first schema version.
+pub(crate) fn schema_version_of(mapping: &es_types::TypeMapping) -> Result<u32> {
+    let version = match mapping
+        ._meta
+        .as_ref()
+        .and_then(|it| it.get("schema_version"))
+    {
+        Some(it) => it,
+        None => return Ok(1),
+    };
+    version
+        .as_u64()
+        .and_then(|it| u32::try_from(it).ok())
+        .with_context(|| format!("Invalid schema version in the index mapping: {}", version))
+}
+
+/// How the documents are copied into the new index
+enum CopyStrategy {
+    /// Via `_reindex` API with the optional painless script
+    Reindex(Option<String>),
+    /// Via scroll and bulk APIs applying the transforms on the client side
+    Client(Vec<fn(&mut Map<String, Value>)>),
+}
+
+fn copy_strategy(pending: &[&Migration]) -> Result<CopyStrategy> {
+    let has_rust_transforms = pending
+        .iter()
+        .any(|it| matches!(it.transform, Transform::Rust(_)));
+
+    if !has_rust_transforms {
+        let scripts: Vec<_> = pending
+            .iter()
+            .filter_map(|it| match it.transform {
+                Transform::Painless(script) => Some(script.trim()),
+                _ => None,
+            })
+            .collect();
+        let script = if scripts.is_empty() {
+            None
+        } else {
+            Some(scripts.join("\n"))
+        };
+        return Ok(CopyStrategy::Reindex(script));
+    }
+
+    if let Some(it) = pending
+        .iter()
+        .find(|it| matches!(it.transform, Transform::Painless(_)))
+    {
+        bail!(
+            "Painless transform of schema version {} can't be applied together with Rust \
+            transforms of the other pending versions, migrate one version at a time via `--to`",
+            it.schema_version
+        );
+    }
+
+    Ok(CopyStrategy::Client(
+        pending
+            .iter()
+            .filter_map(|it| match it.transform {
+                Transform::Rust(transform) => Some(transform),
+                _ => None,
+            })
+            .collect(),
+    ))
+}
+
+pub struct MigrateOpts<'a> {
+    pub elastic: &'a Elasticsearch,
+    /// Schema version to migrate to, the latest one if `None`
+    pub to_schema: Option<u32>,
+    pub number_of_shards: NonZeroU32,
+    pub number_of_replicas: u32,
+    /// Number of documents copied at once
+    pub batch_size: NonZeroU32,
+    pub on_progress: &'a (dyn Fn(MigrationProgress) + Sync),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct MigrationProgress {
+    /// Number of documents copied so far
+    pub processed: u64,
+    pub total: u64,
+}
+
+#[derive(Debug)]
+pub struct MigrationOutcome {
+    pub from_version: IndexVersion,
+    pub to_version: IndexVersion,
+    pub from_schema: u32,
+    pub to_schema: u32,
+    pub migrated_docs: u64,
+}
+
+impl Article {
+    pub async fn fetch_schema_version(
+        elastic: &Elasticsearch,
+        version: IndexVersion,
+    ) -> Result<u32> {
+        schema_version_of(&Self::fetch_index_mapping(elastic, version).await?)
+    }
+
+    /// Copies the documents from the index the alias points to into the next version
+    /// of the index applying the pending migrations and moves the alias to it.
+    /// If the migration fails the new index is deleted and the alias is left intact.
+    pub async fn migrate_index(opts: MigrateOpts<'_>) -> Result<MigrationOutcome> {
+        let elastic = opts.elastic;
+
+        let from_version = Self::fetch_index_version(elastic)
+            .await?
+            .with_context(|| format!("'{}' alias does not exist yet", Self::INDEX_ALIAS))?;
+        let from_schema = Self::fetch_schema_version(elastic, from_version).await?;
+
+        let target = match opts.to_schema {
+            Some(it) => find(it)?,
+            None => latest(),
+        };
+        if target.schema_version <= from_schema {
+            bail!(
+                "Index '{}' already has schema version {}, there is nothing to migrate",
+                from_version.attach_to_alias(Self::INDEX_ALIAS),
+                from_schema
+            );
+        }
+
+        let pending: Vec<_> = MIGRATIONS
+            .iter()
+            .filter(|it| it.schema_version > from_schema)
+            .filter(|it| it.schema_version <= target.schema_version)
+            .collect();
+        let strategy = copy_strategy(&pending)?;
+
+        let to_version = Self::next_index_version(elastic).await?;
+        Self::create_index_with_schema(
+            &CreateArticlesIndexOpts {
+                elastic,
+                version: to_version,
+                number_of_shards: opts.number_of_shards,
+                number_of_replicas: opts.number_of_replicas,
+            },
+            target,
+        )
+        .await?;
+
+        let migration = async {
+            let migrated_docs = match &strategy {
+                CopyStrategy::Reindex(script) => {
+                    Self::reindex(&opts, from_version, to_version, script.as_deref()).await?
+                }
+                CopyStrategy::Client(transforms) => {
+                    Self::copy_transformed(&opts, from_version, to_version, transforms).await?
+                }
+            };
+            Self::validate_index(ValidateIndexOpts {
+                elastic,
+                version: to_version,
+                expected_docs: migrated_docs,
+            })
+            .await?;
+            Ok::<_, anyhow::Error>(migrated_docs)
+        };
+
+        let migrated_docs = match migration.await {
+            Ok(it) => it,
+            Err(err) => {
+                let to_index = to_version.attach_to_alias(Self::INDEX_ALIAS);
+                if let Err(delete_err) = Self::delete_index(elastic, to_version).await {
+                    log::error!("Failed to delete index '{}': {:?}", to_index, delete_err);
+                }
+                return Err(err.context(format!(
+                    "Migration failed, index '{}' was discarded, '{}' alias was left intact",
+                    to_index,
+                    Self::INDEX_ALIAS
+                )));
+            }
+        };
+
+        Self::update_index_alias(elastic, to_version).await?;
+
+        Ok(MigrationOutcome {
+            from_version,
+            to_version,
+            from_schema,
+            to_schema: target.schema_version,
+            migrated_docs,
+        })
+    }
+
+    /// Copies the documents via `_reindex` API, returns the number of created documents
+    async fn reindex(
+        opts: &MigrateOpts<'_>,
+        from: IndexVersion,
+        to: IndexVersion,
+        script: Option<&str>,
+    ) -> Result<u64> {
+        let mut body = json!({
+            "source": {
+                "index": from.attach_to_alias(Self::INDEX_ALIAS),
+                "size": opts.batch_size,
+            },
+            "dest": {
+                "index": to.attach_to_alias(Self::INDEX_ALIAS),
+                "op_type": "create",
+            },
+        });
+        if let Some(script) = script {
+            body["script"] = json!({ "lang": "painless", "source": script });
+        }
+
+        let response = opts
+            .elastic
+            .reindex()
+            .wait_for_completion(false)
+            .refresh(true)
+            .body(body)
+            .send()
+            .await?;
+        let task: es_types::StartedTaskResponse =
+            vna_es_utils::check_status(response).await?.json().await?;
+
+        let response = loop {
+            tokio::time::delay_for(TASK_POLL_INTERVAL).await;
+
+            let response = match Self::fetch_reindex_task(opts.elastic, &task.task).await {
+                Ok(it) => it,
+                Err(err) => {
+                    // Otherwise the task keeps writing into the index that is going to be deleted
+                    if let Err(cancel_err) = Self::cancel_task(opts.elastic, &task.task).await {
+                        log::error!("Failed to cancel task '{}': {:?}", task.task, cancel_err);
+                    }
+                    return Err(err);
+                }
+            };
+
+            if let Some(status) = &response.task.status {
+                (opts.on_progress)(MigrationProgress {
+                    processed: status.created
+                        + status.updated
+                        + status.deleted
+                        + status.noops
+                        + status.version_conflicts,
+                    total: status.total,
+                });
+            }
+
+            if response.completed {
+                break response;
+            }
+        };
+
+        if let Some(error) = response.error {
+            bail!("Reindex task '{}' failed: {}", task.task, error);
+        }
+        let result = response.response.with_context(|| {
+            format!("Reindex task '{}' completed without a response", task.task)
+        })?;
+        if let Some(failure) = result.failures.first() {
+            bail!(
+                "Failed to reindex {} documents, the first failure: {}",
+                result.failures.len(),
+                failure
+            );
+        }
+        if result.timed_out {
+            bail!("Reindex task '{}' timed out", task.task);
+        }
+        Ok(result.status.created)
+    }
+
+    async fn fetch_reindex_task(
+        elastic: &Elasticsearch,
+        task_id: &str,
+    ) -> Result<es_types::GetTaskResponse<es_types::ReindexStatus, es_types::ReindexResponse>> {
+        let response = elastic
+            .tasks()
+            .get(TasksGetParts::TaskId(task_id))
+            .send()
+            .await?;
+        Ok(vna_es_utils::check_status(response).await?.json().await?)
+    }
+
+    async fn cancel_task(elastic: &Elasticsearch, task_id: &str) -> Result<()> {
+        elastic
+            .tasks()
+            .cancel(TasksCancelParts::TaskId(task_id))
+            .send()
+            .await?
+            .success()
+            .await?;
+        Ok(())
+    }
+
+    /// Copies the documents via scroll and bulk APIs applying the transforms to them.
+    /// Returns the number of copied documents.
+    async fn copy_transformed(
+        opts: &MigrateOpts<'_>,
+        from: IndexVersion,
+        to: IndexVersion,
+        transforms: &[fn(&mut Map<String, Value>)],
+    ) -> Result<u64> {
+        let mut scroll_id = None;
+        let copied = Self::copy_pages(opts, from, to, transforms, &mut scroll_id).await;
+
+        match scroll_id {
+            Some(scroll_id) => {
+                let cleared = scroll::clear_scroll(opts.elastic, &scroll_id).await;
+                scroll::with_cleared(copied, cleared)
+            }
+            None => copied,
+        }
+    }
+
+    /// Does the work of [`copy_transformed`](Self::copy_transformed),
+    /// `scroll_id` is set to the id of the scroll to clear afterwards
+    async fn copy_pages(
+        opts: &MigrateOpts<'_>,
+        from: IndexVersion,
+        to: IndexVersion,
+        transforms: &[fn(&mut Map<String, Value>)],
+        scroll_id: &mut Option<String>,
+    ) -> Result<u64> {
+        let elastic = opts.elastic;
+        let from_index = from.attach_to_alias(Self::INDEX_ALIAS);
+        let to_index = to.attach_to_alias(Self::INDEX_ALIAS);
+
+        let response = elastic
+            .count(CountParts::Index(&[&from_index]))
+            .send()
+            .await?;
+        let total = vna_es_utils::check_status(response)
+            .await?
+            .json::<es_types::CountResponse>()
+            .await?
+            .count;
+
+        let mut response = elastic
+            .search(SearchParts::Index(&[&from_index]))
+            .scroll(SCROLL_KEEP_ALIVE)
+            .body(json!({
+                "size": opts.batch_size,
+                "sort": ["_doc"],
+            }))
+            .send()
+            .await?;
+
+        let mut processed = 0;
+        loop {
+            let page: es_types::SearchResponse<Map<String, Value>> =
+                vna_es_utils::check_status(response).await?.json().await?;
+            *scroll_id = page._scroll_id;
+            let current_scroll_id = scroll_id
+                .as_deref()
+                .context("Elasticsearch didn't return the scroll id")?;
+
+            if page.hits.hits.is_empty() {
+                return Ok(processed);
+            }
+
+            let n_docs = page.hits.hits.len() as u64;
+            let mut body = Vec::with_capacity(page.hits.hits.len() * 2);
+            for doc in page.hits.hits {
+                let mut source = doc._source;
+                for transform in transforms {
+                    transform(&mut source);
+                }
+                body.push(json!({ "create": { "_id": doc._id } }).to_string());
+                body.push(Value::Object(source).to_string());
+            }
+
+            let bulk = elastic
+                .bulk(BulkParts::Index(&to_index))
+                .body(body)
+                .send()
+                .await?;
+            let bulk: es_types::BulkResponse =
+                vna_es_utils::check_status(bulk).await?.json().await?;
+
+            if let Some((item, error)) = bulk.failed_items().next() {
+                bail!(
+                    "Failed to copy {} documents, the error for '{}': {}",
+                    bulk.failed_items().count(),
+                    item._id.as_deref().unwrap_or_default(),
+                    error,
+                );
+            }
+
+            processed += n_docs;
+            (opts.on_progress)(MigrationProgress { processed, total });
+
+            response = elastic
+                .scroll(ScrollParts::None)
+                .body(json!({
+                    "scroll": SCROLL_KEEP_ALIVE,
+                    "scroll_id": current_scroll_id,
+                }))
+                .send()
+                .await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migration(schema_version: u32, transform: Transform) -> Migration {
+        Migration {
+            schema_version,
+            description: "",
+            properties: v1_properties,
+            transform,
+        }
+    }
+
+    fn add_field(doc: &mut Map<String, Value>) {
+        doc.insert("bruh".to_owned(), json!(42));
+    }
+
+    #[test]
+    fn schema_versions_are_consecutive() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.schema_version as usize, i + 1);
+        }
+        assert!(matches!(MIGRATIONS[0].transform, Transform::None));
+    }
+
+    #[test]
+    fn concatenates_painless_scripts() {
+        let pending = [
+            migration(2, Transform::Painless("ctx._source.a = 1;")),
+            migration(3, Transform::None),
+            migration(4, Transform::Painless("ctx._source.b = 2;\n")),
+        ];
+        let pending: Vec<_> = pending.iter().collect();
+        assert!(matches!(
+            copy_strategy(&pending).unwrap(),
+            CopyStrategy::Reindex(Some(script)) if script == "ctx._source.a = 1;\nctx._source.b = 2;"
+        ));
+        assert!(matches!(
+            copy_strategy(&pending[1..2]).unwrap(),
+            CopyStrategy::Reindex(None)
+        ));
+    }
+
+    #[test]
+    fn applies_rust_transforms_on_the_client_side() {
+        let pending = [
+            migration(2, Transform::Rust(add_field)),
+            migration(3, Transform::None),
+            migration(4, Transform::Painless("ctx._source.b = 2;")),
+        ];
+        let pending: Vec<_> = pending.iter().collect();
+
+        match copy_strategy(&pending[..2]).unwrap() {
+            CopyStrategy::Client(transforms) => {
+                let mut doc = Map::new();
+                transforms.iter().for_each(|transform| transform(&mut doc));
+                assert_eq!(Value::Object(doc), json!({ "bruh": 42 }));
+            }
+            CopyStrategy::Reindex(_) => panic!("expected the client side copy"),
+        }
+        assert!(copy_strategy(&pending).is_err());
+    }
+
+    #[test]
+    fn reads_schema_version_from_mapping_meta() {
+        let mapping = |meta: Value| -> es_types::TypeMapping {
+            serde_json::from_value(json!({ "_meta": meta })).unwrap()
+        };
+        assert_eq!(schema_version_of(&mapping(json!(null))).unwrap(), 1);
+        assert_eq!(
+            schema_version_of(&mapping(json!({ "schema_version": 3 }))).unwrap(),
+            3
+        );
+        assert!(schema_version_of(&mapping(json!({ "schema_version": "3" }))).is_err());
+    }
+}
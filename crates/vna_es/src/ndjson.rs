@@ -0,0 +1,248 @@
+//! Export and import of the articles as newline-delimited JSON files that are
+//! independent of the Elasticsearch version and of the snapshot repositories.
+//!
+//! Each line is an article with its id: `{"id": "...", "doc": {...}}`.
+//! Files with `.gz` extension are (de)compressed with gzip.
+
+use crate::{
+    Article, ArticleFilters, ArticlesScroll, CreateArticlesIndexOpts, IndexVersion,
+    ValidateIndexOpts, WithId,
+};
+use anyhow::{bail, Context, Result};
+use elasticsearch::{params::Refresh, BulkParts, Elasticsearch};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use serde_json::json;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    num::NonZeroU32,
+    path::Path,
+};
+use vna_es_utils::es_types;
+
+pub struct ExportOpts<'a> {
+    pub elastic: &'a Elasticsearch,
+    /// Path of the file to create, it is gzipped if it has `.gz` extension
+    pub path: &'a Path,
+    pub filters: &'a ArticleFilters,
+    /// Fulltext query to export only the matching articles
+    pub query: Option<&'a str>,
+    /// Number of articles fetched at once
+    pub batch_size: NonZeroU32,
+    /// Called with the number of articles exported so far
+    pub on_progress: &'a (dyn Fn(u64) + Sync),
+}
+
+pub struct ImportOpts<'a> {
+    pub elastic: &'a Elasticsearch,
+    /// Path of the file to import, it is gunzipped if it has `.gz` extension
+    pub path: &'a Path,
+    pub number_of_shards: NonZeroU32,
+    pub number_of_replicas: u32,
+    /// Number of articles ingested at once
+    pub batch_size: NonZeroU32,
+    /// Called with the number of articles imported so far
+    pub on_progress: &'a (dyn Fn(u64) + Sync),
+}
+
+#[derive(Debug)]
+pub struct ImportOutcome {
+    /// Version of the created index the articles alias now points to
+    pub version: IndexVersion,
+    pub total_imported: u64,
+}
+
+fn is_gzipped(path: &Path) -> bool {
+    path.extension().map_or(false, |it| it == "gz")
+}
+
+/// Parses the line of the exported file, `line_no` is used in the error message
+fn parse_line(line_no: usize, line: &str) -> Result<WithId<Article>> {
+    serde_json::from_str(line).with_context(|| format!("Invalid article at line {}", line_no))
+}
+
+impl Article {
+    /// Streams all the articles that match the filters and the query into the file.
+    /// Returns the number of exported articles.
+    pub async fn export_ndjson(opts: ExportOpts<'_>) -> Result<u64> {
+        let file = File::create(opts.path)
+            .with_context(|| format!("Failed to create {}", opts.path.display()))?;
+        let out = BufWriter::new(file);
+
+        if is_gzipped(opts.path) {
+            let mut out = GzEncoder::new(out, Compression::default());
+            let exported = Self::write_ndjson(&opts, &mut out).await?;
+            out.finish()?.flush()?;
+            Ok(exported)
+        } else {
+            let mut out = out;
+            let exported = Self::write_ndjson(&opts, &mut out).await?;
+            out.flush()?;
+            Ok(exported)
+        }
+    }
+
+    async fn write_ndjson(opts: &ExportOpts<'_>, out: &mut impl Write) -> Result<u64> {
+        let mut scroll = ArticlesScroll::new(opts.elastic, opts.filters, opts.batch_size.get());
+        if let Some(query) = opts.query {
+            scroll = scroll.with_query(query);
+        }
+
+        let mut exported = 0;
+        loop {
+            let batch = scroll.next_batch().await?;
+            if batch.is_empty() {
+                break;
+            }
+            for article in &batch {
+                serde_json::to_writer(&mut *out, article)?;
+                out.write_all(b"\n")?;
+            }
+            exported += batch.len() as u64;
+            (opts.on_progress)(exported);
+        }
+        scroll.clear().await?;
+
+        Ok(exported)
+    }
+
+    /// Bulk-loads the exported articles with their ids into the new version of the index
+    /// and points the articles alias to it. The index is deleted if anything goes wrong.
+    pub async fn import_ndjson(opts: ImportOpts<'_>) -> Result<ImportOutcome> {
+        let file = File::open(opts.path)
+            .with_context(|| format!("Failed to open {}", opts.path.display()))?;
+        let input: Box<dyn BufRead + Send> = if is_gzipped(opts.path) {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(BufReader::new(file))
+        };
+
+        let elastic = opts.elastic;
+        let version = Self::next_index_version(elastic).await?;
+        Self::create_index(&CreateArticlesIndexOpts {
+            elastic,
+            version,
+            number_of_shards: opts.number_of_shards,
+            number_of_replicas: opts.number_of_replicas,
+        })
+        .await?;
+
+        let import = async {
+            let total_imported = Self::bulk_ndjson(&opts, input, version).await?;
+            Self::validate_index(ValidateIndexOpts {
+                elastic,
+                version,
+                expected_docs: total_imported,
+            })
+            .await?;
+            Ok::<_, anyhow::Error>(total_imported)
+        };
+
+        let total_imported = match import.await {
+            Ok(it) => it,
+            Err(err) => {
+                let index_name = version.attach_to_alias(Self::INDEX_ALIAS);
+                if let Err(delete_err) = Self::delete_index(elastic, version).await {
+                    log::error!("Failed to delete index '{}': {:?}", index_name, delete_err);
+                }
+                return Err(err.context(format!(
+                    "Import failed, index '{}' was discarded, '{}' alias was left intact",
+                    index_name,
+                    Self::INDEX_ALIAS
+                )));
+            }
+        };
+
+        Self::update_index_alias(elastic, version).await?;
+
+        Ok(ImportOutcome {
+            version,
+            total_imported,
+        })
+    }
+
+    async fn bulk_ndjson(
+        opts: &ImportOpts<'_>,
+        input: impl BufRead,
+        version: IndexVersion,
+    ) -> Result<u64> {
+        let index_name = version.attach_to_alias(Self::INDEX_ALIAS);
+        let batch_size = opts.batch_size.get() as usize;
+
+        let mut lines = input.lines().enumerate();
+        let mut imported = 0;
+        loop {
+            let mut body = Vec::with_capacity(batch_size * 2);
+            for (i, line) in lines.by_ref() {
+                let line = line.with_context(|| format!("Failed to read line {}", i + 1))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let article = parse_line(i + 1, &line)?;
+                body.push(json!({ "index": { "_id": article.id } }).to_string());
+                body.push(serde_json::to_string(&article.doc)?);
+                if body.len() == batch_size * 2 {
+                    break;
+                }
+            }
+            if body.is_empty() {
+                return Ok(imported);
+            }
+
+            let n_docs = body.len() as u64 / 2;
+            let response = opts
+                .elastic
+                .bulk(BulkParts::Index(&index_name))
+                .body(body)
+                .refresh(Refresh::WaitFor)
+                .send()
+                .await?;
+            let response: es_types::BulkResponse =
+                vna_es_utils::check_status(response).await?.json().await?;
+
+            if let Some((item, error)) = response.failed_items().next() {
+                bail!(
+                    "Failed to import {} articles, the error for '{}': {}",
+                    response.failed_items().count(),
+                    item._id.as_deref().unwrap_or_default(),
+                    error,
+                );
+            }
+
+            imported += n_docs;
+            (opts.on_progress)(imported);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_gzipped_files() {
+        assert!(is_gzipped(Path::new("articles.ndjson.gz")));
+        assert!(!is_gzipped(Path::new("articles.ndjson")));
+        assert!(!is_gzipped(Path::new("gz")));
+    }
+
+    #[test]
+    fn parses_exported_lines() {
+        let line = r#"{"id":"abc","doc":{"category":"CRIME","headline":"Headline","authors":"","link":"https://example.com","short_description":"","date":"2018-05-26T10:30:00Z","ingested_at":"2020-06-15T10:30:15Z","sentiment_score":0.5,"sentiment_polarity":"Positive"}}"#;
+        let article = parse_line(1, line).unwrap();
+        assert_eq!(article.id, "abc");
+        assert_eq!(article.doc.category, "CRIME");
+        assert_eq!(serde_json::to_string(&article).unwrap(), line);
+
+        // Exported before the dates were normalised to full timestamps
+        let old_line = line
+            .replace("2018-05-26T10:30:00Z", "2018-05-26")
+            .replace(r#""ingested_at":"2020-06-15T10:30:15Z","#, "");
+        let article = parse_line(1, &old_line).unwrap();
+        assert_eq!(article.doc.date.to_rfc3339(), "2018-05-26T00:00:00+00:00");
+        assert_eq!(article.doc.ingested_at, None);
+
+        let err = parse_line(42, r#"{"id":"abc"}"#).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid article at line 42");
+    }
+}
@@ -0,0 +1,116 @@
+use crate::{Article, ArticleFilters, WithId};
+use anyhow::Result;
+use elasticsearch::{ClearScrollParts, Elasticsearch, ScrollParts, SearchParts};
+use serde_json::json;
+use vna_es_utils::{es_types, Success};
+
+/// How long Elasticsearch keeps the search context alive between the batches
+pub(crate) const SCROLL_KEEP_ALIVE: &str = "2m";
+
+/// Iterates thru all the articles that match the filters batch by batch
+/// via Elasticsearch scroll API
+pub struct ArticlesScroll<'a> {
+    elastic: &'a Elasticsearch,
+    filters: &'a ArticleFilters,
+    query: Option<&'a str>,
+    batch_size: u32,
+    scroll_id: Option<String>,
+    exhausted: bool,
+}
+
+impl<'a> ArticlesScroll<'a> {
+    pub fn new(elastic: &'a Elasticsearch, filters: &'a ArticleFilters, batch_size: u32) -> Self {
+        Self {
+            elastic,
+            filters,
+            query: None,
+            batch_size,
+            scroll_id: None,
+            exhausted: false,
+        }
+    }
+
+    /// Iterates only thru the articles that match the fulltext query
+    pub fn with_query(mut self, query: &'a str) -> Self {
+        self.query = Some(query);
+        self
+    }
+
+    /// Returns the next batch of articles, the batch is empty once all the articles were returned
+    pub async fn next_batch(&mut self) -> Result<Vec<WithId<Article>>> {
+        if self.exhausted {
+            return Ok(vec![]);
+        }
+
+        let response = match &self.scroll_id {
+            None => {
+                self.elastic
+                    .search(SearchParts::Index(&[Article::INDEX_ALIAS]))
+                    .scroll(SCROLL_KEEP_ALIVE)
+                    .body(json!({
+                        "size": self.batch_size,
+                        // Sorting by `_doc` is the most efficient order for scrolling
+                        "sort": ["_doc"],
+                        "query": self.filters.apply_to(match self.query {
+                            Some(query) => Article::fulltext_query(query, None),
+                            None => json!({ "match_all": {} }),
+                        }),
+                    }))
+                    .send()
+                    .await?
+            }
+            Some(scroll_id) => {
+                self.elastic
+                    .scroll(ScrollParts::None)
+                    .body(json!({
+                        "scroll": SCROLL_KEEP_ALIVE,
+                        "scroll_id": scroll_id,
+                    }))
+                    .send()
+                    .await?
+            }
+        };
+
+        response.error_for_status_code_ref()?;
+        let response: es_types::SearchResponse<Article> = response.json().await?;
+
+        self.scroll_id = response._scroll_id;
+        self.exhausted = response.hits.hits.is_empty();
+
+        Ok(response.hits.hits.into_iter().map(WithId::from).collect())
+    }
+
+    /// Releases the search context in Elasticsearch
+    pub async fn clear(self) -> Result<()> {
+        match self.scroll_id {
+            Some(scroll_id) => clear_scroll(self.elastic, &scroll_id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Releases the search context of the scroll in Elasticsearch
+pub(crate) async fn clear_scroll(elastic: &Elasticsearch, scroll_id: &str) -> Result<()> {
+    elastic
+        .clear_scroll(ClearScrollParts::None)
+        .body(json!({ "scroll_id": [scroll_id] }))
+        .send()
+        .await?
+        .success()
+        .await?;
+    Ok(())
+}
+
+/// Combines the result of scrolling with the result of clearing the scroll afterwards.
+/// The scrolling error takes precedence, the clearing error is only logged then.
+pub(crate) fn with_cleared<T>(result: Result<T>, cleared: Result<()>) -> Result<T> {
+    match (result, cleared) {
+        (Ok(it), cleared) => cleared.map(|()| it),
+        (Err(err), cleared) => {
+            if let Err(clear_err) = cleared {
+                log::error!("Failed to clear the scroll: {:?}", clear_err);
+            }
+            Err(err)
+        }
+    }
+}
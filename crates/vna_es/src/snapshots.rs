@@ -0,0 +1,856 @@
+//! Management of the cluster snapshots
+
+use crate::{Article, IndexVersion};
+use anyhow::{bail, Context, Result};
+use elasticsearch::{
+    http::StatusCode,
+    indices::{IndicesCloseParts, IndicesDeleteParts, IndicesExistsParts},
+    snapshot::{
+        SnapshotCreateParts, SnapshotCreateRepositoryParts, SnapshotDeleteParts, SnapshotGetParts,
+        SnapshotRestoreParts, SnapshotStatusParts, SnapshotVerifyRepositoryParts,
+    },
+    Elasticsearch,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fmt,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use vna_es_utils::{es_types, EsError};
+
+/// Which snapshots to delete when pruning the old ones. Only the snapshots named
+/// by [`timestamped_snapshot_name`] with `prefix` are affected.
+#[derive(Debug)]
+pub struct RetentionPolicy<'a> {
+    pub prefix: &'a str,
+    /// Number of the most recent snapshots to keep regardless of their age
+    pub keep_last: Option<u32>,
+    /// Snapshots older than that are deleted unless they are kept by `keep_last`
+    pub max_age: Option<Duration>,
+}
+
+impl RetentionPolicy<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.keep_last.is_none() && self.max_age.is_none()
+    }
+}
+
+/// Type of the snapshot repository
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepoType {
+    /// Shared file system directory, it must be listed in `path.repo` of every node
+    Fs,
+    /// Read-only repository available via `http(s)`, `ftp`, `file` or `jar` urls,
+    /// remote urls must be listed in `repositories.url.allowed_urls` of every node
+    Url,
+    /// S3-compatible bucket, requires `repository-s3` plugin on every node.
+    /// The endpoint and the credentials are configured per `s3.client.<name>` on the nodes.
+    S3,
+}
+
+impl fmt::Display for RepoType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Fs => "fs",
+            Self::Url => "url",
+            Self::S3 => "s3",
+        })
+    }
+}
+
+impl std::str::FromStr for RepoType {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fs" => Ok(Self::Fs),
+            "url" => Ok(Self::Url),
+            "s3" => Ok(Self::S3),
+            _ => Err("repo type must be one of: fs, url, s3"),
+        }
+    }
+}
+
+/// Where the repository keeps the snapshots
+#[derive(Debug)]
+pub enum RepoLocation<'a> {
+    Fs {
+        path: &'a Path,
+    },
+    Url {
+        url: &'a str,
+    },
+    S3 {
+        bucket: &'a str,
+        /// Name of the `s3.client.<name>` settings on the nodes (`default` if not specified)
+        client: Option<&'a str>,
+        /// Path inside the bucket, the root of the bucket if not specified
+        base_path: Option<&'a str>,
+    },
+}
+
+impl RepoLocation<'_> {
+    pub fn repo_type(&self) -> RepoType {
+        match self {
+            Self::Fs { .. } => RepoType::Fs,
+            Self::Url { .. } => RepoType::Url,
+            Self::S3 { .. } => RepoType::S3,
+        }
+    }
+}
+
+impl fmt::Display for RepoLocation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fs { path } => write!(f, "{}", path.display()),
+            Self::Url { url } => f.write_str(url),
+            Self::S3 {
+                bucket, base_path, ..
+            } => match base_path {
+                Some(base_path) => write!(f, "s3://{}/{}", bucket, base_path),
+                None => write!(f, "s3://{}", bucket),
+            },
+        }
+    }
+}
+
+/// Settings common to all the writable repository types
+#[derive(Debug, Default)]
+pub struct RepoSettings<'a> {
+    /// Compress the index metadata files (Elasticsearch default is `true`)
+    pub compress: Option<bool>,
+    /// Split the big files into chunks of the given size, e.g. `1gb` or `500mb`
+    pub chunk_size: Option<&'a str>,
+    /// Register the repository for restoring only, this makes it safe to register
+    /// the same repository in several clusters
+    pub readonly: bool,
+}
+
+pub struct RegisterRepoOpts<'a> {
+    pub elastic: &'a Elasticsearch,
+    pub repo: &'a stdx::NonHollowString,
+    pub location: RepoLocation<'a>,
+    pub settings: RepoSettings<'a>,
+}
+
+/// Creates the body of the create repository request
+fn repo_definition(location: &RepoLocation<'_>, opts: &RepoSettings<'_>) -> Result<Value> {
+    let mut settings = match location {
+        RepoLocation::Fs { path } => {
+            let path = path
+                .to_str()
+                .context("Snapshot repo path contains invalid UTF8 characters")?;
+            json!({ "location": path })
+        }
+        RepoLocation::Url { url } => {
+            if opts.compress.is_some() || opts.chunk_size.is_some() {
+                bail!(
+                    "Url repositories are read-only, \
+                    they don't support compress and chunk size settings"
+                );
+            }
+            // Url repositories are always read-only, so the setting is not needed
+            return Ok(json!({
+                "type": RepoType::Url,
+                "settings": { "url": url },
+            }));
+        }
+        RepoLocation::S3 {
+            bucket,
+            client,
+            base_path,
+        } => {
+            let mut settings = json!({ "bucket": bucket });
+            if let Some(client) = client {
+                settings["client"] = json!(client);
+            }
+            if let Some(base_path) = base_path {
+                settings["base_path"] = json!(base_path);
+            }
+            settings
+        }
+    };
+    if let Some(compress) = opts.compress {
+        settings["compress"] = json!(compress);
+    }
+    if let Some(chunk_size) = opts.chunk_size {
+        settings["chunk_size"] = json!(chunk_size);
+    }
+    if opts.readonly {
+        settings["readonly"] = json!(true);
+    }
+    Ok(json!({
+        "type": location.repo_type(),
+        "settings": settings,
+    }))
+}
+
+/// Creates or updates the snapshot repository. Elasticsearch verifies
+/// that all the nodes have access to it in the process.
+pub async fn register_repo(opts: &RegisterRepoOpts<'_>) -> Result<()> {
+    let response = opts
+        .elastic
+        .snapshot()
+        .create_repository(SnapshotCreateRepositoryParts::Repository(opts.repo))
+        .body(repo_definition(&opts.location, &opts.settings)?)
+        .send()
+        .await?;
+
+    let response: es_types::AcknowledgedResponse =
+        vna_es_utils::check_status(response).await?.json().await?;
+
+    if !response.acknowledged {
+        bail!(
+            "Elasticsearch didn't acknowledge the registration of snapshot repo '{}'",
+            opts.repo
+        );
+    }
+    Ok(())
+}
+
+/// Checks that all the nodes have access to the repository.
+/// Returns the names of the nodes.
+pub async fn verify_repo(
+    elastic: &Elasticsearch,
+    repo: &stdx::NonHollowString,
+) -> Result<Vec<String>> {
+    let response = elastic
+        .snapshot()
+        .verify_repository(SnapshotVerifyRepositoryParts::Repository(repo))
+        .send()
+        .await?;
+
+    let response: es_types::VerifyRepositoryResponse =
+        vna_es_utils::check_status(response).await?.json().await?;
+
+    let mut nodes: Vec<_> = response
+        .nodes
+        .into_iter()
+        .map(|(_, node)| node.name)
+        .collect();
+    nodes.sort();
+    Ok(nodes)
+}
+
+/// Returns the snapshot name with the current UTC time appended to the prefix in
+/// Elasticsearch date math format, e.g. `vna_snapshot-2020.06.07-13.09.48`.
+/// The actual name is returned in [`SnapshotInfo`](es_types::SnapshotInfo).
+pub fn timestamped_snapshot_name(prefix: &str) -> String {
+    format!("<{}-{{now{{yyyy.MM.dd-HH.mm.ss}}}}>", prefix)
+}
+
+/// Checks if the snapshot was named by [`timestamped_snapshot_name`] with the prefix,
+/// so other snapshots that merely start with the prefix are never touched
+pub fn is_timestamped_snapshot(name: &str, prefix: &str) -> bool {
+    // `0` stands for any digit
+    const TIMESTAMP: &str = "-0000.00.00-00.00.00";
+
+    if !name.starts_with(prefix) {
+        return false;
+    }
+    let timestamp = &name[prefix.len()..];
+    timestamp.len() == TIMESTAMP.len()
+        && timestamp
+            .bytes()
+            .zip(TIMESTAMP.bytes())
+            .all(|(actual, expected)| match expected {
+                b'0' => actual.is_ascii_digit(),
+                _ => actual == expected,
+            })
+}
+
+/// Creates the snapshot of the given indices or of the whole cluster if `indices` is empty
+/// and waits for its completion
+pub async fn take_snapshot(
+    elastic: &Elasticsearch,
+    repo: &stdx::NonHollowString,
+    snapshot: &str,
+    indices: &[&str],
+) -> Result<es_types::SnapshotInfo> {
+    let create = elastic
+        .snapshot()
+        .create(SnapshotCreateParts::RepositorySnapshot(repo, snapshot))
+        .wait_for_completion(true);
+
+    let response = if indices.is_empty() {
+        create.send().await?
+    } else {
+        create
+            .body(json!({
+                "indices": indices.join(","),
+                "include_global_state": false,
+            }))
+            .send()
+            .await?
+    };
+
+    let response: es_types::CreateSnapshotResponse =
+        vna_es_utils::check_status(response).await?.json().await?;
+
+    response
+        .snapshot
+        .context("Snapshot info is missing in the response with `wait_for_completion`")
+}
+
+/// Returns all the snapshots in the repository from the oldest to the newest
+pub async fn list_snapshots(
+    elastic: &Elasticsearch,
+    repo: &stdx::NonHollowString,
+) -> Result<Vec<es_types::SnapshotInfo>> {
+    let response = elastic
+        .snapshot()
+        .get(SnapshotGetParts::RepositorySnapshot(repo, &["_all"]))
+        .send()
+        .await?;
+
+    let mut response: es_types::GetSnapshotsResponse =
+        vna_es_utils::check_status(response).await?.json().await?;
+
+    response
+        .snapshots
+        .sort_by_key(|it| it.start_time_in_millis.unwrap_or_default());
+    Ok(response.snapshots)
+}
+
+/// Returns the detailed status of the snapshot, or of all the running snapshots if `None`
+pub async fn snapshot_status(
+    elastic: &Elasticsearch,
+    repo: &stdx::NonHollowString,
+    snapshot: Option<&str>,
+) -> Result<Vec<es_types::SnapshotStatus>> {
+    let snapshot = snapshot.map(|it| [it]);
+    let parts = match &snapshot {
+        Some(snapshot) => SnapshotStatusParts::RepositorySnapshot(repo, snapshot),
+        None => SnapshotStatusParts::Repository(repo),
+    };
+    let response = elastic.snapshot().status(parts).send().await?;
+
+    let response: es_types::SnapshotStatusResponse =
+        vna_es_utils::check_status(response).await?.json().await?;
+
+    Ok(response.snapshots)
+}
+
+pub async fn delete_snapshot(
+    elastic: &Elasticsearch,
+    repo: &stdx::NonHollowString,
+    snapshot: &str,
+) -> Result<()> {
+    let response = elastic
+        .snapshot()
+        .delete(SnapshotDeleteParts::RepositorySnapshot(repo, snapshot))
+        .send()
+        .await?;
+
+    let response: es_types::AcknowledgedResponse =
+        vna_es_utils::check_status(response).await?.json().await?;
+
+    if !response.acknowledged {
+        bail!(
+            "Elasticsearch didn't acknowledge the deletion of snapshot '{}'",
+            snapshot
+        );
+    }
+    Ok(())
+}
+
+/// Deletes the snapshots that are expired according to the policy.
+/// Returns the names of the deleted snapshots.
+pub async fn prune_snapshots(
+    elastic: &Elasticsearch,
+    repo: &stdx::NonHollowString,
+    policy: &RetentionPolicy<'_>,
+) -> Result<Vec<String>> {
+    if policy.is_empty() {
+        return Ok(vec![]);
+    }
+    let snapshots = list_snapshots(elastic, repo).await?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("System time is before the unix epoch")?;
+
+    let expired: Vec<_> = select_expired(&snapshots, policy, now)
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect();
+
+    for snapshot in &expired {
+        delete_snapshot(elastic, repo, snapshot).await?;
+    }
+    Ok(expired)
+}
+
+/// Returns the names of the snapshots to delete. The snapshots must be sorted from
+/// the oldest to the newest, `now` is the time since the unix epoch.
+/// Running snapshots and the latest successful one are never deleted.
+fn select_expired<'s>(
+    snapshots: &'s [es_types::SnapshotInfo],
+    policy: &RetentionPolicy<'_>,
+    now: Duration,
+) -> Vec<&'s str> {
+    if policy.is_empty() {
+        return vec![];
+    }
+
+    let candidates: Vec<_> = snapshots
+        .iter()
+        .filter(|it| is_timestamped_snapshot(&it.snapshot, policy.prefix))
+        .filter(|it| it.state != "IN_PROGRESS")
+        .collect();
+
+    let latest_successful = candidates.iter().rev().find(|it| it.state == "SUCCESS");
+
+    let keep_last = policy.keep_last.unwrap_or(0) as usize;
+
+    candidates
+        .iter()
+        .rev()
+        .skip(keep_last)
+        .filter(|it| match policy.max_age {
+            Some(max_age) => {
+                let started = Duration::from_millis(it.start_time_in_millis.unwrap_or_default());
+                now.checked_sub(started).map_or(false, |age| age > max_age)
+            }
+            // Only `keep_last` is specified
+            None => true,
+        })
+        .filter(|it| latest_successful.map_or(true, |latest| latest.snapshot != it.snapshot))
+        .map(|it| it.snapshot.as_str())
+        .collect()
+}
+
+/// What to do with the existing indices that have the same names as the restored ones
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Don't restore anything
+    Fail,
+    /// Close the existing indices, they are overwritten by the restored ones
+    Close,
+    /// Delete the existing indices
+    Delete,
+}
+
+impl std::str::FromStr for OnConflict {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(Self::Fail),
+            "close" => Ok(Self::Close),
+            "delete" => Ok(Self::Delete),
+            _ => Err("conflict action must be one of: fail, close, delete"),
+        }
+    }
+}
+
+pub struct RestoreOpts<'a> {
+    pub elastic: &'a Elasticsearch,
+    pub repo: &'a str,
+    pub snapshot: &'a str,
+    /// Names or wildcard patterns of the indices to restore, all the indices if empty
+    pub indices: &'a [String],
+    /// Regex the restored index names are matched against, e.g. `articles_(.+)`
+    pub rename_pattern: Option<&'a str>,
+    /// Replacement of the matched part of the index name, e.g. `restored_articles_$1`
+    pub rename_replacement: Option<&'a str>,
+    pub include_global_state: bool,
+}
+
+/// Indices the snapshot restore would create
+#[derive(Debug)]
+pub struct RestorePlan {
+    /// Names of the indices in the snapshot to restore
+    pub indices: Vec<String>,
+    /// Existing indices that have the same names as the restored ones. They are looked up
+    /// only when the indices are not renamed, since Elasticsearch applies the rename
+    /// replacement with Java regex semantics which we don't reproduce.
+    pub conflicts: Vec<String>,
+}
+
+impl RestoreOpts<'_> {
+    fn rename(&self) -> Result<Option<(&str, &str)>> {
+        match (self.rename_pattern, self.rename_replacement) {
+            (Some(pattern), Some(replacement)) => Ok(Some((pattern, replacement))),
+            (None, None) => Ok(None),
+            _ => bail!("Rename pattern and replacement must be specified together"),
+        }
+    }
+}
+
+/// Resolves the indices to restore and finds the existing ones that conflict with them
+pub async fn plan_restore(opts: &RestoreOpts<'_>) -> Result<RestorePlan> {
+    let rename = opts.rename()?;
+
+    let response = opts
+        .elastic
+        .snapshot()
+        .get(SnapshotGetParts::RepositorySnapshot(
+            opts.repo,
+            &[opts.snapshot],
+        ))
+        .send()
+        .await?;
+    let mut response: es_types::GetSnapshotsResponse =
+        vna_es_utils::check_status(response).await?.json().await?;
+
+    let info = response
+        .snapshots
+        .pop()
+        .with_context(|| format!("Snapshot '{}' was not found", opts.snapshot))?;
+
+    let indices: Vec<_> = info
+        .indices
+        .into_iter()
+        .filter(|index| {
+            opts.indices.is_empty()
+                || opts
+                    .indices
+                    .iter()
+                    .any(|pattern| matches_wildcard(pattern, index))
+        })
+        .collect();
+
+    if indices.is_empty() {
+        bail!(
+            "Snapshot '{}' doesn't contain indices matching [{}]",
+            opts.snapshot,
+            opts.indices.join(", ")
+        );
+    }
+
+    let mut conflicts = vec![];
+    if rename.is_none() {
+        for index in &indices {
+            let response = opts
+                .elastic
+                .indices()
+                .exists(IndicesExistsParts::Index(&[index]))
+                .send()
+                .await?;
+            match response.status_code() {
+                StatusCode::OK => conflicts.push(index.clone()),
+                StatusCode::NOT_FOUND => {}
+                _ => return Err(EsError::from_response(response).await.into()),
+            }
+        }
+    }
+
+    Ok(RestorePlan { indices, conflicts })
+}
+
+/// Restores the indices according to the plan resolving the conflicts with the existing indices.
+/// The restored indices can't be closed or deleted when they are renamed, in this case
+/// Elasticsearch fails the restore if there are open indices with the same names.
+pub async fn restore_snapshot(
+    opts: &RestoreOpts<'_>,
+    plan: &RestorePlan,
+    on_conflict: OnConflict,
+) -> Result<es_types::RestoreInfo> {
+    let elastic = opts.elastic;
+    let rename = opts.rename()?;
+
+    if rename.is_some() && on_conflict != OnConflict::Fail {
+        bail!(
+            "Conflicting indices can't be closed or deleted when the restored indices are renamed, \
+            since their names are known only after the restore"
+        );
+    }
+
+    if !plan.conflicts.is_empty() {
+        let conflicts: Vec<_> = plan.conflicts.iter().map(String::as_str).collect();
+        match on_conflict {
+            OnConflict::Fail => bail!(
+                "Indices [{}] already exist, close or delete them first or restore them \
+                under different names via the rename pattern",
+                conflicts.join(", ")
+            ),
+            OnConflict::Close => {
+                let response = elastic
+                    .indices()
+                    .close(IndicesCloseParts::Index(&conflicts))
+                    .send()
+                    .await?;
+                vna_es_utils::check_status(response).await?;
+            }
+            OnConflict::Delete => {
+                let response = elastic
+                    .indices()
+                    .delete(IndicesDeleteParts::Index(&conflicts))
+                    .send()
+                    .await?;
+                vna_es_utils::check_status(response).await?;
+            }
+        }
+    }
+
+    let mut body = json!({
+        "indices": plan.indices.join(","),
+        "include_global_state": opts.include_global_state,
+    });
+    if let Some((pattern, replacement)) = rename {
+        body["rename_pattern"] = json!(pattern);
+        body["rename_replacement"] = json!(replacement);
+    }
+
+    let response = elastic
+        .snapshot()
+        .restore(SnapshotRestoreParts::RepositorySnapshot(
+            opts.repo,
+            opts.snapshot,
+        ))
+        .body(body)
+        .wait_for_completion(true)
+        .send()
+        .await?;
+
+    let response: es_types::RestoreSnapshotResponse =
+        vna_es_utils::check_status(response).await?.json().await?;
+
+    response
+        .snapshot
+        .context("Restore info is missing in the response with `wait_for_completion`")
+}
+
+/// Points the articles alias to the restored articles index, there must be exactly one
+/// restored index with `articles_<version>` name. The names are taken from the restore
+/// response, so they are the actual ones even if the indices were renamed.
+pub async fn point_alias_to_restored(
+    elastic: &Elasticsearch,
+    restored: &es_types::RestoreInfo,
+) -> Result<IndexVersion> {
+    let versions: Vec<_> = restored
+        .indices
+        .iter()
+        .filter_map(|it| IndexVersion::from_index_name_of_alias(it, Article::INDEX_ALIAS))
+        .collect();
+
+    let version = match versions.as_slice() {
+        [it] => *it,
+        [] => bail!(
+            "None of the restored indices is named as '{}_<version>', \
+            so the alias can't point to it",
+            Article::INDEX_ALIAS
+        ),
+        _ => bail!(
+            "Several articles indices were restored, restore only one of them \
+            to point the alias to it"
+        ),
+    };
+
+    Article::update_index_alias(elastic, version).await?;
+    Ok(version)
+}
+
+/// Checks if the name matches the pattern where `*` matches any sequence of characters
+fn matches_wildcard(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    if !name.starts_with(first) {
+        return false;
+    }
+    let mut rest = &name[first.len()..];
+    let parts: Vec<_> = parts.collect();
+    let (last, middle) = match parts.split_last() {
+        Some(it) => it,
+        // No wildcards
+        None => return rest.is_empty(),
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Returns the name of the most recent successful snapshot named by
+/// [`timestamped_snapshot_name`] with the given prefix
+pub async fn latest_snapshot(
+    elastic: &Elasticsearch,
+    repo: &stdx::NonHollowString,
+    prefix: &str,
+) -> Result<String> {
+    list_snapshots(elastic, repo)
+        .await?
+        .into_iter()
+        .rev()
+        .find(|it| is_timestamped_snapshot(&it.snapshot, prefix) && it.state == "SUCCESS")
+        .map(|it| it.snapshot)
+        .with_context(|| {
+            format!(
+                "There are no successful snapshots with '{}' prefix in '{}' repo",
+                prefix, repo
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_repo_definitions() {
+        let fs = RepoLocation::Fs {
+            path: Path::new("/mnt/snapshots"),
+        };
+        assert_eq!(
+            repo_definition(&fs, &RepoSettings::default()).unwrap(),
+            json!({ "type": "fs", "settings": { "location": "/mnt/snapshots" } })
+        );
+
+        let s3 = RepoLocation::S3 {
+            bucket: "vna",
+            client: Some("minio"),
+            base_path: None,
+        };
+        let settings = RepoSettings {
+            compress: Some(false),
+            chunk_size: Some("100mb"),
+            readonly: true,
+        };
+        assert_eq!(
+            repo_definition(&s3, &settings).unwrap(),
+            json!({
+                "type": "s3",
+                "settings": {
+                    "bucket": "vna",
+                    "client": "minio",
+                    "compress": false,
+                    "chunk_size": "100mb",
+                    "readonly": true,
+                }
+            })
+        );
+
+        let url = RepoLocation::Url {
+            url: "http://example.com/snapshots/",
+        };
+        let readonly = RepoSettings {
+            readonly: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            repo_definition(&url, &readonly).unwrap(),
+            json!({ "type": "url", "settings": { "url": "http://example.com/snapshots/" } })
+        );
+        assert!(repo_definition(&url, &settings).is_err());
+    }
+
+    const DAY: u64 = 24 * 60 * 60 * 1000;
+
+    fn snapshots(states: &[(&str, &str, u64)]) -> Vec<es_types::SnapshotInfo> {
+        states
+            .iter()
+            .map(|(name, state, started_day)| {
+                serde_json::from_value(json!({
+                    "snapshot": name,
+                    "uuid": name,
+                    "indices": ["articles_1"],
+                    "state": state,
+                    "start_time_in_millis": started_day * DAY,
+                }))
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn policy(keep_last: Option<u32>, max_age_days: Option<u64>) -> RetentionPolicy<'static> {
+        RetentionPolicy {
+            prefix: "vna",
+            keep_last,
+            max_age: max_age_days.map(|it| Duration::from_millis(it * DAY)),
+        }
+    }
+
+    #[test]
+    fn keeps_last_snapshots() {
+        let snapshots = snapshots(&[
+            ("vna-2020.06.01-03.00.00", "SUCCESS", 1),
+            ("other-2020.06.02-03.00.00", "SUCCESS", 2),
+            ("vna-2020.06.03-03.00.00", "FAILED", 3),
+            ("vna-2020.06.04-03.00.00", "SUCCESS", 4),
+            ("vna-2020.06.05-03.00.00", "IN_PROGRESS", 5),
+        ]);
+        let now = Duration::from_millis(10 * DAY);
+
+        assert_eq!(
+            select_expired(&snapshots, &policy(Some(1), None), now),
+            ["vna-2020.06.03-03.00.00", "vna-2020.06.01-03.00.00"]
+        );
+        assert!(select_expired(&snapshots, &policy(None, None), now).is_empty());
+    }
+
+    #[test]
+    fn deletes_snapshots_older_than_max_age() {
+        let snapshots = snapshots(&[
+            ("vna-2020.06.01-03.00.00", "SUCCESS", 1),
+            ("vna-2020.06.02-03.00.00", "PARTIAL", 2),
+            ("vna-2020.06.08-03.00.00", "SUCCESS", 8),
+        ]);
+        let now = Duration::from_millis(10 * DAY);
+
+        assert_eq!(
+            select_expired(&snapshots, &policy(None, Some(5)), now),
+            ["vna-2020.06.02-03.00.00", "vna-2020.06.01-03.00.00"]
+        );
+        assert_eq!(
+            select_expired(&snapshots, &policy(Some(2), Some(5)), now),
+            ["vna-2020.06.01-03.00.00"]
+        );
+    }
+
+    #[test]
+    fn prunes_only_timestamped_snapshots_with_the_prefix() {
+        let snapshots = snapshots(&[
+            ("vna", "SUCCESS", 1),
+            ("vna_manual", "SUCCESS", 1),
+            ("vna-before-upgrade", "SUCCESS", 1),
+            ("vna-data-sync-2020.06.01-03.00.00", "SUCCESS", 1),
+            ("vna-2020.06.01-03.00.00", "SUCCESS", 1),
+            ("vna-2020.06.02-03.00.00", "SUCCESS", 2),
+        ]);
+        let now = Duration::from_millis(10 * DAY);
+
+        assert_eq!(
+            select_expired(&snapshots, &policy(Some(0), Some(5)), now),
+            ["vna-2020.06.01-03.00.00"]
+        );
+
+        assert!(is_timestamped_snapshot("vna-2020.06.07-13.09.48", "vna"));
+        assert!(!is_timestamped_snapshot(
+            "vna-2020.06.07-13.09.48",
+            "vna-2020"
+        ));
+        assert!(!is_timestamped_snapshot("vna-2020.06.07-13.09.4", "vna"));
+        assert!(!is_timestamped_snapshot("vna-2020.06.07-13.09.48-x", "vna"));
+        assert!(!is_timestamped_snapshot("vna-2020.06.07T13.09.48", "vna"));
+    }
+
+    #[test]
+    fn matches_wildcard_patterns() {
+        assert!(matches_wildcard("articles_1", "articles_1"));
+        assert!(!matches_wildcard("articles_1", "articles_10"));
+        assert!(matches_wildcard("articles_*", "articles_10"));
+        assert!(matches_wildcard("*", ".kibana_1"));
+        assert!(matches_wildcard("*_1*", "articles_10"));
+        assert!(!matches_wildcard("a*a", "a"));
+        assert!(!matches_wildcard("articles_*", ".kibana_1"));
+    }
+
+    #[test]
+    fn never_deletes_the_latest_successful_snapshot() {
+        let snapshots = snapshots(&[
+            ("vna-2020.06.01-03.00.00", "SUCCESS", 1),
+            ("vna-2020.06.02-03.00.00", "FAILED", 2),
+        ]);
+        let now = Duration::from_millis(10 * DAY);
+
+        assert_eq!(
+            select_expired(&snapshots, &policy(Some(0), Some(5)), now),
+            ["vna-2020.06.02-03.00.00"]
+        );
+    }
+}
@@ -1,7 +1,19 @@
 use elasticsearch::cat::CatIndicesParts;
-use std::num::NonZeroU32;
-use vna_es::{Article, CreateArticlesIndexOpts, IndexVersion};
-use vna_es_utils::elasticsearch_client_or_return;
+use http::{Method, StatusCode};
+use serde_json::json;
+use std::{
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use vna_es::{
+    Article, ArticleFilters, CreateArticlesIndexOpts, FindDuplicatesOpts, FindSimilarOpts,
+    IndexTemplateOpts, IndexVersion,
+};
+use vna_es_utils::{elasticsearch_client_or_return, es_types, EsError, HealthStatus};
+use vna_test_utils::{article_json, error_response, hits_response, mock_elasticsearch};
 
 #[tokio::test]
 async fn create_index_works() {
@@ -24,12 +36,187 @@ async fn create_index_works() {
         .await
         .unwrap();
 
-    let res: serde_json::Value = res.json().await.unwrap();
+    let res: Vec<es_types::CatIndicesRecord> = res.json().await.unwrap();
 
-    assert_eq!(res[0]["index"], serde_json::json! {"articles_v1"});
-    assert_eq!(res[0]["health"], serde_json::json! {"green"});
+    assert_eq!(res[0].index, "articles_v1");
+    assert_eq!(res[0].health, Some(HealthStatus::Green));
 
     Article::delete_index(&elastic, IndexVersion::default())
         .await
         .unwrap();
 }
+
+#[tokio::test]
+async fn fetch_by_id_returns_none_for_missing_article() {
+    let elastic = mock_elasticsearch(|method, path, _| {
+        assert_eq!(method, Method::GET);
+        assert_eq!(path, "/articles/_doc/missing");
+        let body = json!({ "_index": "articles_1", "_id": "missing", "found": false });
+        (StatusCode::NOT_FOUND, body)
+    });
+
+    let article = Article::fetch_by_id(&elastic, "missing").await.unwrap();
+    assert!(article.is_none());
+}
+
+#[tokio::test]
+async fn fetch_by_id_fails_when_articles_index_is_missing() {
+    let elastic = mock_elasticsearch(|_, _, _| {
+        error_response(
+            StatusCode::NOT_FOUND,
+            "index_not_found_exception",
+            "no such index [articles]",
+        )
+    });
+
+    let err = Article::fetch_by_id(&elastic, "42").await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<EsError>(),
+        Some(EsError::IndexNotFound { .. })
+    ));
+
+    let err = Article::delete_by_id(&elastic, "42").await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<EsError>(),
+        Some(EsError::IndexNotFound { .. })
+    ));
+}
+
+#[tokio::test]
+async fn fetch_index_summary_uses_write_index_of_ambiguous_alias() {
+    let elastic = mock_elasticsearch(|method, path, _| {
+        assert_eq!(method, Method::GET);
+        let body = if path == "/_alias/articles" {
+            json!({
+                "articles_1": { "aliases": { "articles": {} } },
+                "articles_2": { "aliases": { "articles": { "is_write_index": true } } },
+            })
+        } else {
+            assert!(path.starts_with("/articles_2/_stats/"), "{}", path);
+            json!({
+                "_shards": { "total": 2, "successful": 1, "failed": 0 },
+                "indices": {
+                    "articles_2": {
+                        "primaries": { "docs": { "count": 42, "deleted": 0 } },
+                        "total": { "store": { "size_in_bytes": 1024 } },
+                    }
+                }
+            })
+        };
+        (StatusCode::OK, body)
+    });
+
+    let summary = Article::fetch_index_summary(&elastic)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(summary.index_name, "articles_2");
+    assert_eq!(summary.doc_count, 42);
+    assert_eq!(summary.size_in_bytes, 1024);
+}
+
+#[tokio::test]
+async fn ensure_index_template_keeps_synonyms_of_up_to_date_template() {
+    let template = Article::index_template(&IndexTemplateOpts {
+        synonyms: vec!["usa, united states".to_owned()],
+    });
+    let elastic = mock_elasticsearch(move |method, path, _| {
+        assert_eq!(method, Method::GET, "the template must not be replaced");
+        assert_eq!(path, "/_template/articles");
+        let mut template = template.clone();
+        template["order"] = json!(0);
+        template["aliases"] = json!({});
+        (StatusCode::OK, json!({ "articles": template }))
+    });
+
+    Article::ensure_index_template(&elastic).await.unwrap();
+}
+
+#[tokio::test]
+async fn find_similar_sends_more_like_this_query() {
+    let elastic = mock_elasticsearch(|method, path, body| {
+        assert_eq!(method, Method::POST);
+        assert_eq!(path, "/articles/_search");
+
+        let body = body.unwrap();
+        assert_eq!(body["size"], 5);
+        assert_eq!(
+            body["query"]["more_like_this"],
+            json!({
+                "fields": ["headline", "short_description"],
+                "like": [{ "_index": "articles", "_id": "42" }],
+                "min_term_freq": 1,
+                "max_query_terms": 25,
+            })
+        );
+
+        let body = hits_response(&[("43", article_json("Similar headline"))]);
+        (StatusCode::OK, body)
+    });
+
+    let similar = Article::find_similar(FindSimilarOpts {
+        elastic: &elastic,
+        id: "42",
+        max_results: 5,
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(similar.len(), 1);
+    assert_eq!(similar[0].id, "43");
+    assert_eq!(similar[0].doc.headline, "Similar headline");
+}
+
+#[tokio::test]
+async fn delete_by_id_returns_false_for_missing_article() {
+    let elastic = mock_elasticsearch(|method, path, _| {
+        assert_eq!(method, Method::DELETE);
+        assert_eq!(path, "/articles/_doc/missing");
+        let body = json!({
+            "_index": "articles_1",
+            "_id": "missing",
+            "result": "not_found",
+        });
+        (StatusCode::NOT_FOUND, body)
+    });
+
+    let deleted = Article::delete_by_id(&elastic, "missing").await.unwrap();
+    assert!(!deleted);
+}
+
+#[tokio::test]
+async fn find_duplicates_clears_scroll_when_candidates_search_fails() {
+    let scroll_cleared = Arc::new(AtomicBool::new(false));
+
+    let elastic = mock_elasticsearch({
+        let scroll_cleared = Arc::clone(&scroll_cleared);
+        move |method, path, _| match (method.as_str(), path) {
+            ("POST", "/articles/_search") => {
+                let mut body = hits_response(&[("1", article_json("Headline"))]);
+                body["_scroll_id"] = json!("scroll-1");
+                (StatusCode::OK, body)
+            }
+            ("POST", "/articles/_msearch") => error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "search_phase_execution_exception",
+                "all shards failed",
+            ),
+            ("DELETE", "/_search/scroll") => {
+                scroll_cleared.store(true, Ordering::SeqCst);
+                (StatusCode::OK, json!({ "succeeded": true, "num_freed": 1 }))
+            }
+            _ => panic!("Unexpected request: {} {}", method, path),
+        }
+    });
+
+    let result = Article::find_duplicates(FindDuplicatesOpts {
+        elastic: &elastic,
+        filters: &ArticleFilters::default(),
+        threshold: 0.5,
+        max_candidates: 10,
+    })
+    .await;
+
+    assert!(result.is_err());
+    assert!(scroll_cleared.load(Ordering::SeqCst));
+}
@@ -0,0 +1,239 @@
+//! Configurable construction of the Elasticsearch client
+
+use crate::es_types;
+use anyhow::{bail, Context, Result};
+use elasticsearch::{
+    auth::{ClientCertificate, Credentials},
+    cert::{Certificate, CertificateValidation},
+    http::transport::{
+        CloudConnectionPool, Connection, ConnectionPool, SingleNodeConnectionPool, TransportBuilder,
+    },
+    nodes::NodesInfoParts,
+    Elasticsearch,
+};
+use std::{
+    fmt::Debug,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use url::Url;
+
+/// Options to connect to Elasticsearch cluster with
+#[derive(Debug, Default, Clone)]
+pub struct ConnectionOpts {
+    /// Seed nodes urls, requests are distributed among them in round-robin fashion
+    pub urls: Vec<Url>,
+    /// Id of Elastic Cloud deployment, if specified `urls` must be empty
+    pub cloud_id: Option<String>,
+    /// Whether to discover all the http nodes of the cluster via the seed nodes
+    pub sniff: bool,
+    pub auth: Option<Auth>,
+    /// Path to PEM-encoded CA certificate to validate the server certificates with
+    pub ca_cert: Option<PathBuf>,
+    /// Disables the validation of the server certificates altogether
+    pub insecure: bool,
+    /// Timeout for each request to Elasticsearch
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Auth {
+    Basic {
+        username: String,
+        password: String,
+    },
+    ApiKey {
+        id: String,
+        key: String,
+    },
+    /// PKCS#12 archive with the client certificate and its private key
+    ClientCert {
+        path: PathBuf,
+        password: Option<String>,
+    },
+}
+
+impl ConnectionOpts {
+    pub fn single_node(url: Url) -> Self {
+        Self {
+            urls: vec![url],
+            ..Self::default()
+        }
+    }
+}
+
+/// Creates Elasticsearch client according to the given options.
+/// Sends a request to the seed nodes only if sniffing is enabled.
+pub async fn connect(opts: &ConnectionOpts) -> Result<Elasticsearch> {
+    if let Some(cloud_id) = &opts.cloud_id {
+        if !opts.urls.is_empty() {
+            bail!("Elasticsearch urls must not be specified together with Elastic Cloud id");
+        }
+        let pool = CloudConnectionPool::new(cloud_id)
+            .with_context(|| format!("Invalid Elastic Cloud id '{}'", cloud_id))?;
+        return build_client(opts, pool);
+    }
+
+    match opts.urls.as_slice() {
+        [] => bail!("At least one Elasticsearch url must be specified"),
+        [url] if !opts.sniff => build_client(opts, SingleNodeConnectionPool::new(url.clone())),
+        urls => {
+            let seeds = RoundRobinConnectionPool::new(urls.to_vec());
+            if !opts.sniff {
+                return build_client(opts, seeds);
+            }
+            let urls = sniff_nodes(&build_client(opts, seeds)?, &urls[0])
+                .await
+                .context("Failed to sniff Elasticsearch nodes")?;
+
+            log::debug!("Sniffed Elasticsearch nodes: {:?}", urls);
+
+            build_client(opts, RoundRobinConnectionPool::new(urls))
+        }
+    }
+}
+
+fn build_client<P>(opts: &ConnectionOpts, pool: P) -> Result<Elasticsearch>
+where
+    P: ConnectionPool + Debug + Clone + Send + 'static,
+{
+    let mut transport = TransportBuilder::new(pool).disable_proxy();
+
+    if let Some(auth) = &opts.auth {
+        transport = transport.auth(credentials(auth)?);
+    }
+
+    if opts.insecure {
+        transport = transport.cert_validation(CertificateValidation::None);
+    } else if let Some(path) = &opts.ca_cert {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("Failed to read CA certificate at {}", path.display()))?;
+        let cert = Certificate::from_pem(&pem)
+            .with_context(|| format!("Invalid PEM CA certificate at {}", path.display()))?;
+        transport = transport.cert_validation(CertificateValidation::Full(cert));
+    }
+
+    if let Some(timeout) = opts.timeout {
+        transport = transport.timeout(timeout);
+    }
+
+    Ok(Elasticsearch::new(transport.build()?))
+}
+
+fn credentials(auth: &Auth) -> Result<Credentials> {
+    Ok(match auth {
+        Auth::Basic { username, password } => {
+            Credentials::Basic(username.clone(), password.clone())
+        }
+        Auth::ApiKey { id, key } => Credentials::ApiKey(id.clone(), key.clone()),
+        Auth::ClientCert { path, password } => {
+            let pkcs12 = std::fs::read(path).with_context(|| {
+                format!("Failed to read client certificate at {}", path.display())
+            })?;
+            Credentials::Certificate(ClientCertificate::Pkcs12(pkcs12, password.clone()))
+        }
+    })
+}
+
+/// Returns the urls of all the nodes in the cluster that have http enabled
+async fn sniff_nodes(elastic: &Elasticsearch, seed: &Url) -> Result<Vec<Url>> {
+    let response = elastic
+        .nodes()
+        .info(NodesInfoParts::Metric(&["http"]))
+        .send()
+        .await?;
+    response.error_for_status_code_ref()?;
+
+    let info: es_types::NodesInfoResponse = response.json().await?;
+
+    let urls: Vec<_> = info
+        .nodes
+        .into_iter()
+        .filter_map(|(_id, node)| node.http)
+        .map(|http| publish_address_to_url(seed, &http.publish_address))
+        .collect::<Result<_>>()?;
+
+    if urls.is_empty() {
+        bail!("No nodes with http enabled were found in the cluster");
+    }
+    Ok(urls)
+}
+
+/// Converts `publish_address` of the form `[hostname/]ip:port` into the url
+/// with the same scheme as the `seed` url has
+fn publish_address_to_url(seed: &Url, publish_address: &str) -> Result<Url> {
+    let address = match publish_address.rfind('/') {
+        Some(slash) => &publish_address[slash + 1..],
+        None => publish_address,
+    };
+    format!("{}://{}", seed.scheme(), address)
+        .parse()
+        .with_context(|| format!("Invalid node publish address '{}'", publish_address))
+}
+
+/// Connection pool that iterates through the given nodes in round-robin fashion
+#[derive(Debug, Clone)]
+pub struct RoundRobinConnectionPool {
+    connections: Vec<Connection>,
+    next: Arc<AtomicUsize>,
+}
+
+impl RoundRobinConnectionPool {
+    /// Panics if `urls` is empty
+    pub fn new(urls: Vec<Url>) -> Self {
+        assert!(
+            !urls.is_empty(),
+            "connection pool requires at least one url"
+        );
+        Self {
+            connections: urls.into_iter().map(Connection::new).collect(),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl ConnectionPool for RoundRobinConnectionPool {
+    fn next(&self) -> &Connection {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.connections[next % self.connections.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_publish_address_to_url() {
+        let seed: Url = "https://127.0.0.1:9200".parse().unwrap();
+        assert_eq!(
+            publish_address_to_url(&seed, "es02/172.18.0.3:9200")
+                .unwrap()
+                .as_str(),
+            "https://172.18.0.3:9200/"
+        );
+        assert_eq!(
+            publish_address_to_url(&seed, "172.18.0.3:9201")
+                .unwrap()
+                .as_str(),
+            "https://172.18.0.3:9201/"
+        );
+    }
+
+    #[test]
+    fn round_robin_pool_cycles_thru_the_nodes() {
+        let urls: Vec<Url> = vec![
+            "http://es01:9200".parse().unwrap(),
+            "http://es02:9200".parse().unwrap(),
+        ];
+        let pool = RoundRobinConnectionPool::new(urls);
+
+        assert!(std::ptr::eq(pool.next(), &pool.connections[0]));
+        assert!(std::ptr::eq(pool.next(), &pool.connections[1]));
+        assert!(std::ptr::eq(pool.next(), &pool.connections[0]));
+    }
+}
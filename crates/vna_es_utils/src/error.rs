@@ -0,0 +1,221 @@
+//! Typed errors parsed from the bodies of non-2xx Elasticsearch responses
+
+use elasticsearch::http::{response::Response as ElasticsearchResponse, StatusCode};
+use serde::Deserialize;
+use std::fmt;
+
+/// Error returned by Elasticsearch. Only the errors that callers may want to
+/// handle specifically have their own variants, everything else is `Other`.
+#[derive(Debug)]
+pub enum EsError {
+    /// `index_not_found_exception`
+    IndexNotFound { index: String },
+    /// `resource_already_exists_exception`, e.g. the index being created already exists
+    ResourceAlreadyExists { index: String },
+    /// `version_conflict_engine_exception`
+    VersionConflict { reason: String },
+    /// `es_rejected_execution_exception`, the cluster is overloaded and the request may be retried
+    RejectedExecution { reason: String },
+    /// `mapper_parsing_exception`, the document doesn't match the index mapping
+    MapperParsing { reason: String },
+    /// `illegal_argument_exception`
+    IllegalArgument { reason: String },
+    /// `repository_missing_exception`
+    RepositoryMissing { repository: String },
+    /// `snapshot_missing_exception`
+    SnapshotMissing { reason: String },
+    /// 404 response with no structured error info (e.g. when the requested alias is missing)
+    NotFound { reason: String },
+    Other {
+        status: StatusCode,
+        kind: String,
+        reason: String,
+    },
+    /// The error response body could not be read
+    Transport(elasticsearch::Error),
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorBody {
+    Structured(ErrorCause),
+    Message(String),
+}
+
+#[derive(Deserialize)]
+struct ErrorCause {
+    #[serde(rename = "type")]
+    kind: String,
+    reason: Option<String>,
+    index: Option<String>,
+    #[serde(rename = "resource.id")]
+    resource_id: Option<String>,
+}
+
+impl EsError {
+    /// Reads the error from the body of the non-2xx response
+    pub async fn from_response(response: ElasticsearchResponse) -> Self {
+        let status = response.status_code();
+        match response.text().await {
+            Ok(body) => Self::from_body(status, &body),
+            Err(err) => Self::Transport(err),
+        }
+    }
+
+    pub fn from_body(status: StatusCode, body: &str) -> Self {
+        let cause = match serde_json::from_str::<ErrorResponse>(body) {
+            Ok(ErrorResponse {
+                error: ErrorBody::Structured(it),
+            }) => it,
+            Ok(ErrorResponse {
+                error: ErrorBody::Message(reason),
+            }) if status == StatusCode::NOT_FOUND => return Self::NotFound { reason },
+            Ok(ErrorResponse {
+                error: ErrorBody::Message(reason),
+            }) => {
+                return Self::Other {
+                    status,
+                    kind: String::new(),
+                    reason,
+                }
+            }
+            Err(_) => {
+                return Self::Other {
+                    status,
+                    kind: String::new(),
+                    reason: body.to_owned(),
+                }
+            }
+        };
+
+        let reason = cause.reason.unwrap_or_default();
+        let index = cause.index.unwrap_or_else(|| reason.clone());
+
+        match cause.kind.as_str() {
+            "index_not_found_exception" => Self::IndexNotFound { index },
+            "resource_already_exists_exception" => Self::ResourceAlreadyExists { index },
+            "version_conflict_engine_exception" => Self::VersionConflict { reason },
+            "es_rejected_execution_exception" => Self::RejectedExecution { reason },
+            "mapper_parsing_exception" => Self::MapperParsing { reason },
+            "illegal_argument_exception" => Self::IllegalArgument { reason },
+            "repository_missing_exception" => Self::RepositoryMissing {
+                repository: cause.resource_id.unwrap_or(reason),
+            },
+            "snapshot_missing_exception" => Self::SnapshotMissing { reason },
+            _ => Self::Other {
+                status,
+                kind: cause.kind.clone(),
+                reason,
+            },
+        }
+    }
+}
+
+impl fmt::Display for EsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IndexNotFound { index } => write!(f, "Index '{}' does not exist", index),
+            Self::ResourceAlreadyExists { index } => write!(f, "Index '{}' already exists", index),
+            Self::VersionConflict { reason } => write!(f, "Version conflict: {}", reason),
+            Self::RejectedExecution { reason } => write!(
+                f,
+                "Elasticsearch is overloaded and rejected the request, retry later: {}",
+                reason
+            ),
+            Self::MapperParsing { reason } => {
+                write!(f, "Document doesn't match the index mapping: {}", reason)
+            }
+            Self::IllegalArgument { reason } => write!(f, "Illegal argument: {}", reason),
+            Self::RepositoryMissing { repository } => write!(
+                f,
+                "Snapshot repository '{}' is not registered (see `vna snapshots register-repo`)",
+                repository
+            ),
+            Self::SnapshotMissing { reason } => write!(f, "Snapshot is missing: {}", reason),
+            Self::NotFound { reason } => write!(f, "Not found: {}", reason),
+            Self::Other {
+                status,
+                kind,
+                reason,
+            } if kind.is_empty() => write!(f, "Elasticsearch returned {}: {}", status, reason),
+            Self::Other {
+                status,
+                kind,
+                reason,
+            } => write!(f, "Elasticsearch returned {} {}: {}", status, kind, reason),
+            Self::Transport(err) => write!(f, "Failed to read Elasticsearch response: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for EsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Transport(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_structured_errors() {
+        let err = EsError::from_body(
+            StatusCode::BAD_REQUEST,
+            r#"{
+                "error": {
+                    "root_cause": [{
+                        "type": "resource_already_exists_exception",
+                        "reason": "index [articles_2/Xq9] already exists",
+                        "index_uuid": "Xq9",
+                        "index": "articles_2"
+                    }],
+                    "type": "resource_already_exists_exception",
+                    "reason": "index [articles_2/Xq9] already exists",
+                    "index_uuid": "Xq9",
+                    "index": "articles_2"
+                },
+                "status": 400
+            }"#,
+        );
+        assert!(matches!(err, EsError::ResourceAlreadyExists { index } if index == "articles_2"));
+
+        let err = EsError::from_body(
+            StatusCode::NOT_FOUND,
+            r#"{
+                "error": {
+                    "type": "repository_missing_exception",
+                    "reason": "[fs_repo] missing",
+                    "resource.id": "fs_repo"
+                },
+                "status": 404
+            }"#,
+        );
+        assert!(
+            matches!(err, EsError::RepositoryMissing { repository } if repository == "fs_repo")
+        );
+    }
+
+    #[test]
+    fn parses_message_errors() {
+        let err = EsError::from_body(
+            StatusCode::NOT_FOUND,
+            r#"{ "error": "alias [articles] missing", "status": 404 }"#,
+        );
+        assert!(matches!(err, EsError::NotFound { .. }));
+
+        let err = EsError::from_body(StatusCode::BAD_GATEWAY, "<html>Bad Gateway</html>");
+        assert_eq!(
+            err.to_string(),
+            "Elasticsearch returned 502 Bad Gateway: <html>Bad Gateway</html>"
+        );
+    }
+}
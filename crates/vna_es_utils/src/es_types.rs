@@ -1,63 +1,1019 @@
-use serde::Deserialize;
-use std::collections::HashMap;
+//! Typed Elasticsearch response bodies.
+//! Only the fields we use are modeled, all other fields are ignored during deserialization.
 
-// FIXME: there are definitely more fields in response objects
-// (deserialization might fail when other fields are present too?)
+use crate::HealthStatus;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
 
-#[derive(Deserialize)]
+#[cfg(test)]
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct AcknowledgedResponse {
+    pub acknowledged: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateIndexResponse {
+    pub acknowledged: bool,
+    /// `false` if the requested number of shard copies didn't become active before the timeout
+    pub shards_acknowledged: bool,
+    pub index: String,
+}
+
+#[test]
+fn create_index_response_works() {
+    let res = serde_json::from_value::<CreateIndexResponse>(json!({
+        "acknowledged": true,
+        "shards_acknowledged": true,
+        "index": "articles_1"
+    }))
+    .unwrap();
+    assert_eq!(res.index, "articles_1");
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetMappingResponse(pub HashMap<String, IndexMapping>);
+
+#[derive(Debug, Deserialize)]
+pub struct IndexMapping {
+    pub mappings: TypeMapping,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TypeMapping {
+    /// Field mappings as they are returned by Elasticsearch, i.e. with default parameters omitted
+    #[serde(default)]
+    pub properties: BTreeMap<String, serde_json::Value>,
+    /// Arbitrary metadata attached to the mapping
+    pub _meta: Option<serde_json::Value>,
+}
+
+#[test]
+fn get_mapping_response_works() {
+    let res = serde_json::from_value::<GetMappingResponse>(json!({
+        "articles_1": {
+            "mappings": {
+                "_meta": { "schema_version": 2 },
+                "properties": {
+                    "category": { "type": "keyword" },
+                    "sentiment_score": { "type": "float", "index": false }
+                }
+            }
+        },
+        "empty": { "mappings": {} }
+    }))
+    .unwrap();
+
+    let properties = &res.0["articles_1"].mappings.properties;
+    assert_eq!(properties["category"], json!({ "type": "keyword" }));
+    assert_eq!(
+        res.0["articles_1"].mappings._meta,
+        Some(json!({ "schema_version": 2 }))
+    );
+    assert!(res.0["empty"].mappings.properties.is_empty());
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CountResponse {
+    pub count: u64,
+    pub _shards: ShardsInfo,
+}
+
+#[test]
+fn count_response_works() {
+    let res = serde_json::from_value::<CountResponse>(json!({
+        "count": 200853,
+        "_shards": { "total": 1, "successful": 1, "skipped": 0, "failed": 0 }
+    }))
+    .unwrap();
+    assert_eq!(res.count, 200853);
+}
+
+#[derive(Debug, Deserialize)]
 pub struct GetAliasesResponse(pub HashMap<String, IndexAliases>);
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct IndexAliases {
     pub aliases: HashMap<String, IndexAlias>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct IndexAlias {
-    // TODO: fill in
+    pub filter: Option<serde_json::Value>,
+    pub index_routing: Option<String>,
+    pub search_routing: Option<String>,
+    /// Present only if it was set explicitly when creating the alias
+    pub is_write_index: Option<bool>,
 }
 
 #[test]
 fn get_aliases_response_works() {
-    serde_json::from_value::<GetAliasesResponse>(serde_json::json!({
+    let res = serde_json::from_value::<GetAliasesResponse>(json!({
         ".kibana_1": { "aliases": { ".kibana": {} } },
         ".kibana_task_manager_1": { "aliases": { ".kibana_task_manager": {} } },
         ".apm-agent-configuration": { "aliases": {} },
-        ".apm-custom-link": { "aliases": {} }
+        ".apm-custom-link": { "aliases": {} },
+        "articles_2": {
+            "aliases": {
+                "articles": {
+                    "filter": { "term": { "category": "POLITICS" } },
+                    "index_routing": "1",
+                    "search_routing": "1,2",
+                    "is_write_index": true
+                }
+            }
+        }
     }))
     .unwrap();
+
+    let alias = &res.0["articles_2"].aliases["articles"];
+    assert_eq!(alias.is_write_index, Some(true));
+    assert_eq!(alias.search_routing.as_deref(), Some("1,2"));
+}
+
+/// Summary of the shards the request was executed on
+#[derive(Debug, Deserialize)]
+pub struct ShardsInfo {
+    pub total: u32,
+    pub successful: u32,
+    #[serde(default)]
+    pub skipped: u32,
+    pub failed: u32,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct Doc<Entity> {
+    pub _index: String,
     pub _id: String,
+    /// `None` if the results are sorted by something other than relevance
+    pub _score: Option<f64>,
     pub _source: Entity,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
+pub struct GetResponse<Entity> {
+    pub _index: String,
+    pub _id: String,
+    pub found: bool,
+    /// Absent if the document was not found
+    pub _version: Option<u64>,
+    pub _seq_no: Option<u64>,
+    pub _primary_term: Option<u64>,
+    pub _source: Option<Entity>,
+}
+
+#[test]
+fn get_response_works() {
+    let res = serde_json::from_value::<GetResponse<serde_json::Value>>(json!({
+        "_index": "articles_1",
+        "_type": "_doc",
+        "_id": "42",
+        "_version": 1,
+        "_seq_no": 10,
+        "_primary_term": 1,
+        "found": true,
+        "_source": { "headline": "Bruh" }
+    }))
+    .unwrap();
+    assert_eq!(res._source.unwrap()["headline"], "Bruh");
+
+    let res = serde_json::from_value::<GetResponse<serde_json::Value>>(json!({
+        "_index": "articles_1",
+        "_type": "_doc",
+        "_id": "43",
+        "found": false
+    }))
+    .unwrap();
+    assert!(!res.found);
+    assert!(res._source.is_none());
+}
+
+#[derive(Debug, Deserialize)]
 pub struct SearchHits<Entity> {
+    /// Absent if the search was issued with `track_total_hits: false`
+    pub total: Option<HitsTotal>,
+    pub max_score: Option<f64>,
     pub hits: Vec<Doc<Entity>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
+pub struct HitsTotal {
+    pub value: u64,
+    pub relation: HitsTotalRelation,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HitsTotalRelation {
+    /// `value` is the exact number of hits
+    Eq,
+    /// `value` is the lower bound of the number of hits
+    Gte,
+}
+
+#[derive(Debug, Deserialize)]
 pub struct SearchResponse<Entity> {
-    // FIXME: fully fill in
+    /// Milliseconds it took Elasticsearch to execute the request
+    pub took: u64,
+    pub timed_out: bool,
+    pub _shards: ShardsInfo,
     pub hits: SearchHits<Entity>,
+    /// Present only when the search was issued with `scroll` parameter
+    pub _scroll_id: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[test]
+fn search_response_works() {
+    let res = serde_json::from_value::<SearchResponse<serde_json::Value>>(json!({
+        "_scroll_id": "DXF1ZXJ5QW5kRmV0Y2gBAAAAAAAAAD4WYm9laVYtZndUQlNsdDcwakFMNjU1QQ==",
+        "took": 5,
+        "timed_out": false,
+        "_shards": { "total": 1, "successful": 1, "skipped": 0, "failed": 0 },
+        "hits": {
+            "total": { "value": 10000, "relation": "gte" },
+            "max_score": 1.3862942,
+            "hits": [{
+                "_index": "articles_1",
+                "_type": "_doc",
+                "_id": "0",
+                "_score": 1.3862942,
+                "_source": { "headline": "Bruh" }
+            }]
+        }
+    }))
+    .unwrap();
+
+    let total = res.hits.total.unwrap();
+    assert_eq!(total.relation, HitsTotalRelation::Gte);
+    assert_eq!(total.value, 10000);
+    assert!(res.hits.hits[0]._score.is_some());
+    assert!(res._scroll_id.is_some());
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MultiSearchResponse<Entity> {
+    pub took: u64,
+    /// Responses to the searches in the order they were sent
+    pub responses: Vec<MultiSearchItem<Entity>>,
+}
+
+/// Each of the searches of `_msearch` request succeeds or fails on its own
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MultiSearchItem<Entity> {
+    Ok(SearchResponse<Entity>),
+    Err { error: ErrorCause, status: u16 },
+}
+
+#[test]
+fn multi_search_response_works() {
+    let res = serde_json::from_value::<MultiSearchResponse<serde_json::Value>>(json!({
+        "took": 7,
+        "responses": [
+            {
+                "took": 5,
+                "timed_out": false,
+                "_shards": { "total": 1, "successful": 1, "skipped": 0, "failed": 0 },
+                "hits": {
+                    "total": { "value": 1, "relation": "eq" },
+                    "max_score": 1.3862942,
+                    "hits": [{
+                        "_index": "articles_1",
+                        "_type": "_doc",
+                        "_id": "0",
+                        "_score": 1.3862942,
+                        "_source": { "headline": "Bruh" }
+                    }]
+                },
+                "status": 200
+            },
+            {
+                "error": {
+                    "root_cause": [],
+                    "type": "search_phase_execution_exception",
+                    "reason": "all shards failed"
+                },
+                "status": 400
+            }
+        ]
+    }))
+    .unwrap();
+
+    assert_eq!(res.responses.len(), 2);
+    match &res.responses[0] {
+        MultiSearchItem::Ok(it) => assert_eq!(it.hits.hits[0]._id, "0"),
+        MultiSearchItem::Err { .. } => panic!("the first search must have succeeded"),
+    }
+    match &res.responses[1] {
+        MultiSearchItem::Err { error, status } => {
+            assert_eq!(*status, 400);
+            assert_eq!(
+                error.to_string(),
+                "search_phase_execution_exception: all shards failed"
+            );
+        }
+        MultiSearchItem::Ok(_) => panic!("the second search must have failed"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
 pub struct AggrsResponse<Aggrs> {
+    pub took: u64,
+    pub timed_out: bool,
+    pub _shards: ShardsInfo,
     pub aggregations: Aggrs,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
+pub struct ClearScrollResponse {
+    pub succeeded: bool,
+    pub num_freed: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkResponse {
+    pub took: u64,
+    /// `true` if at least one of the items has failed
+    pub errors: bool,
+    pub items: Vec<BulkResponseItem>,
+}
+
+impl BulkResponse {
+    /// Returns the items that have failed along with their errors
+    pub fn failed_items(&self) -> impl Iterator<Item = (&BulkItemResult, &ErrorCause)> {
+        self.items
+            .iter()
+            .map(BulkResponseItem::result)
+            .filter_map(|it| Some((it, it.error.as_ref()?)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkResponseItem {
+    Index(BulkItemResult),
+    Create(BulkItemResult),
+    Update(BulkItemResult),
+    Delete(BulkItemResult),
+}
+
+impl BulkResponseItem {
+    pub fn result(&self) -> &BulkItemResult {
+        match self {
+            Self::Index(it) | Self::Create(it) | Self::Update(it) | Self::Delete(it) => it,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkItemResult {
+    pub _index: String,
+    pub _id: Option<String>,
+    pub status: u16,
+    /// E.g. `created`, `updated`, `deleted`, `not_found` or `noop`, absent if the item failed
+    pub result: Option<String>,
+    pub error: Option<ErrorCause>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorCause {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub reason: Option<String>,
+}
+
+impl fmt::Display for ErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "{}: {}", self.kind, reason),
+            None => f.write_str(&self.kind),
+        }
+    }
+}
+
+#[test]
+fn bulk_response_works() {
+    let res = serde_json::from_value::<BulkResponse>(json!({
+        "took": 30,
+        "errors": true,
+        "items": [
+            {
+                "index": {
+                    "_index": "articles_1",
+                    "_type": "_doc",
+                    "_id": "1",
+                    "_version": 1,
+                    "result": "created",
+                    "_shards": { "total": 1, "successful": 1, "failed": 0 },
+                    "status": 201,
+                    "_seq_no": 0,
+                    "_primary_term": 1
+                }
+            },
+            {
+                "update": {
+                    "_index": "articles_1",
+                    "_type": "_doc",
+                    "_id": "2",
+                    "status": 404,
+                    "error": {
+                        "type": "document_missing_exception",
+                        "reason": "[_doc][2]: document missing",
+                        "index_uuid": "aAsFqTI0Tc2W0LCWgPNrOA",
+                        "shard": "0",
+                        "index": "articles_1"
+                    }
+                }
+            },
+            {
+                "delete": {
+                    "_index": "articles_1",
+                    "_type": "_doc",
+                    "_id": "3",
+                    "_version": 2,
+                    "result": "not_found",
+                    "_shards": { "total": 1, "successful": 1, "failed": 0 },
+                    "status": 404,
+                    "_seq_no": 1,
+                    "_primary_term": 1
+                }
+            }
+        ]
+    }))
+    .unwrap();
+
+    let failed: Vec<_> = res.failed_items().collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].0._id.as_deref(), Some("2"));
+    assert_eq!(
+        failed[0].1.to_string(),
+        "document_missing_exception: [_doc][2]: document missing"
+    );
+}
+
+/// Record of `_cat/indices?format=json&bytes=b` response.
+/// All the values are strings, the counters are absent for closed indices.
+#[derive(Debug, Deserialize)]
+pub struct CatIndicesRecord {
+    pub health: Option<HealthStatus>,
+    /// `open` or `close`
+    pub status: String,
+    pub index: String,
+    pub uuid: String,
+    pub pri: Option<String>,
+    pub rep: Option<String>,
+    #[serde(rename = "docs.count")]
+    pub docs_count: Option<String>,
+    #[serde(rename = "docs.deleted")]
+    pub docs_deleted: Option<String>,
+    #[serde(rename = "store.size")]
+    pub store_size: Option<String>,
+    #[serde(rename = "pri.store.size")]
+    pub pri_store_size: Option<String>,
+}
+
+#[test]
+fn cat_indices_response_works() {
+    let res = serde_json::from_value::<Vec<CatIndicesRecord>>(json!([
+        {
+            "health": "green",
+            "status": "open",
+            "index": "articles_1",
+            "uuid": "u8FNjxh8Rfy_awN11oDKYQ",
+            "pri": "1",
+            "rep": "0",
+            "docs.count": "200853",
+            "docs.deleted": "0",
+            "store.size": "67108864",
+            "pri.store.size": "67108864"
+        },
+        {
+            "status": "close",
+            "index": "articles_0",
+            "uuid": "nM9Gy1sLTWGi8PKqGcjcMg"
+        }
+    ]))
+    .unwrap();
+
+    assert_eq!(res[0].health, Some(HealthStatus::Green));
+    assert_eq!(res[0].docs_count.as_deref(), Some("200853"));
+    assert!(res[1].docs_count.is_none());
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClusterHealthResponse {
+    pub cluster_name: String,
+    pub status: HealthStatus,
+    /// `true` if the requested `wait_for_status` was not reached within the timeout
+    pub timed_out: bool,
+    pub number_of_nodes: u32,
+    pub number_of_data_nodes: u32,
+    pub active_primary_shards: u32,
+    pub active_shards: u32,
+    pub relocating_shards: u32,
+    pub initializing_shards: u32,
+    pub unassigned_shards: u32,
+}
+
+#[test]
+fn cluster_health_response_works() {
+    let health = serde_json::from_value::<ClusterHealthResponse>(json!({
+        "cluster_name": "es-docker-cluster",
+        "status": "yellow",
+        "timed_out": false,
+        "number_of_nodes": 1,
+        "number_of_data_nodes": 1,
+        "active_primary_shards": 6,
+        "active_shards": 6,
+        "relocating_shards": 0,
+        "initializing_shards": 0,
+        "unassigned_shards": 1,
+        "delayed_unassigned_shards": 0,
+        "number_of_pending_tasks": 0,
+        "number_of_in_flight_fetch": 0,
+        "task_max_waiting_in_queue_millis": 0,
+        "active_shards_percent_as_number": 85.71428571428571
+    }))
+    .unwrap();
+
+    assert_eq!(health.status, HealthStatus::Yellow);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NodesInfoResponse {
+    pub nodes: HashMap<String, NodeInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NodeInfo {
+    pub name: String,
+    /// Present only if `http` metric was requested and the node has http enabled
+    pub http: Option<NodeHttpInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NodeHttpInfo {
+    /// Address of the form `[hostname/]ip:port`
+    pub publish_address: String,
+}
+
+#[test]
+fn nodes_info_response_works() {
+    let res = serde_json::from_value::<NodesInfoResponse>(json!({
+        "_nodes": { "total": 1, "successful": 1, "failed": 0 },
+        "cluster_name": "es-docker-cluster",
+        "nodes": {
+            "hJRmJ7fbSoKdZwJZBAe_tw": {
+                "name": "es01",
+                "transport_address": "172.18.0.2:9300",
+                "host": "172.18.0.2",
+                "ip": "172.18.0.2",
+                "version": "7.7.0",
+                "roles": ["data", "ingest", "master"],
+                "http": {
+                    "bound_address": ["0.0.0.0:9200"],
+                    "publish_address": "es01/172.18.0.2:9200",
+                    "max_content_length_in_bytes": 104857600
+                }
+            }
+        }
+    }))
+    .unwrap();
+
+    let node = &res.nodes["hJRmJ7fbSoKdZwJZBAe_tw"];
+    assert_eq!(
+        node.http.as_ref().unwrap().publish_address,
+        "es01/172.18.0.2:9200"
+    );
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IndicesStatsResponse {
+    pub _shards: ShardsInfo,
+    pub indices: HashMap<String, IndexStats>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IndexStats {
+    pub uuid: Option<String>,
+    pub primaries: IndexStatsValues,
+    pub total: IndexStatsValues,
+}
+
+/// Only the requested metrics are present
+#[derive(Debug, Deserialize)]
+pub struct IndexStatsValues {
+    pub docs: Option<DocsStats>,
+    pub store: Option<StoreStats>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DocsStats {
+    pub count: u64,
+    pub deleted: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StoreStats {
+    pub size_in_bytes: u64,
+}
+
+#[test]
+fn indices_stats_response_works() {
+    let res = serde_json::from_value::<IndicesStatsResponse>(json!({
+        "_shards": { "total": 2, "successful": 1, "failed": 0 },
+        "_all": {
+            "primaries": { "docs": { "count": 200853, "deleted": 0 } },
+            "total": { "docs": { "count": 200853, "deleted": 0 } }
+        },
+        "indices": {
+            "articles_1": {
+                "uuid": "u8FNjxh8Rfy_awN11oDKYQ",
+                "primaries": {
+                    "docs": { "count": 200853, "deleted": 0 },
+                    "store": { "size_in_bytes": 67108864, "reserved_in_bytes": 0 }
+                },
+                "total": {
+                    "docs": { "count": 200853, "deleted": 0 },
+                    "store": { "size_in_bytes": 67108864, "reserved_in_bytes": 0 }
+                }
+            }
+        }
+    }))
+    .unwrap();
+
+    let stats = &res.indices["articles_1"];
+    assert_eq!(stats.primaries.docs.as_ref().unwrap().count, 200853);
+    assert_eq!(stats.total.store.as_ref().unwrap().size_in_bytes, 67108864);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSnapshotResponse {
+    /// Present only if the snapshot was created with `wait_for_completion`
+    pub snapshot: Option<SnapshotInfo>,
+    /// Present only if the snapshot was created without `wait_for_completion`
+    pub accepted: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetSnapshotsResponse {
+    pub snapshots: Vec<SnapshotInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotInfo {
+    pub snapshot: String,
+    pub uuid: String,
+    pub indices: Vec<String>,
+    #[serde(default)]
+    pub include_global_state: bool,
+    /// `IN_PROGRESS`, `SUCCESS`, `FAILED`, `PARTIAL` or `INCOMPATIBLE`
+    pub state: String,
+    pub start_time: Option<String>,
+    pub start_time_in_millis: Option<u64>,
+    pub end_time: Option<String>,
+    pub end_time_in_millis: Option<u64>,
+    pub duration_in_millis: Option<u64>,
+    #[serde(default)]
+    pub failures: Vec<serde_json::Value>,
+    pub shards: Option<SnapshotShardsStats>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotShardsStats {
+    pub total: u32,
+    pub failed: u32,
+    pub successful: u32,
+}
+
+#[test]
+fn snapshot_responses_work() {
+    let snapshot = json!({
+        "snapshot": "vna_snapshot",
+        "uuid": "dKb54xw67gvdRctLCxSket",
+        "version_id": 7070099,
+        "version": "7.7.0",
+        "indices": ["articles_1"],
+        "include_global_state": true,
+        "state": "SUCCESS",
+        "start_time": "2020-06-07T13:09:48.424Z",
+        "start_time_in_millis": 1591535388424u64,
+        "end_time": "2020-06-07T13:09:49.225Z",
+        "end_time_in_millis": 1591535389225u64,
+        "duration_in_millis": 801,
+        "failures": [],
+        "shards": { "total": 1, "failed": 0, "successful": 1 }
+    });
+
+    let res =
+        serde_json::from_value::<CreateSnapshotResponse>(json!({ "snapshot": snapshot })).unwrap();
+    let info = res.snapshot.unwrap();
+    assert_eq!(info.state, "SUCCESS");
+    assert_eq!(info.shards.unwrap().successful, 1);
+
+    let res =
+        serde_json::from_value::<CreateSnapshotResponse>(json!({ "accepted": true })).unwrap();
+    assert_eq!(res.accepted, Some(true));
+
+    let res =
+        serde_json::from_value::<GetSnapshotsResponse>(json!({ "snapshots": [snapshot] })).unwrap();
+    assert_eq!(res.snapshots[0].indices, ["articles_1"]);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotStatusResponse {
+    pub snapshots: Vec<SnapshotStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotStatus {
+    pub snapshot: String,
+    pub repository: String,
+    /// `IN_PROGRESS`, `STARTED`, `SUCCESS`, `FAILED` or `ABORTED`
+    pub state: String,
+    pub shards_stats: SnapshotShardsStatusStats,
+    pub stats: SnapshotStats,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotShardsStatusStats {
+    pub initializing: u32,
+    pub started: u32,
+    pub finalizing: u32,
+    pub done: u32,
+    pub failed: u32,
+    pub total: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotStats {
+    /// Files that were not present in the repository before this snapshot
+    pub incremental: SnapshotFilesStats,
+    pub total: SnapshotFilesStats,
+    pub start_time_in_millis: u64,
+    pub time_in_millis: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotFilesStats {
+    pub file_count: u64,
+    pub size_in_bytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyRepositoryResponse {
+    /// Nodes that have access to the repository by their ids
+    pub nodes: HashMap<String, VerifiedNode>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifiedNode {
+    pub name: String,
+}
+
+#[test]
+fn snapshot_status_response_works() {
+    let res = serde_json::from_value::<SnapshotStatusResponse>(json!({
+        "snapshots": [{
+            "snapshot": "vna_snapshot-2020.06.07-13.09.48",
+            "repository": "fs_repo",
+            "uuid": "dKb54xw67gvdRctLCxSket",
+            "state": "SUCCESS",
+            "include_global_state": true,
+            "shards_stats": {
+                "initializing": 0,
+                "started": 0,
+                "finalizing": 0,
+                "done": 1,
+                "failed": 0,
+                "total": 1
+            },
+            "stats": {
+                "incremental": { "file_count": 3, "size_in_bytes": 1024 },
+                "total": { "file_count": 30, "size_in_bytes": 67108864 },
+                "start_time_in_millis": 1591535388424u64,
+                "time_in_millis": 801
+            },
+            "indices": {}
+        }]
+    }))
+    .unwrap();
+
+    let status = &res.snapshots[0];
+    assert_eq!(status.shards_stats.done, 1);
+    assert_eq!(status.stats.total.size_in_bytes, 67108864);
+}
+
+#[test]
+fn verify_repository_response_works() {
+    let res = serde_json::from_value::<VerifyRepositoryResponse>(json!({
+        "nodes": { "oTUltX4IQMOUUVeiohTt8A": { "name": "es01" } }
+    }))
+    .unwrap();
+    assert_eq!(res.nodes["oTUltX4IQMOUUVeiohTt8A"].name, "es01");
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreSnapshotResponse {
+    /// Present only if the restore was requested with `wait_for_completion`
+    pub snapshot: Option<RestoreInfo>,
+    pub accepted: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreInfo {
+    pub snapshot: String,
+    pub indices: Vec<String>,
+    pub shards: SnapshotShardsStats,
+}
+
+#[test]
+fn restore_snapshot_response_works() {
+    let res = serde_json::from_value::<RestoreSnapshotResponse>(json!({
+        "snapshot": {
+            "snapshot": "vna_snapshot",
+            "indices": ["articles_1"],
+            "shards": { "total": 1, "failed": 0, "successful": 1 }
+        }
+    }))
+    .unwrap();
+    assert_eq!(res.snapshot.unwrap().indices, ["articles_1"]);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetTemplatesResponse(pub HashMap<String, IndexTemplate>);
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IndexTemplate {
+    pub order: i64,
+    /// Arbitrary version number assigned by the creator of the template
+    pub version: Option<u32>,
+    pub index_patterns: Vec<String>,
+    /// Settings are returned with all the values converted to strings
+    pub settings: serde_json::Value,
+    pub mappings: serde_json::Value,
+    pub aliases: serde_json::Value,
+}
+
+#[test]
+fn get_templates_response_works() {
+    let res = serde_json::from_value::<GetTemplatesResponse>(json!({
+        "articles": {
+            "order": 0,
+            "version": 2,
+            "index_patterns": ["articles_*"],
+            "settings": {
+                "index": {
+                    "analysis": {
+                        "filter": {
+                            "english_stop": { "type": "stop", "stopwords": "_english_" }
+                        }
+                    }
+                }
+            },
+            "mappings": {
+                "_meta": { "schema_version": 2 },
+                "properties": { "category": { "type": "keyword" } }
+            },
+            "aliases": {}
+        }
+    }))
+    .unwrap();
+
+    let template = &res.0["articles"];
+    assert_eq!(template.version, Some(2));
+    assert_eq!(template.index_patterns, ["articles_*"]);
+}
+
+/// Response of the APIs called with `wait_for_completion=false`
+#[derive(Debug, Deserialize)]
+pub struct StartedTaskResponse {
+    /// Id of the task in `<node_id>:<task_number>` form
+    pub task: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetTaskResponse<Status, Response> {
+    pub completed: bool,
+    pub task: TaskInfo<Status>,
+    /// Present only if the task completed successfully
+    pub response: Option<Response>,
+    /// Present only if the task failed
+    pub error: Option<ErrorCause>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskInfo<Status> {
+    pub action: String,
+    pub description: Option<String>,
+    pub status: Option<Status>,
+    pub running_time_in_nanos: u64,
+}
+
+/// Status of a running `_reindex` task
+#[derive(Debug, Deserialize)]
+pub struct ReindexStatus {
+    pub total: u64,
+    pub created: u64,
+    pub updated: u64,
+    pub deleted: u64,
+    pub batches: u64,
+    pub version_conflicts: u64,
+    pub noops: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReindexResponse {
+    pub took: u64,
+    pub timed_out: bool,
+    #[serde(flatten)]
+    pub status: ReindexStatus,
+    #[serde(default)]
+    pub failures: Vec<serde_json::Value>,
+}
+
+#[test]
+fn reindex_task_responses_work() {
+    let res = serde_json::from_value::<StartedTaskResponse>(json!({
+        "task": "oTUltX4IQMOUUVeiohTt8A:12345"
+    }))
+    .unwrap();
+    assert_eq!(res.task, "oTUltX4IQMOUUVeiohTt8A:12345");
+
+    let status = json!({
+        "total": 200853,
+        "updated": 0,
+        "created": 12000,
+        "deleted": 0,
+        "batches": 12,
+        "version_conflicts": 0,
+        "noops": 0,
+        "retries": { "bulk": 0, "search": 0 },
+        "throttled_millis": 0,
+        "requests_per_second": -1.0,
+        "throttled_until_millis": 0
+    });
+    let task = json!({
+        "node": "oTUltX4IQMOUUVeiohTt8A",
+        "id": 12345,
+        "type": "transport",
+        "action": "indices:data/write/reindex",
+        "status": status,
+        "description": "reindex from [articles_1] to [articles_2][_doc]",
+        "start_time_in_millis": 1591535388424u64,
+        "running_time_in_nanos": 1543246781u64,
+        "cancellable": true,
+        "headers": {}
+    });
+
+    let res = serde_json::from_value::<GetTaskResponse<ReindexStatus, ReindexResponse>>(json!({
+        "completed": false,
+        "task": task
+    }))
+    .unwrap();
+    assert!(!res.completed);
+    assert_eq!(res.task.status.unwrap().created, 12000);
+
+    let res = serde_json::from_value::<GetTaskResponse<ReindexStatus, ReindexResponse>>(json!({
+        "completed": true,
+        "task": task,
+        "response": {
+            "took": 15432,
+            "timed_out": false,
+            "total": 200853,
+            "updated": 0,
+            "created": 200853,
+            "deleted": 0,
+            "batches": 201,
+            "version_conflicts": 0,
+            "noops": 0,
+            "retries": { "bulk": 0, "search": 0 },
+            "throttled": "0s",
+            "throttled_millis": 0,
+            "requests_per_second": -1.0,
+            "throttled_until": "0s",
+            "throttled_until_millis": 0,
+            "failures": []
+        }
+    }))
+    .unwrap();
+    let response = res.response.unwrap();
+    assert_eq!(response.status.created, 200853);
+    assert!(response.failures.is_empty());
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct SignificantTextAggr {
     pub doc_count: u64,
     pub bg_count: u64,
     pub buckets: Vec<SignificantTextAggrBucket>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct SignificantTextAggrBucket {
     pub key: String,
     pub doc_count: u64,
@@ -65,15 +1021,71 @@ pub struct SignificantTextAggrBucket {
     pub bg_count: u64,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct TermsAggr {
     pub doc_count_error_upper_bound: u64,
     pub sum_other_doc_count: u64,
     pub buckets: Vec<TermsAggrBucket>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct TermsAggrBucket {
     pub key: String,
     pub doc_count: u64,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct DateHistogramAggr {
+    pub buckets: Vec<DateHistogramAggrBucket>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DateHistogramAggrBucket {
+    pub key_as_string: String,
+    pub key: i64,
+    pub doc_count: u64,
+}
+
+#[test]
+fn aggregations_work() {
+    #[derive(Deserialize)]
+    struct Aggrs {
+        words: SignificantTextAggr,
+        terms: TermsAggr,
+        timeline: DateHistogramAggr,
+    }
+
+    let res = serde_json::from_value::<AggrsResponse<Aggrs>>(json!({
+        "took": 12,
+        "timed_out": false,
+        "_shards": { "total": 1, "successful": 1, "skipped": 0, "failed": 0 },
+        "hits": { "total": { "value": 10000, "relation": "gte" }, "max_score": null, "hits": [] },
+        "aggregations": {
+            "words": {
+                "doc_count": 20,
+                "bg_count": 1000,
+                "buckets": [{ "key": "research", "doc_count": 7, "score": 1.5, "bg_count": 30 }]
+            },
+            "terms": {
+                "doc_count_error_upper_bound": 0,
+                "sum_other_doc_count": 0,
+                "buckets": [{ "key": "POLITICS", "doc_count": 32739 }]
+            },
+            "timeline": {
+                "buckets": [{
+                    "key_as_string": "2018-01-01",
+                    "key": 1514764800000i64,
+                    "doc_count": 4
+                }]
+            }
+        }
+    }))
+    .unwrap();
+
+    assert_eq!(res.aggregations.words.buckets[0].key, "research");
+    assert_eq!(res.aggregations.terms.buckets[0].doc_count, 32739);
+    assert_eq!(
+        res.aggregations.timeline.buckets[0].key_as_string,
+        "2018-01-01"
+    );
+}
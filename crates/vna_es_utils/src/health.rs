@@ -0,0 +1,135 @@
+//! Cluster health checks to run before the operations that require a healthy cluster
+
+use crate::es_types::ClusterHealthResponse;
+use anyhow::{bail, Context, Result};
+use elasticsearch::{cluster::ClusterHealthParts, params::WaitForStatus, Elasticsearch};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Delay between the attempts to reach the cluster when it is down
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Health status of the cluster, ordered from the worst to the best one
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Red,
+    Yellow,
+    Green,
+}
+
+impl HealthStatus {
+    fn to_wait_for_status(self) -> WaitForStatus {
+        match self {
+            Self::Red => WaitForStatus::Red,
+            Self::Yellow => WaitForStatus::Yellow,
+            Self::Green => WaitForStatus::Green,
+        }
+    }
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Red => "red",
+            Self::Yellow => "yellow",
+            Self::Green => "green",
+        })
+    }
+}
+
+impl std::str::FromStr for HealthStatus {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "red" => Self::Red,
+            "yellow" => Self::Yellow,
+            "green" => Self::Green,
+            _ => return Err("health status must be one of: green, yellow, red"),
+        })
+    }
+}
+
+/// Returns the current health of the cluster without waiting for any status
+pub async fn cluster_health(elastic: &Elasticsearch) -> Result<ClusterHealthResponse> {
+    let response = elastic
+        .cluster()
+        .health(ClusterHealthParts::None)
+        .send()
+        .await
+        .context("Elasticsearch cluster is unreachable")?;
+
+    response.error_for_status_code_ref()?;
+
+    Ok(response.json().await?)
+}
+
+/// Waits until the cluster health is at least `status`.
+/// The cluster being unreachable is not an error until the `timeout` expires,
+/// so this may be used to wait for the cluster to start up.
+pub async fn wait_for_health(
+    elastic: &Elasticsearch,
+    status: HealthStatus,
+    timeout: Duration,
+) -> Result<ClusterHealthResponse> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+
+        let response = elastic
+            .cluster()
+            .health(ClusterHealthParts::None)
+            .wait_for_status(status.to_wait_for_status())
+            .timeout(&format!("{}ms", remaining.as_millis()))
+            .send()
+            .await;
+
+        let response = match response {
+            Ok(it) => it,
+            Err(err) => {
+                if Instant::now() + RETRY_DELAY >= deadline {
+                    return Err(err).with_context(|| {
+                        format!("Elasticsearch cluster is unreachable after {:?}", timeout)
+                    });
+                }
+                log::debug!("Elasticsearch cluster is unreachable, retrying: {}", err);
+                tokio::time::delay_for(RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        // Elasticsearch responds with 408 when the status was not reached within the timeout,
+        // the body is a regular health response in this case
+        if response.status_code() != http::StatusCode::REQUEST_TIMEOUT {
+            response.error_for_status_code_ref()?;
+        }
+
+        let health: ClusterHealthResponse = response.json().await?;
+
+        if health.timed_out || health.status < status {
+            bail!(
+                "Elasticsearch cluster health is {} while at least {} is required (waited for {:?})",
+                health.status,
+                status,
+                timeout
+            );
+        }
+        return Ok(health);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn health_statuses_are_ordered_from_worst_to_best() {
+        assert!(HealthStatus::Red < HealthStatus::Yellow);
+        assert!(HealthStatus::Yellow < HealthStatus::Green);
+        assert_eq!("Green".parse(), Ok(HealthStatus::Green));
+    }
+}
@@ -1,19 +1,26 @@
 //! Assorted utilities for elasticsearch
 
+pub use connection::{connect, Auth, ConnectionOpts, RoundRobinConnectionPool};
 pub use elasticsearch;
+pub use error::EsError;
+pub use health::{cluster_health, wait_for_health, HealthStatus};
+mod connection;
+mod error;
 pub mod es_types;
-use anyhow::{bail, Result};
+mod health;
+use anyhow::Result;
 use elasticsearch::{
     http::{
         response::Response as ElasticsearchResponse,
         transport::{SingleNodeConnectionPool, TransportBuilder},
+        StatusCode,
     },
     Elasticsearch,
 };
 use std::{future::Future, pin::Pin};
 use url::Url;
 
-type BoxedFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+type BoxedFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
 
 pub trait Success {
     type Ok;
@@ -23,26 +30,44 @@ pub trait Success {
 
 impl Success for ElasticsearchResponse {
     type Ok = ();
-    type Err = anyhow::Error;
+    type Err = EsError;
     fn success(self) -> BoxedFuture<Result<Self::Ok, Self::Err>> {
         Box::pin(async move {
-            let status_code = self.status_code();
-            if !status_code.is_success() {
-                let backtrace = backtrace::Backtrace::new();
-                let res: serde_json::Value = self.json().await?;
-                bail!(
-                    "Elasticsearch returned {} response: {:#} at\n{:?}",
-                    status_code,
-                    res,
-                    backtrace
-                );
-            }
+            check_status(self).await?;
             Ok(())
         })
     }
 }
 
-/// Create an instance of simple proxy-less elasticsearch client (not the one on Elastic cloud)
+/// Returns the response back if it has 2xx status code, otherwise parses the error from its body
+pub async fn check_status(
+    response: ElasticsearchResponse,
+) -> Result<ElasticsearchResponse, EsError> {
+    if response.status_code().is_success() {
+        return Ok(response);
+    }
+    Err(EsError::from_response(response).await)
+}
+
+/// Same as [`check_status`](check_status), but returns `None` for the 404 response about
+/// the missing document (e.g. from get or delete API). Missing index is still an error.
+pub async fn check_document_status(
+    response: ElasticsearchResponse,
+) -> Result<Option<ElasticsearchResponse>, EsError> {
+    let status = response.status_code();
+    if status != StatusCode::NOT_FOUND {
+        return check_status(response).await.map(Some);
+    }
+    let body = response.text().await.map_err(EsError::Transport)?;
+    // Missing documents are reported without the `error` object
+    match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(it) if it.get("error").is_none() => Ok(None),
+        _ => Err(EsError::from_body(status, &body)),
+    }
+}
+
+/// Create an instance of simple proxy-less elasticsearch client (not the one on Elastic cloud),
+/// use [`connect`](connect) for the clusters that require more configuration
 pub fn create_elasticsearch_client(url: Url) -> Result<Elasticsearch> {
     let conn_pool = SingleNodeConnectionPool::new(url);
     let transport = TransportBuilder::new(conn_pool).disable_proxy().build()?;
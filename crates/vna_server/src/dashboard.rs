@@ -0,0 +1,24 @@
+//! Static web dashboard for interactive exploration of the news.
+//! All of its assets are bundled into the binary at compile time.
+
+use warp::{Filter, Rejection, Reply};
+
+const INDEX_HTML: &str = include_str!("dashboard/index.html");
+const DASHBOARD_JS: &str = include_str!("dashboard/dashboard.js");
+const DASHBOARD_CSS: &str = include_str!("dashboard/dashboard.css");
+
+/// Creates the filter that serves the dashboard assets
+pub(crate) fn routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let index = warp::path::end().map(|| warp::reply::html(INDEX_HTML));
+
+    let js = warp::path!("dashboard.js")
+        .map(|| asset(DASHBOARD_JS, "application/javascript; charset=utf-8"));
+
+    let css = warp::path!("dashboard.css").map(|| asset(DASHBOARD_CSS, "text/css; charset=utf-8"));
+
+    index.or(js).or(css)
+}
+
+fn asset(content: &'static str, content_type: &'static str) -> impl Reply {
+    warp::reply::with_header(content, "content-type", content_type)
+}
@@ -0,0 +1,182 @@
+// Dashboard client, it talks to the JSON HTTP API served by `vna serve`.
+// It is deliberately dependency-free to be bundled into the `vna` binary as is.
+"use strict";
+
+const $ = id => document.getElementById(id);
+
+/** Offset of the timezone the article dates are displayed in, it is fetched from `/settings` */
+let utcOffsetMs = 0;
+
+/** Collects query parameters that are common to all the API endpoints */
+function commonParams() {
+  const params = new URLSearchParams();
+  const set = (key, value) => value && params.set(key, value);
+
+  set("query", $("query").value.trim());
+  set("category", $("category").value.trim());
+  set("sentiment_polarity", $("sentiment-polarity").value);
+  set("date_from", $("date-from").value);
+  set("date_to", $("date-to").value);
+
+  return params;
+}
+
+async function fetchJson(path, params) {
+  const response = await fetch(`${path}?${params}`);
+  const body = await response.json();
+  if (!response.ok) {
+    throw new Error(body.error || response.statusText);
+  }
+  return body;
+}
+
+/** Renders the given `[{ key, doc_count }]` buckets as an SVG bar chart */
+function renderBarChart(container, buckets) {
+  const width = 600;
+  const height = 220;
+  const bottom = 60;
+  const max = Math.max(1, ...buckets.map(it => it.doc_count));
+  const barWidth = width / Math.max(1, buckets.length);
+
+  const bars = buckets.map((bucket, i) => {
+    const barHeight = ((height - bottom) * bucket.doc_count) / max;
+    const x = i * barWidth;
+    const y = height - bottom - barHeight;
+    const labelX = x + barWidth / 2;
+    return `
+      <g>
+        <title>${escapeHtml(bucket.key)}: ${bucket.doc_count}</title>
+        <rect x="${x + 1}" y="${y}" width="${Math.max(1, barWidth - 2)}" height="${barHeight}"></rect>
+        <text x="${labelX}" y="${height - bottom + 4}"
+          transform="rotate(45 ${labelX} ${height - bottom + 4})">${escapeHtml(bucket.key)}</text>
+      </g>`;
+  });
+
+  container.innerHTML = `
+    <svg viewBox="0 0 ${width} ${height}" preserveAspectRatio="none">${bars.join("")}</svg>`;
+}
+
+/** Builds the article list via DOM API, so that no dataset value is ever parsed as HTML */
+function renderArticles(articles) {
+  const list = $("articles");
+  list.textContent = "";
+  list.append(...articles.map(renderArticle));
+}
+
+function renderArticle({ id, doc }) {
+  const element = (tag, text, className) => {
+    const it = document.createElement(tag);
+    it.textContent = text;
+    if (className) {
+      it.className = className;
+    }
+    return it;
+  };
+
+  const headline = element("a", doc.headline);
+  const link = safeLink(doc.link);
+  if (link) {
+    headline.setAttribute("href", link);
+    headline.setAttribute("target", "_blank");
+    headline.setAttribute("rel", "noopener");
+  }
+
+  const meta = [
+    doc.category,
+    formatDate(doc.date),
+    doc.authors,
+    `${doc.sentiment_polarity} (${doc.sentiment_score.toFixed(2)})`,
+    `id: ${id}`,
+  ];
+
+  const item = document.createElement("li");
+  item.append(
+    headline,
+    element("p", doc.short_description),
+    element("p", meta.join(" \u00b7 "), "meta")
+  );
+  return item;
+}
+
+/** Returns the link if it is an absolute `http:` or `https:` URL, `null` otherwise */
+function safeLink(link) {
+  try {
+    const url = new URL(link);
+    return url.protocol === "http:" || url.protocol === "https:" ? url.href : null;
+  } catch (err) {
+    return null;
+  }
+}
+
+/** Formats the RFC 3339 timestamp as `YYYY-MM-DD` in the configured timezone */
+function formatDate(timestamp) {
+  return new Date(Date.parse(timestamp) + utcOffsetMs).toISOString().slice(0, 10);
+}
+
+/** Escapes the text to be safely placed both into HTML content and quoted attribute values */
+function escapeHtml(text) {
+  const entities = { "&": "&amp;", "<": "&lt;", ">": "&gt;", '"': "&quot;", "'": "&#39;" };
+  return String(text).replace(/[&<>"']/g, it => entities[it]);
+}
+
+async function refreshCharts() {
+  const params = commonParams();
+  const timelineParams = new URLSearchParams(params);
+  timelineParams.set("interval", $("interval").value);
+
+  const charts = [
+    ["sentiment-chart", "/stats/sentiment", params],
+    ["category-chart", "/stats/category", params],
+    ["timeline-chart", "/stats/timeline", timelineParams],
+  ];
+
+  await Promise.all(
+    charts.map(async ([id, path, params]) => {
+      try {
+        renderBarChart($(id), await fetchJson(path, params));
+      } catch (err) {
+        $(id).innerHTML = `<p class="error">${escapeHtml(err.message)}</p>`;
+      }
+    })
+  );
+}
+
+async function refreshArticles() {
+  const params = commonParams();
+  if (!params.has("query")) {
+    $("status").textContent = "Type a query to search for the news";
+    $("articles").innerHTML = "";
+    return;
+  }
+  const fieldName = $("field-name").value;
+  if (fieldName) {
+    params.set("field_name", fieldName);
+  }
+
+  $("status").textContent = "Searching...";
+  try {
+    const articles = await fetchJson("/search", params);
+    $("status").textContent = `Found ${articles.length} articles`;
+    renderArticles(articles);
+  } catch (err) {
+    $("status").innerHTML = `<span class="error">${escapeHtml(err.message)}</span>`;
+  }
+}
+
+function refresh() {
+  refreshArticles();
+  refreshCharts();
+}
+
+$("search-form").addEventListener("submit", event => {
+  event.preventDefault();
+  refresh();
+});
+$("filters-form").addEventListener("change", refresh);
+
+fetchJson("/settings", new URLSearchParams())
+  .then(settings => {
+    utcOffsetMs = settings.utc_offset_secs * 1000;
+  })
+  .catch(err => console.error("Failed to fetch the settings, dates are displayed in UTC", err))
+  .then(refresh);
@@ -0,0 +1,288 @@
+//! HTTP API that exposes the same functionality as `vna` cli does.
+//! It also serves a static web dashboard that is built on top of this API.
+
+use chrono::FixedOffset;
+use elasticsearch::Elasticsearch;
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, net::SocketAddr};
+use warp::{http::StatusCode, Filter, Rejection, Reply};
+
+mod dashboard;
+
+/// Runs the HTTP server at the given address, the returned future never resolves
+pub async fn serve(elastic: Elasticsearch, addr: SocketAddr, timezone: FixedOffset) {
+    warp::serve(routes(elastic, timezone)).run(addr).await
+}
+
+/// Creates the filter that handles all the endpoints of the HTTP API.
+/// The article dates are filtered, bucketed and displayed by the dashboard in `timezone`.
+pub fn routes(
+    elastic: Elasticsearch,
+    timezone: FixedOffset,
+) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
+    let elastic = warp::any().map(move || elastic.clone());
+    let timezone = warp::any().map(move || timezone);
+
+    let search = warp::path!("search")
+        .and(elastic.clone())
+        .and(timezone.clone())
+        .and(warp::query())
+        .and_then(search);
+
+    let sentiment_stats = warp::path!("stats" / "sentiment")
+        .and(elastic.clone())
+        .and(timezone.clone())
+        .and(warp::query())
+        .and_then(sentiment_stats);
+
+    let category_stats = warp::path!("stats" / "category")
+        .and(elastic.clone())
+        .and(timezone.clone())
+        .and(warp::query())
+        .and_then(category_stats);
+
+    let timeline_stats = warp::path!("stats" / "timeline")
+        .and(elastic.clone())
+        .and(timezone.clone())
+        .and(warp::query())
+        .and_then(timeline_stats);
+
+    let significant_words = warp::path!("stats" / "significant-words")
+        .and(elastic)
+        .and(warp::query())
+        .and_then(significant_words);
+
+    let settings = warp::path!("settings").and(timezone).map(settings);
+
+    warp::get()
+        .and(
+            dashboard::routes()
+                .or(search)
+                .or(sentiment_stats)
+                .or(category_stats)
+                .or(timeline_stats)
+                .or(significant_words)
+                .or(settings),
+        )
+        .recover(handle_rejection)
+        .with(warp::log("vna_server"))
+}
+
+/// Query parameters of `/search` endpoint, they mirror `vna search` cli args
+#[derive(Deserialize)]
+struct SearchParams {
+    query: stdx::NonHollowString,
+    field_name: Option<String>,
+    #[serde(flatten)]
+    filters: vna_es::ArticleFilters,
+}
+
+/// Query parameters of `/stats/{sentiment,category}` endpoints,
+/// they mirror `vna stats {sentiment,category}` cli args
+#[derive(Deserialize)]
+struct StatsParams {
+    query: Option<stdx::NonHollowString>,
+    #[serde(default = "default_field_name")]
+    field_name: String,
+    #[serde(flatten)]
+    filters: vna_es::ArticleFilters,
+}
+
+/// Query parameters of `/stats/timeline` endpoint,
+/// they mirror `vna stats timeline` cli args
+#[derive(Deserialize)]
+struct TimelineParams {
+    #[serde(flatten)]
+    stats: StatsParams,
+    #[serde(default = "default_interval")]
+    interval: vna_es::CalendarInterval,
+}
+
+/// Query parameters of `/stats/significant-words` endpoint,
+/// they mirror `vna stats significant-words` cli args
+#[derive(Deserialize)]
+struct SignificantWordsParams {
+    query: stdx::NonHollowString,
+    #[serde(default = "default_field_name")]
+    field_name: String,
+    #[serde(default = "default_max_words")]
+    max_words: u32,
+}
+
+fn default_field_name() -> String {
+    "short_description".to_owned()
+}
+
+fn default_max_words() -> u32 {
+    15
+}
+
+fn default_interval() -> vna_es::CalendarInterval {
+    vna_es::CalendarInterval::Month
+}
+
+#[derive(Serialize)]
+struct StatsBucket {
+    key: String,
+    doc_count: u64,
+}
+
+/// Server settings the dashboard depends on
+#[derive(Serialize)]
+struct Settings {
+    /// Offset of the timezone the article dates are displayed in
+    utc_offset_secs: i32,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+/// Rejection that is produced when Elasticsearch request fails
+#[derive(Debug)]
+struct ElasticsearchFailure(anyhow::Error);
+
+impl warp::reject::Reject for ElasticsearchFailure {}
+
+fn elasticsearch_failure(err: anyhow::Error) -> Rejection {
+    warp::reject::custom(ElasticsearchFailure(err))
+}
+
+async fn search(
+    elastic: Elasticsearch,
+    timezone: FixedOffset,
+    mut params: SearchParams,
+) -> Result<impl Reply, Rejection> {
+    params.filters.time_zone = Some(timezone);
+    let articles = vna_es::Article::fulltext_search(vna_es::FulltextSearchOpts {
+        elastic: &elastic,
+        field_name: params.field_name.as_deref(),
+        query: &params.query,
+        filters: &params.filters,
+    })
+    .await
+    .map_err(elasticsearch_failure)?;
+
+    Ok(warp::reply::json(&articles))
+}
+
+async fn sentiment_stats(
+    elastic: Elasticsearch,
+    timezone: FixedOffset,
+    mut params: StatsParams,
+) -> Result<impl Reply, Rejection> {
+    params.filters.time_zone = Some(timezone);
+    let stats = vna_es::Article::sentiment_stats(vna_es::StatsOpts {
+        elastic: &elastic,
+        field_name: &params.field_name,
+        query: &params.query,
+        filters: &params.filters,
+    })
+    .await
+    .map_err(elasticsearch_failure)?;
+
+    Ok(stats_reply(stats))
+}
+
+async fn category_stats(
+    elastic: Elasticsearch,
+    timezone: FixedOffset,
+    mut params: StatsParams,
+) -> Result<impl Reply, Rejection> {
+    params.filters.time_zone = Some(timezone);
+    let stats = vna_es::Article::category_stats(vna_es::StatsOpts {
+        elastic: &elastic,
+        field_name: &params.field_name,
+        query: &params.query,
+        filters: &params.filters,
+    })
+    .await
+    .map_err(elasticsearch_failure)?;
+
+    Ok(stats_reply(stats))
+}
+
+async fn timeline_stats(
+    elastic: Elasticsearch,
+    timezone: FixedOffset,
+    mut params: TimelineParams,
+) -> Result<impl Reply, Rejection> {
+    params.stats.filters.time_zone = Some(timezone);
+    let stats = vna_es::Article::timeline_stats(
+        vna_es::StatsOpts {
+            elastic: &elastic,
+            field_name: &params.stats.field_name,
+            query: &params.stats.query,
+            filters: &params.stats.filters,
+        },
+        params.interval,
+        timezone,
+    )
+    .await
+    .map_err(elasticsearch_failure)?;
+
+    Ok(stats_reply(stats))
+}
+
+fn stats_reply(stats: vna_es::Stats) -> impl Reply {
+    let buckets: Vec<_> = stats
+        .0
+        .into_iter()
+        .map(|(key, doc_count)| StatsBucket { key, doc_count })
+        .collect();
+
+    warp::reply::json(&buckets)
+}
+
+fn settings(timezone: FixedOffset) -> impl Reply {
+    warp::reply::json(&Settings {
+        utc_offset_secs: timezone.local_minus_utc(),
+    })
+}
+
+async fn significant_words(
+    elastic: Elasticsearch,
+    params: SignificantWordsParams,
+) -> Result<impl Reply, Rejection> {
+    let words = vna_es::Article::significant_words(vna_es::SignificantWordsOpts {
+        elastic: &elastic,
+        field_name: &params.field_name,
+        query: &params.query,
+        max_words: params.max_words,
+    })
+    .await
+    .map_err(elasticsearch_failure)?;
+
+    Ok(warp::reply::json(&words))
+}
+
+async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Infallible> {
+    let (status, error) = if rejection.is_not_found() {
+        (StatusCode::NOT_FOUND, "Not found".to_owned())
+    } else if let Some(err) = rejection.find::<warp::reject::InvalidQuery>() {
+        (StatusCode::BAD_REQUEST, err.to_string())
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Method not allowed".to_owned(),
+        )
+    } else if let Some(ElasticsearchFailure(err)) = rejection.find() {
+        log::error!("Elasticsearch request failed: {:?}", err);
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("Elasticsearch request failed: {}", err),
+        )
+    } else {
+        log::error!("Unhandled rejection: {:?}", rejection);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Internal server error".to_owned(),
+        )
+    };
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&ErrorBody { error }),
+        status,
+    ))
+}
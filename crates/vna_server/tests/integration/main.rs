@@ -0,0 +1,252 @@
+use chrono::FixedOffset;
+use serde_json::{json, Value};
+use vna_test_utils::{article_json, hits_response, mock_elasticsearch, search_response};
+use warp::http::StatusCode;
+
+fn terms_aggr_response(buckets: Value) -> (StatusCode, Value) {
+    let body = search_response(json!({
+        "aggregations": {
+            "aggr": {
+                "doc_count_error_upper_bound": 0,
+                "sum_other_doc_count": 0,
+                "buckets": buckets,
+            }
+        }
+    }));
+    (StatusCode::OK, body)
+}
+
+#[tokio::test]
+async fn search_returns_found_articles() {
+    let elastic = mock_elasticsearch(|_, path, body| {
+        assert_eq!(path, "/articles/_search");
+        assert_eq!(
+            body.unwrap()["query"]["bool"]["should"][0],
+            json!({ "match": { "headline": "trump" } })
+        );
+        (
+            StatusCode::OK,
+            hits_response(&[("42", article_json("Trump said"))]),
+        )
+    });
+
+    let res = warp::test::request()
+        .path("/search?query=trump&field_name=headline")
+        .reply(&vna_server::routes(elastic, FixedOffset::east(0)))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(
+        body,
+        json!([{ "id": "42", "doc": article_json("Trump said") }])
+    );
+}
+
+#[tokio::test]
+async fn search_rejects_hollow_query() {
+    let elastic = mock_elasticsearch(|_, _, _| panic!("Elasticsearch must not be requested"));
+
+    let res = warp::test::request()
+        .path("/search?query=%20%20")
+        .reply(&vna_server::routes(elastic, FixedOffset::east(0)))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn sentiment_stats_aggregate_all_articles_by_default() {
+    let elastic = mock_elasticsearch(|_, path, body| {
+        assert_eq!(path, "/articles/_search");
+        let body = body.unwrap();
+        assert_eq!(body["query"], json!({ "match_all": {} }));
+        assert_eq!(body["aggs"]["aggr"]["terms"]["field"], "sentiment_polarity");
+
+        terms_aggr_response(json!([
+            { "key": "Positive", "doc_count": 10 },
+            { "key": "Negative", "doc_count": 5 },
+        ]))
+    });
+
+    let res = warp::test::request()
+        .path("/stats/sentiment")
+        .reply(&vna_server::routes(elastic, FixedOffset::east(0)))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(
+        body,
+        json!([
+            { "key": "Positive", "doc_count": 10 },
+            { "key": "Negative", "doc_count": 5 },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn category_stats_filter_by_query() {
+    let elastic = mock_elasticsearch(|_, _, body| {
+        let body = body.unwrap();
+        assert_eq!(body["query"], json!({ "match": { "headline": "study" } }));
+        assert_eq!(body["aggs"]["aggr"]["terms"]["field"], "category");
+
+        terms_aggr_response(json!([{ "key": "SCIENCE", "doc_count": 3 }]))
+    });
+
+    let res = warp::test::request()
+        .path("/stats/category?query=study&field_name=headline")
+        .reply(&vna_server::routes(elastic, FixedOffset::east(0)))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body, json!([{ "key": "SCIENCE", "doc_count": 3 }]));
+}
+
+#[tokio::test]
+async fn significant_words_use_cli_defaults() {
+    let elastic = mock_elasticsearch(|_, _, body| {
+        let body = body.unwrap();
+        assert_eq!(
+            body["aggs"]["keywords"]["significant_text"],
+            json!({ "field": "short_description", "size": 15 })
+        );
+
+        let body = search_response(json!({
+            "aggregations": {
+                "keywords": {
+                    "doc_count": 20,
+                    "bg_count": 1000,
+                    "buckets": [
+                        { "key": "research", "doc_count": 7, "score": 1.5, "bg_count": 30 }
+                    ]
+                }
+            }
+        }));
+        (StatusCode::OK, body)
+    });
+
+    let res = warp::test::request()
+        .path("/stats/significant-words?query=study")
+        .reply(&vna_server::routes(elastic, FixedOffset::east(0)))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body["doc_count"], 20);
+    assert_eq!(body["buckets"][0]["key"], "research");
+}
+
+#[tokio::test]
+async fn unknown_routes_are_not_found() {
+    let elastic = mock_elasticsearch(|_, _, _| panic!("Elasticsearch must not be requested"));
+
+    let res = warp::test::request()
+        .path("/bruh")
+        .reply(&vna_server::routes(elastic, FixedOffset::east(0)))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn timeline_stats_apply_filters() {
+    let elastic = mock_elasticsearch(|_, _, body| {
+        let body = body.unwrap();
+        assert_eq!(
+            body["query"]["bool"]["filter"],
+            json!([{ "term": { "sentiment_polarity": "Negative" } }])
+        );
+        assert_eq!(
+            body["aggs"]["aggr"]["date_histogram"]["calendar_interval"],
+            "year"
+        );
+
+        let body = search_response(json!({
+            "aggregations": {
+                "aggr": {
+                    "buckets": [
+                        { "key_as_string": "2017-01-01", "key": 1483228800000i64, "doc_count": 4 },
+                        { "key_as_string": "2018-01-01", "key": 1514764800000i64, "doc_count": 2 },
+                    ]
+                }
+            }
+        }));
+        (StatusCode::OK, body)
+    });
+
+    let res = warp::test::request()
+        .path("/stats/timeline?interval=year&sentiment_polarity=Negative")
+        .reply(&vna_server::routes(elastic, FixedOffset::east(0)))
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(
+        body,
+        json!([
+            { "key": "2017-01-01", "doc_count": 4 },
+            { "key": "2018-01-01", "doc_count": 2 },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn dates_are_filtered_bucketed_and_displayed_in_configured_timezone() {
+    let elastic = mock_elasticsearch(|_, _, body| {
+        let body = body.unwrap();
+        assert_eq!(
+            body["query"]["bool"]["filter"],
+            json!([{ "range": { "date": { "gte": "2018-01-01", "time_zone": "+02:00" } } }])
+        );
+        assert_eq!(
+            body["aggs"]["aggr"]["date_histogram"]["time_zone"],
+            "+02:00"
+        );
+        let body = search_response(json!({ "aggregations": { "aggr": { "buckets": [] } } }));
+        (StatusCode::OK, body)
+    });
+    let routes = vna_server::routes(elastic, FixedOffset::east(2 * 3600));
+
+    let res = warp::test::request()
+        .path("/stats/timeline?date_from=2018-01-01")
+        .reply(&routes)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = warp::test::request().path("/settings").reply(&routes).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body, json!({ "utc_offset_secs": 7200 }));
+}
+
+#[tokio::test]
+async fn dashboard_is_served() {
+    let elastic = mock_elasticsearch(|_, _, _| panic!("Elasticsearch must not be requested"));
+    let routes = vna_server::routes(elastic, FixedOffset::east(0));
+
+    let res = warp::test::request().path("/").reply(&routes).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+
+    let res = warp::test::request()
+        .path("/dashboard.js")
+        .reply(&routes)
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("application/javascript"));
+}
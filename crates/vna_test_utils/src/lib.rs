@@ -0,0 +1,91 @@
+//! Mocked Elasticsearch and the fixtures shared by the integration tests
+
+use elasticsearch::Elasticsearch;
+use serde_json::{json, Value};
+use warp::{
+    http::{Method, StatusCode},
+    path::FullPath,
+    Filter,
+};
+
+/// Spawns an http server that pretends to be Elasticsearch.
+/// It responds to every request with the status and the json returned from `respond`
+/// that receives the request method, path and its json body (if there is any).
+/// Must be called within tokio runtime.
+pub fn mock_elasticsearch<F>(respond: F) -> Elasticsearch
+where
+    F: Fn(&Method, &str, Option<Value>) -> (StatusCode, Value) + Clone + Send + Sync + 'static,
+{
+    let mock = warp::method()
+        .and(warp::path::full())
+        .and(warp::body::bytes())
+        .map(move |method: Method, path: FullPath, body: bytes::Bytes| {
+            let body = serde_json::from_slice(&body).ok();
+            let (status, body) = respond(&method, path.as_str(), body);
+            warp::reply::with_status(warp::reply::json(&body), status)
+        });
+
+    let (addr, server) = warp::serve(mock).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    let url = format!("http://{}", addr).parse().unwrap();
+    vna_es_utils::create_elasticsearch_client(url).unwrap()
+}
+
+/// Source of the article document as it is stored in the index
+pub fn article_json(headline: &str) -> Value {
+    json!({
+        "category": "POLITICS",
+        "headline": headline,
+        "authors": "Ron Dicker",
+        "link": "https://www.huffingtonpost.com/entry/some-article",
+        "short_description": "Some description",
+        "date": "2018-05-26T00:00:00Z",
+        "sentiment_score": 0.5,
+        "sentiment_polarity": "Positive",
+    })
+}
+
+/// Adds the metadata Elasticsearch puts into every search response
+pub fn search_response(mut body: Value) -> Value {
+    body["took"] = json!(1);
+    body["timed_out"] = json!(false);
+    body["_shards"] = json!({ "total": 1, "successful": 1, "skipped": 0, "failed": 0 });
+    body
+}
+
+/// Search response with the given `(id, source)` hits
+pub fn hits_response(hits: &[(&str, Value)]) -> Value {
+    let hits: Vec<_> = hits
+        .iter()
+        .map(|(id, source)| {
+            json!({
+                "_index": "articles_1",
+                "_id": id,
+                "_score": 1.0,
+                "_source": source,
+            })
+        })
+        .collect();
+
+    search_response(json!({
+        "hits": {
+            "total": { "value": hits.len(), "relation": "eq" },
+            "max_score": 1.0,
+            "hits": hits,
+        }
+    }))
+}
+
+/// Error response Elasticsearch replies with when the request fails
+pub fn error_response(status: StatusCode, error_type: &str, reason: &str) -> (StatusCode, Value) {
+    let body = json!({
+        "error": {
+            "root_cause": [{ "type": error_type, "reason": reason }],
+            "type": error_type,
+            "reason": reason,
+        },
+        "status": status.as_u16(),
+    });
+    (status, body)
+}